@@ -29,7 +29,7 @@ function showPassword(id) {
         const xhr = e.target;
         if (xhr.status === 201) {
             window.location.replace(xhr.getResponseHeader('Location'));
-        } else if (xhr.status === 422) {
+        } else if (xhr.status === 403 || xhr.status === 409 || xhr.status === 422 || xhr.status === 429) {
             disableSpinner()
             showError(xhr.response);
         } else if (xhr.status === 500) {
@@ -80,6 +80,13 @@ function showPassword(id) {
         }
     }
 
+    // Parts of the page that live-status.js swapped in bring new forms.
+    document.addEventListener('live-update', function (event) {
+        for (const form of event.target.querySelectorAll('form.xhr')) {
+            initForm(form);
+        }
+    });
+
     if (document.readyState === 'loading') {
         // Loading hasn't finished yet
         document.addEventListener('DOMContentLoaded', initForms);
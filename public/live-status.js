@@ -0,0 +1,76 @@
+"use strict";
+
+// Keeps the parts of the page that are marked with data-live up to date. Whenever the server reports a
+// change, the page is loaded again in the background and those parts are swapped in. If the page as a
+// whole looks different now, e.g. because a backup started, it is reloaded instead.
+(function () {
+    const EVENTS = ["container", "job", "backup", "updates", "lagged"];
+    let timeout;
+
+    function mode(page) {
+        const marker = page.querySelector("[data-live-mode]");
+        return marker ? marker.dataset.liveMode : "";
+    }
+
+    function update(html) {
+        const page = new DOMParser().parseFromString(html, "text/html");
+        if (mode(page) !== mode(document)) {
+            window.location.reload();
+            return;
+        }
+        for (const part of document.querySelectorAll("[data-live]")) {
+            const replacement = page.getElementById(part.id);
+            part.innerHTML = replacement ? replacement.innerHTML : "";
+            part.dispatchEvent(new CustomEvent("live-update", {bubbles: true}));
+        }
+    }
+
+    function load() {
+        fetch(window.location.href, {credentials: "same-origin"})
+            .then(function (response) {
+                // E.g. the session expired, a reload shows the login.
+                if (!response.ok || response.redirected) {
+                    window.location.reload();
+                    return;
+                }
+                return response.text().then(update);
+            })
+            .catch(function () {
+                // The next event tries again.
+            });
+    }
+
+    // Events come in bursts, e.g. while the containers are started one after another.
+    function schedule() {
+        clearTimeout(timeout);
+        timeout = setTimeout(load, 500);
+    }
+
+    function connect() {
+        const source = new EventSource("/api/events");
+        let reconnected = false;
+        source.addEventListener("open", function () {
+            document.body.classList.add("live");
+            // Events that happened while the connection was down are missed.
+            if (reconnected) {
+                schedule();
+            }
+        });
+        source.addEventListener("error", function () {
+            document.body.classList.remove("live");
+            reconnected = true;
+        });
+        for (const event of EVENTS) {
+            source.addEventListener(event, schedule);
+        }
+    }
+
+    if (typeof EventSource === "undefined") {
+        return;
+    }
+    if (document.readyState === 'loading') {
+        document.addEventListener('DOMContentLoaded', connect);
+    } else {
+        connect();
+    }
+})()
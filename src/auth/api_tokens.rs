@@ -0,0 +1,194 @@
+use crate::auth::password_hash::constant_time_eq;
+use crate::data::data_const::get_api_tokens_file;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::{Display, Formatter};
+use time::macros::format_description;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{error, info};
+
+const PREFIX: &str = "aio";
+// Like the last activity of sessions, the last use is only written to the file this often.
+const LAST_USED_PRECISION: Duration = Duration::minutes(1);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Scope {
+    #[serde(rename = "read")]
+    Read,
+    #[serde(rename = "containers:control")]
+    ContainersControl,
+    #[serde(rename = "backup")]
+    Backup,
+    #[serde(rename = "config:write")]
+    ConfigWrite,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::Read,
+        Scope::ContainersControl,
+        Scope::Backup,
+        Scope::ConfigWrite,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::ContainersControl => "containers:control",
+            Scope::Backup => "backup",
+            Scope::ConfigWrite => "config:write",
+        }
+    }
+}
+
+impl Display for Scope {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ApiToken {
+    pub id: String,
+    pub name: String,
+    hash: String,
+    pub scopes: Vec<Scope>,
+    #[serde(with = "time::serde::timestamp")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::timestamp::option", default)]
+    pub last_used_at: Option<OffsetDateTime>,
+}
+
+pub fn format_time(time: OffsetDateTime) -> String {
+    let format = format_description!("[year]-[month]-[day] [hour]:[minute] UTC");
+    time.format(format).unwrap_or_default()
+}
+
+impl ApiToken {
+    pub fn created(&self) -> String {
+        format_time(self.created_at)
+    }
+
+    pub fn last_used(&self) -> String {
+        self.last_used_at
+            .map_or_else(|| String::from("never"), format_time)
+    }
+
+    pub fn scope_list(&self) -> String {
+        self.scopes
+            .iter()
+            .map(Scope::as_str)
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+pub struct ApiTokens {
+    tokens: Vec<ApiToken>,
+}
+
+static INSTANCE: OnceCell<Mutex<ApiTokens>> = OnceCell::const_new();
+
+fn hash(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.as_bytes()))
+}
+
+// Tokens look like `aio_<id>_<secret>`, so the id finds the entry without comparing every hash.
+fn split(token: &str) -> Option<(&str, &str)> {
+    let rest = token.strip_prefix(PREFIX)?.strip_prefix('_')?;
+    rest.split_once('_')
+}
+
+impl ApiTokens {
+    fn load() -> Self {
+        let tokens = std::fs::read(get_api_tokens_file())
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self { tokens }
+    }
+
+    pub async fn instance() -> &'static Mutex<Self> {
+        INSTANCE
+            .get_or_init(|| async { Mutex::new(Self::load()) })
+            .await
+    }
+
+    pub async fn reload() {
+        *Self::instance().await.lock().await = Self::load();
+    }
+
+    fn persist(&self) {
+        let result = serde_json::to_vec(&self.tokens)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                std::fs::write(get_api_tokens_file(), data).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            error!("Could not write the API tokens file: {}", e);
+        }
+    }
+
+    pub fn list(&self) -> &[ApiToken] {
+        &self.tokens
+    }
+
+    pub fn create(&mut self, name: &str, scopes: Vec<Scope>) -> Result<String, String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(String::from("Please enter a name for the API token."));
+        }
+        if self.tokens.iter().any(|token| token.name == name) {
+            return Err(format!("An API token named '{}' already exists.", name));
+        }
+        if scopes.is_empty() {
+            return Err(String::from("Please select at least one scope."));
+        }
+        let mut id = hex::encode(rand::random::<[u8; 4]>());
+        while self.tokens.iter().any(|token| token.id == id) {
+            id = hex::encode(rand::random::<[u8; 4]>());
+        }
+        let secret = hex::encode(rand::random::<[u8; 24]>());
+        self.tokens.push(ApiToken {
+            id: id.clone(),
+            name: String::from(name),
+            hash: hash(&secret),
+            scopes,
+            created_at: OffsetDateTime::now_utc(),
+            last_used_at: None,
+        });
+        self.persist();
+        info!(token = name, "Created API token");
+        Ok(format!("{}_{}_{}", PREFIX, id, secret))
+    }
+
+    pub fn revoke(&mut self, id: &str) -> bool {
+        let Some(index) = self.tokens.iter().position(|token| token.id == id) else {
+            return false;
+        };
+        let token = self.tokens.remove(index);
+        self.persist();
+        info!(token = token.name, "Revoked API token");
+        true
+    }
+
+    pub fn authenticate(&mut self, token: &str) -> Option<ApiToken> {
+        let (id, secret) = split(token)?;
+        let entry = self.tokens.iter_mut().find(|token| token.id == id)?;
+        if !constant_time_eq(entry.hash.as_bytes(), hash(secret).as_bytes()) {
+            return None;
+        }
+        let now = OffsetDateTime::now_utc();
+        let is_stale = entry
+            .last_used_at
+            .is_none_or(|last_used_at| now - last_used_at >= LAST_USED_PRECISION);
+        if !is_stale {
+            return Some(entry.clone());
+        }
+        entry.last_used_at = Some(now);
+        let entry = entry.clone();
+        self.persist();
+        Some(entry)
+    }
+}
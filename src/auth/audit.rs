@@ -0,0 +1,272 @@
+use crate::auth::api_tokens::{format_time, ApiToken};
+use crate::auth::current_user;
+use crate::configuration::StateConfiguration;
+use crate::data::data_const::get_audit_log_file;
+use axum::async_trait;
+use axum::extract::FromRequestParts;
+use axum::http::request::Parts;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::{Mutex, OnceCell};
+use tower_sessions::Session;
+use tracing::{error, info};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuditEvent {
+    Login,
+    SecondFactor,
+    TokenLogin,
+    ProxyLogin,
+    Logout,
+    PasswordChange,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AuditEntry {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub event: AuditEvent,
+    pub actor: String,
+    pub ip: String,
+    pub outcome: Outcome,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+// Empty fields match every entry, the actor is matched case-insensitively as a part of the name.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AuditFilter {
+    pub event: String,
+    pub actor: String,
+    pub outcome: String,
+}
+
+// Who made a request: the API token, the logged in user or nobody yet.
+pub struct Actor(pub String);
+
+impl AuditEvent {
+    pub const ALL: [AuditEvent; 6] = [
+        AuditEvent::Login,
+        AuditEvent::SecondFactor,
+        AuditEvent::TokenLogin,
+        AuditEvent::ProxyLogin,
+        AuditEvent::Logout,
+        AuditEvent::PasswordChange,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AuditEvent::Login => "login",
+            AuditEvent::SecondFactor => "second-factor",
+            AuditEvent::TokenLogin => "token-login",
+            AuditEvent::ProxyLogin => "proxy-login",
+            AuditEvent::Logout => "logout",
+            AuditEvent::PasswordChange => "password-change",
+        }
+    }
+}
+
+impl Display for AuditEvent {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AuditEvent::Login => "passphrase login",
+            AuditEvent::SecondFactor => "second factor",
+            AuditEvent::TokenLogin => "getlogin token",
+            AuditEvent::ProxyLogin => "reverse proxy login",
+            AuditEvent::Logout => "logout",
+            AuditEvent::PasswordChange => "passphrase change",
+        })
+    }
+}
+
+impl Outcome {
+    pub const ALL: [Outcome; 2] = [Outcome::Success, Outcome::Failure];
+
+    pub fn of(success: bool) -> Self {
+        if success {
+            Outcome::Success
+        } else {
+            Outcome::Failure
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Success => "success",
+            Outcome::Failure => "failure",
+        }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl AuditEntry {
+    pub fn new(event: AuditEvent, actor: &str, ip: impl Display, outcome: Outcome) -> Self {
+        AuditEntry {
+            time: OffsetDateTime::now_utc(),
+            event,
+            actor: String::from(actor),
+            ip: ip.to_string(),
+            outcome,
+            detail: None,
+        }
+    }
+
+    pub fn detail(mut self, detail: impl Into<String>) -> Self {
+        self.detail = Some(detail.into());
+        self
+    }
+
+    pub fn formatted_time(&self) -> String {
+        format_time(self.time)
+    }
+}
+
+impl AuditFilter {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        (self.event.is_empty() || self.event == entry.event.as_str())
+            && (self.outcome.is_empty() || self.outcome == entry.outcome.as_str())
+            && (self.actor.is_empty()
+                || entry
+                    .actor
+                    .to_lowercase()
+                    .contains(&self.actor.trim().to_lowercase()))
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Actor {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(token) = parts.extensions.get::<ApiToken>() {
+            return Ok(Actor(format!("API token {}", token.name)));
+        }
+        let user = match Session::from_request_parts(parts, state).await {
+            Ok(session) => current_user(&session).await.ok().flatten(),
+            Err(_) => None,
+        };
+        Ok(Actor(user.map_or_else(
+            || String::from("anonymous"),
+            |user| user.name,
+        )))
+    }
+}
+
+// One JSON entry per line. Entries are only ever appended, except by the retention, which drops
+// the oldest ones.
+pub struct AuditLog {
+    file: PathBuf,
+}
+
+static INSTANCE: OnceCell<Mutex<AuditLog>> = OnceCell::const_new();
+
+impl AuditLog {
+    pub async fn instance() -> &'static Mutex<Self> {
+        INSTANCE
+            .get_or_init(|| async {
+                Mutex::new(Self {
+                    file: get_audit_log_file(),
+                })
+            })
+            .await
+    }
+
+    pub fn record(&mut self, entry: AuditEntry) {
+        let result = serde_json::to_string(&entry)
+            .map_err(|e| e.to_string())
+            .and_then(|line| {
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(&self.file)
+                    .and_then(|mut file| writeln!(file, "{}", line))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            error!("Could not write the audit log: {}", e);
+        }
+    }
+
+    fn read(&self) -> Vec<AuditEntry> {
+        fs::read_to_string(&self.file)
+            .map(|data| {
+                data.lines()
+                    .filter_map(|line| serde_json::from_str(line).ok())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Newest first.
+    pub fn entries(&self, filter: &AuditFilter) -> Vec<AuditEntry> {
+        let mut entries: Vec<AuditEntry> = self
+            .read()
+            .into_iter()
+            .filter(|entry| filter.matches(entry))
+            .collect();
+        entries.reverse();
+        entries
+    }
+
+    pub fn prune(&mut self, retention: Duration) {
+        let entries = self.read();
+        let cutoff = OffsetDateTime::now_utc() - retention;
+        let kept: Vec<&AuditEntry> = entries.iter().filter(|entry| entry.time > cutoff).collect();
+        if kept.len() == entries.len() {
+            return;
+        }
+        let mut data = String::new();
+        for entry in &kept {
+            if let Ok(line) = serde_json::to_string(entry) {
+                data.push_str(&line);
+                data.push('\n');
+            }
+        }
+        // Written next to the log and renamed, so that a crash never leaves half a log behind.
+        let temporary = self.file.with_extension("tmp");
+        let result = fs::write(&temporary, data).and_then(|()| fs::rename(&temporary, &self.file));
+        match result {
+            Ok(()) => info!(
+                "Removed {} audit log entries older than the retention",
+                entries.len() - kept.len()
+            ),
+            Err(e) => error!("Could not prune the audit log: {}", e),
+        }
+    }
+}
+
+pub async fn record(entry: AuditEntry) {
+    AuditLog::instance().await.lock().await.record(entry);
+}
+
+pub async fn prune_periodically(period: std::time::Duration) {
+    let mut interval = tokio::time::interval(period);
+    loop {
+        interval.tick().await;
+        let days = StateConfiguration::instance_ref().await.audit_retention;
+        AuditLog::instance()
+            .await
+            .lock()
+            .await
+            .prune(Duration::days(days.into()));
+    }
+}
@@ -1,45 +1,228 @@
-// use crate::auth::auth_manager::AuthManager;
-//
-// // namespace AIO\Middleware;
-//
-//
-// pub struct AuthMiddleware {
-// auth_manager: AuthManager,
-// }
-//
-// impl AuthMiddleware {
-// // readonly class AuthMiddleware {
-// // }
-//
-// pub fn new(&self) -> Self {
-// panic!("Not implemented"); // TODO
-// //     public function __construct(
-// //         private AuthManager $authManager
-// //     ) {
-// //     }
-// }
-//
-// pub fn __invoke(&self, request: ServerRequestInterface, handler: RequestHandlerInterface) -> ResponseInterface {
-// panic!("Not implemented"); // TODO
-// //     public function __invoke(ServerRequestInterface $request, RequestHandlerInterface $handler): ResponseInterface
-// //     {
-// //         $publicRoutes = [
-// //             '/api/auth/login',
-// //             '/api/auth/getlogin',
-// //             '/login',
-// //             '/setup',
-// //             '/',
-// //         ];
-// //         if(!in_array($request->getUri()->getPath(), $publicRoutes)) {
-// //             if(!$this->authManager->IsAuthenticated()) {
-// //                 $status = 302;
-// //                 $headers = ['Location' => '/'];
-// //                 $response = new Response($status, $headers);
-// //                 return $response;
-// //             }
-// //         }
-// //         $response = $handler->handle($request);
-// //         return $response;
-// //     }
-// }
-// } hdshdghgdhgdh hgshghgfhfdht
+use crate::auth::api_tokens::{ApiTokens, Scope};
+use crate::auth::audit::{record, AuditEntry, AuditEvent, Outcome};
+use crate::auth::client_ip::{is_trusted, peer_ip, ClientIp};
+use crate::auth::sessions::Sessions;
+use crate::auth::users::Role;
+use crate::auth::{current_user, set_auth_from_proxy, track_session, CurrentUser, ProxyLogin};
+use crate::configuration::StateConfiguration;
+use crate::routes::HttpResponse;
+use axum::extract::Request;
+use axum::http::{header, Extensions, HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tower_sessions::Session;
+use tracing::{info, info_span, warn, Instrument};
+
+const PUBLIC_ROUTES: &[&str] = &[
+    "/",
+    "/login",
+    "/setup",
+    "/api/auth/login",
+    "/api/auth/getlogin",
+    // Only accepted after the passphrase step, see is_second_factor_pending().
+    "/login/two-factor",
+    "/api/auth/two-factor",
+    // Only accepted while no configuration exists, so there is nobody to log in yet.
+    "/api/configuration/import",
+    "/api/v1/openapi.json",
+];
+
+const STATIC_ASSETS: &[&str] = &[".js", ".css", ".txt", ".png", ".webp", ".svg"];
+
+pub fn is_public(path: &str) -> bool {
+    PUBLIC_ROUTES.contains(&path)
+        || (!path.starts_with("/api/") && STATIC_ASSETS.iter().any(|ext| path.ends_with(ext)))
+}
+
+// Routes without a scope, like pages, logins and token management, need a session.
+fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    match path {
+        _ if !path.starts_with("/api/") => None,
+        _ if path.starts_with("/api/auth/") || path.starts_with("/api/tokens") => None,
+        // Reading what only admins may see, like the audit log, needs a session as well.
+        _ if *method == Method::GET => {
+            (required_role(method, path) == Role::Viewer).then_some(Scope::Read)
+        }
+        "/api/docker/start"
+        | "/api/docker/stop"
+        | "/api/docker/watchtower"
+        | "/api/v1/containers/start"
+        | "/api/v1/containers/stop"
+        | "/api/jobs/cancel"
+        | "/api/v1/jobs/cancel" => Some(Scope::ContainersControl),
+        "/api/docker/backup"
+        | "/api/docker/backup-check"
+        | "/api/docker/backup-check-repair"
+        | "/api/docker/backup-test"
+        | "/api/docker/restore"
+        | "/api/v1/backups"
+        | "/api/v1/backups/check"
+        | "/api/v1/backups/check-repair"
+        | "/api/v1/backups/test" => Some(Scope::Backup),
+        // The export contains every secret, so it needs a session just like the passphrase change.
+        "/api/configuration" | "/api/v1/configuration" => Some(Scope::ConfigWrite),
+        _ => None,
+    }
+}
+
+// Reading is open to every role, the containers and backups need an operator and everything
+// else, like the configuration, restores, users and tokens, an admin. Everybody manages their own
+// password and second factor.
+fn required_role(method: &Method, path: &str) -> Role {
+    match path {
+        "/audit" | "/tokens" | "/users" | "/sessions" | "/api/auth/login-token" => Role::Admin,
+        _ if path.starts_with("/api/audit")
+            || path.starts_with("/api/tokens")
+            || path.starts_with("/api/users")
+            || path.starts_with("/api/sessions") =>
+        {
+            Role::Admin
+        }
+        "/api/auth/logout" | "/api/auth/password" => Role::Viewer,
+        _ if path.starts_with("/api/two-factor") => Role::Viewer,
+        _ if *method == Method::GET => Role::Viewer,
+        "/api/docker/start"
+        | "/api/docker/stop"
+        | "/api/docker/watchtower"
+        | "/api/docker/backup"
+        | "/api/docker/backup-check"
+        | "/api/docker/backup-check-repair"
+        | "/api/docker/backup-test"
+        | "/api/v1/containers/start"
+        | "/api/v1/containers/stop"
+        | "/api/v1/backups"
+        | "/api/v1/backups/check"
+        | "/api/v1/backups/check-repair"
+        | "/api/v1/backups/test"
+        | "/api/jobs/cancel"
+        | "/api/v1/jobs/cancel" => Role::Operator,
+        _ => Role::Admin,
+    }
+}
+
+fn bearer_token(request: &Request) -> Option<&str> {
+    request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+        .map(str::trim)
+}
+
+// The client address and browser shown in the session list.
+async fn client_details(headers: &HeaderMap, extensions: &Extensions) -> (String, String) {
+    let ip = ClientIp::resolve(headers, extensions).await;
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    (ip.to_string(), String::from(user_agent))
+}
+
+async fn token_auth(token: &str, mut request: Request, next: Next) -> Response {
+    let Some(api_token) = ApiTokens::instance().await.lock().await.authenticate(token) else {
+        warn!(path = request.uri().path(), "Rejected an unknown API token");
+        return HttpResponse::UnauthorizedRedirect("/login").into_response();
+    };
+    let (ip, user_agent) = client_details(request.headers(), request.extensions()).await;
+    Sessions::instance().await.lock().await.touch_token(
+        &api_token.id,
+        &api_token.name,
+        &ip,
+        &user_agent,
+    );
+    let path = request.uri().path();
+    let allowed = required_scope(request.method(), path)
+        .is_some_and(|scope| api_token.scopes.contains(&scope));
+    if !allowed {
+        warn!(
+            token = api_token.name,
+            path, "Rejected an API token without the required scope"
+        );
+        return HttpResponse::Forbidden("The API token is not allowed to use this route.")
+            .into_response();
+    }
+    if *request.method() != Method::GET {
+        info!(token = api_token.name, path, "API token request");
+    }
+    request.extensions_mut().insert(api_token);
+    next.run(request).await
+}
+
+// Everything logged while handling the request is attributed to the user.
+async fn user_auth(user: CurrentUser, request: Request, next: Next) -> Response {
+    let path = request.uri().path();
+    let required = required_role(request.method(), path);
+    if user.role < required {
+        warn!(
+            user = user.name,
+            role = %user.role,
+            path, "Rejected a request that needs the {} role", required
+        );
+        return HttpResponse::Forbidden("Your role is not allowed to do this.").into_response();
+    }
+    if *request.method() != Method::GET {
+        info!(user = user.name, path, "User request");
+    }
+    let span = info_span!("user", name = user.name);
+    next.run(request).instrument(span).await
+}
+
+// The header is only believed from a trusted proxy, which has to overwrite it on every request so
+// that clients cannot send their own.
+async fn proxy_user(headers: &HeaderMap, extensions: &Extensions) -> Option<String> {
+    let config = StateConfiguration::instance_ref().await;
+    let header = config.auth_proxy_header.as_deref()?;
+    let user = headers
+        .get(header)
+        .and_then(|value| value.to_str().ok())
+        .map(str::trim)
+        .filter(|user| !user.is_empty())?;
+    let peer = peer_ip(extensions);
+    if !is_trusted(&peer, &config.trusted_proxies) {
+        warn!(
+            %peer,
+            header, "Ignored the proxy authentication header from an untrusted peer"
+        );
+        return None;
+    }
+    Some(String::from(user))
+}
+
+pub async fn auth_middleware(session: Session, request: Request, next: Next) -> Response {
+    if let Some(user) = proxy_user(request.headers(), request.extensions()).await {
+        let outcome = match set_auth_from_proxy(&session, &user).await {
+            Ok(ProxyLogin::Unchanged) => None,
+            Ok(ProxyLogin::Authenticated) => Some(Outcome::Success),
+            Ok(ProxyLogin::Rejected) => Some(Outcome::Failure),
+            Err(e) => return HttpResponse::Error(e.to_string()).into_response(),
+        };
+        if let Some(outcome) = outcome {
+            let ip = ClientIp::resolve(request.headers(), request.extensions()).await;
+            record(AuditEntry::new(AuditEvent::ProxyLogin, &user, ip, outcome)).await;
+        }
+    }
+    let path = request.uri().path();
+    if is_public(path) {
+        return next.run(request).await;
+    }
+    if let Some(token) = bearer_token(&request) {
+        let token = token.to_owned();
+        return token_auth(&token, request, next).await;
+    }
+    let (ip, user_agent) = client_details(request.headers(), request.extensions()).await;
+    let user = match current_user(&session).await {
+        Ok(Some(user)) => track_session(&session, &user, &ip, &user_agent)
+            .await
+            .map(|is_active| is_active.then_some(user)),
+        result => result,
+    };
+    match user {
+        Ok(Some(user)) => user_auth(user, request, next).await,
+        Ok(None) if path.starts_with("/api/") => {
+            HttpResponse::UnauthorizedRedirect("/login").into_response()
+        }
+        Ok(None) => HttpResponse::TemporaryRedirect("/").into_response(),
+        Err(e) => HttpResponse::Error(e.to_string()).into_response(),
+    }
+}
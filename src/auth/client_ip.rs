@@ -0,0 +1,79 @@
+use crate::configuration::StateConfiguration;
+use axum::async_trait;
+use axum::extract::{ConnectInfo, FromRequestParts};
+use axum::http::request::Parts;
+use axum::http::{Extensions, HeaderMap};
+use ipnet::IpNet;
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+pub fn is_trusted(ip: &IpAddr, trusted_proxies: &[IpNet]) -> bool {
+    trusted_proxies.iter().any(|network| network.contains(ip))
+}
+
+// X-Forwarded-For is only believed when the peer is a trusted proxy. The header is read from the
+// right, so the first untrusted hop is the client and entries it may have forged are ignored.
+pub fn resolve_client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: &[IpNet]) -> IpAddr {
+    if !is_trusted(&peer, trusted_proxies) {
+        return peer;
+    }
+    let mut client = peer;
+    for hop in headers
+        .get_all(FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .collect::<Vec<_>>()
+        .into_iter()
+        .rev()
+    {
+        match hop.trim().parse::<IpAddr>() {
+            Ok(ip) => {
+                client = ip;
+                if !is_trusted(&ip, trusted_proxies) {
+                    break;
+                }
+            }
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+pub fn peer_ip(extensions: &Extensions) -> IpAddr {
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |info| info.0.ip())
+}
+
+impl ClientIp {
+    pub async fn resolve(headers: &HeaderMap, extensions: &Extensions) -> Self {
+        let peer = peer_ip(extensions);
+        let trusted_proxies = StateConfiguration::instance_ref()
+            .await
+            .trusted_proxies
+            .clone();
+        ClientIp(resolve_client_ip(peer, headers, &trusted_proxies))
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(ClientIp::resolve(&parts.headers, &parts.extensions).await)
+    }
+}
+
+impl Display for ClientIp {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
@@ -1,50 +1,384 @@
-use crate::auth::password_generator::generate_password;
+use crate::auth::login_tokens::LoginTokens;
+use crate::auth::password_generator::{generate_password, PasswordOptions};
+use crate::auth::password_hash::hash_password;
+use crate::auth::sessions::{AuthMethod, Lifetimes, Sessions};
+use crate::auth::two_factor::TwoFactor;
+use crate::auth::users::{Role, Users, SHARED_ACCOUNT};
 use crate::configuration::{MutConfiguration, StateConfiguration};
 use crate::container::controller::DockerController;
-use crate::data::data_const::get_config_file;
+use crate::data::data_const::{get_config_file, get_session_date_file, get_session_directory};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
 use tower_sessions::Session;
+use tracing::{error, info, warn};
 
 const SESSION_KEY: &str = "aio_authenticated";
 const SESSION_DATE_KEY: &str = "date_time";
+const SESSION_GENERATION_KEY: &str = "generation";
+// Set after a correct passphrase while the second factor is still missing, see PendingLogin.
+const SESSION_SECOND_FACTOR_KEY: &str = "second_factor_pending";
+const SECOND_FACTOR_TIMEOUT: i64 = 5 * 60;
+// The identity reported by a trusted reverse proxy, see set_auth_from_proxy().
+const SESSION_EXTERNAL_USER_KEY: &str = "external_user";
+// An account from the user store. Sessions without one belong to the shared AIO passphrase.
+const SESSION_USER_KEY: &str = "user";
+// How the session logged in and its entry in the session list.
+const SESSION_METHOD_KEY: &str = "auth_method";
+const SESSION_ID_KEY: &str = "session_id";
 
 type Result<T> = std::result::Result<T, tower_sessions::session::Error>;
 
-pub async fn set_auth_from_password(session: &Session, password: &str) -> Result<bool> {
-    if !DockerController::is_login_allowed().await.unwrap() {
-        Ok(false)
+#[derive(Serialize, Deserialize)]
+struct SessionUser {
+    name: String,
+    generation: u64,
+}
+
+// The account only becomes the session user once the second factor is correct as well.
+#[derive(Serialize, Deserialize)]
+struct PendingLogin {
+    user: Option<SessionUser>,
+    since: i64,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum ProxyLogin {
+    Unchanged,
+    Authenticated,
+    Rejected,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PasswordChange {
+    Changed,
+    WrongPassword,
+    Rejected(String),
+}
+
+pub struct CurrentUser {
+    pub name: String,
+    pub role: Role,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum PasswordLogin {
+    Authenticated,
+    SecondFactorRequired,
+    Invalid,
+}
+
+// An empty name logs in with the shared AIO passphrase.
+pub async fn set_auth_from_password(
+    session: &Session,
+    name: &str,
+    password: &str,
+) -> Result<PasswordLogin> {
+    let user = if !DockerController::is_login_allowed().await.unwrap() {
+        None
+    } else if name.trim().is_empty() {
+        StateConfiguration::test_password(password)
+            .await
+            .then_some(None)
+    } else {
+        let users = Users::instance().await.lock().await;
+        users.authenticate(name, password).map(|user| {
+            Some(SessionUser {
+                name: user.name.clone(),
+                generation: user.generation,
+            })
+        })
+    };
+    // Whoever was logged in on this session before is logged out, also while the second factor
+    // is still missing.
+    set_auth_state(session, None).await?;
+    let Some(user) = user else {
+        return Ok(PasswordLogin::Invalid);
+    };
+    session.cycle_id().await?;
+    let account = account_name(user.as_ref());
+    if TwoFactor::instance()
+        .await
+        .lock()
+        .await
+        .is_enrolled(account)
+    {
+        let since = OffsetDateTime::now_utc().unix_timestamp();
+        let pending = PendingLogin { user, since };
+        session.insert(SESSION_SECOND_FACTOR_KEY, pending).await?;
+        Ok(PasswordLogin::SecondFactorRequired)
     } else {
-        let value = StateConfiguration::test_password(password).await;
-        session.insert(SESSION_KEY, value).await.and(Ok(value))
+        set_session_user(session, user).await?;
+        set_auth_state(session, Some(AuthMethod::Password)).await?;
+        Ok(PasswordLogin::Authenticated)
+    }
+}
+
+fn account_name(user: Option<&SessionUser>) -> &str {
+    user.map_or(SHARED_ACCOUNT, |user| &user.name)
+}
+
+async fn pending_login(session: &Session) -> Result<Option<PendingLogin>> {
+    let pending: Option<PendingLogin> = session.get(SESSION_SECOND_FACTOR_KEY).await?;
+    let now = OffsetDateTime::now_utc().unix_timestamp();
+    Ok(pending.filter(|pending| now - pending.since < SECOND_FACTOR_TIMEOUT))
+}
+
+pub async fn is_second_factor_pending(session: &Session) -> Result<bool> {
+    pending_login(session)
+        .await
+        .map(|pending| pending.is_some())
+}
+
+pub async fn set_auth_from_second_factor(session: &Session, code: &str) -> Result<bool> {
+    let Some(pending) = pending_login(session).await? else {
+        return Ok(false);
+    };
+    let account = account_name(pending.user.as_ref());
+    if !TwoFactor::instance()
+        .await
+        .lock()
+        .await
+        .verify(account, code)
+    {
+        return Ok(false);
     }
+    session
+        .remove::<PendingLogin>(SESSION_SECOND_FACTOR_KEY)
+        .await?;
+    set_session_user(session, pending.user).await?;
+    set_auth_state(session, Some(AuthMethod::Password))
+        .await
+        .and(Ok(true))
 }
 
 pub async fn set_auth_from_token(session: &Session, token: &str) -> Result<bool> {
-    let value = StateConfiguration::test_token(token).await;
-    session.insert(SESSION_KEY, value).await.and(Ok(value))
+    let value = LoginTokens::instance().await.lock().await.consume(token);
+    if value {
+        set_session_user(session, None).await?;
+    }
+    let method = value.then_some(AuthMethod::LoginToken);
+    set_auth_state(session, method).await.and(Ok(value))
+}
+
+// The proxy has already authenticated the user, including any second factor it requires, but the
+// user still needs a role here. A different user on the same session starts over.
+pub async fn set_auth_from_proxy(session: &Session, user: &str) -> Result<ProxyLogin> {
+    let current: Option<String> = session.get(SESSION_EXTERNAL_USER_KEY).await?;
+    if current.as_deref() == Some(user) && is_logged_in_session(session).await? {
+        return Ok(ProxyLogin::Unchanged);
+    }
+    if current.is_some() {
+        session.flush().await?;
+    }
+    if Users::instance()
+        .await
+        .lock()
+        .await
+        .external_role(user)
+        .is_none()
+    {
+        set_auth_state(session, None).await?;
+        warn!(
+            user,
+            "The trusted reverse proxy logged in a user without an account"
+        );
+        return Ok(ProxyLogin::Rejected);
+    }
+    set_session_user(session, None).await?;
+    set_auth_state(session, Some(AuthMethod::Proxy)).await?;
+    session.insert(SESSION_EXTERNAL_USER_KEY, user).await?;
+    info!(user, "Logged in by the trusted reverse proxy");
+    Ok(ProxyLogin::Authenticated)
+}
+
+async fn set_session_user(session: &Session, user: Option<SessionUser>) -> Result<()> {
+    session.remove::<String>(SESSION_EXTERNAL_USER_KEY).await?;
+    match user {
+        Some(user) => session.insert(SESSION_USER_KEY, user).await,
+        None => session
+            .remove::<SessionUser>(SESSION_USER_KEY)
+            .await
+            .and(Ok(())),
+    }
 }
 
 pub async fn clear_auth(session: &Session) -> Result<()> {
-    session.insert(SESSION_KEY, false).await
-}
-
-async fn set_auth_state(session: &Session, is_logged_in: bool) -> Result<()> {
-    session.insert(SESSION_KEY, is_logged_in).await
-    //     public function SetAuthState(bool $isLoggedIn) : void {
-    //         if (!$this->IsAuthenticated() && $isLoggedIn === true) {
-    //             $date = new DateTime();
-    //             $dateTime = $date->getTimestamp();
-    //             $_SESSION['date_time'] = $dateTime;
-    //             $df = disk_free_space(DataConst::GetSessionDirectory());
-    //             if ($df !== false && (int)$df < 10240) {
-    //                 error_log(DataConst::GetSessionDirectory() . " has only less than 10KB free space. The login might not succeed because of that!");
-    //             }
-    //             file_put_contents(DataConst::GetSessionDateFile(), (string)$dateTime);
-    //         }
-    //         $_SESSION[self::SESSION_KEY] = $isLoggedIn;
-    //     }
+    set_auth_state(session, None).await
+}
+
+// A new login gets a new session id against session fixation and a new entry in the session list
+// with its next request, see track_session().
+async fn set_auth_state(session: &Session, method: Option<AuthMethod>) -> Result<()> {
+    let Some(method) = method else {
+        session
+            .remove::<PendingLogin>(SESSION_SECOND_FACTOR_KEY)
+            .await?;
+        if let Some(id) = session.remove::<String>(SESSION_ID_KEY).await? {
+            Sessions::instance().await.lock().await.end(&id);
+        }
+        return session.insert(SESSION_KEY, false).await;
+    };
+    if !is_logged_in_session(session).await? {
+        session.cycle_id().await?;
+        let date_time = OffsetDateTime::now_utc().unix_timestamp();
+        session.insert(SESSION_DATE_KEY, date_time).await?;
+        let directory = get_session_directory();
+        if fs2::available_space(&directory).is_ok_and(|free| free < 10240) {
+            warn!(
+                "{} has only less than 10KB free space. The login might not succeed because of that!",
+                directory.display()
+            );
+        }
+        if let Err(e) = std::fs::write(get_session_date_file(), date_time.to_string()) {
+            error!("Could not write the session date file: {}", e);
+        }
+        let generation = StateConfiguration::instance_ref().await.session_generation;
+        set_session_generation(session, generation).await?;
+        session.insert(SESSION_METHOD_KEY, method).await?;
+        session.remove::<String>(SESSION_ID_KEY).await?;
+    }
+    session.insert(SESSION_KEY, true).await
+}
+
+// Sessions from an older generation were logged out, e.g. by a passphrase change elsewhere.
+async fn is_logged_in_session(session: &Session) -> Result<bool> {
+    if !session.get(SESSION_KEY).await?.unwrap_or(false) {
+        return Ok(false);
+    }
+    let generation: u64 = session.get(SESSION_GENERATION_KEY).await?.unwrap_or(0);
+    Ok(generation == StateConfiguration::instance_ref().await.session_generation)
 }
+
+// Removed users and users with a new password lose their sessions, and users from the reverse proxy
+// need an account once there are any.
+pub async fn current_user(session: &Session) -> Result<Option<CurrentUser>> {
+    if !is_logged_in_session(session).await? {
+        return Ok(None);
+    }
+    let user: Option<SessionUser> = session.get(SESSION_USER_KEY).await?;
+    let external: Option<String> = session.get(SESSION_EXTERNAL_USER_KEY).await?;
+    let users = Users::instance().await.lock().await;
+    let current = match (user, external) {
+        (Some(user), _) => users
+            .role(&user.name, user.generation)
+            .map(|role| CurrentUser {
+                name: user.name,
+                role,
+            }),
+        (None, Some(name)) => users
+            .external_role(&name)
+            .map(|role| CurrentUser { name, role }),
+        (None, None) => Some(CurrentUser {
+            name: String::from(SHARED_ACCOUNT),
+            role: Role::Admin,
+        }),
+    };
+    Ok(current)
+}
+
+// The account a login is for, also while its second factor is still missing.
+pub async fn login_name(session: &Session) -> Result<String> {
+    if let Some(pending) = pending_login(session).await? {
+        return Ok(String::from(account_name(pending.user.as_ref())));
+    }
+    let user: Option<SessionUser> = session.get(SESSION_USER_KEY).await?;
+    let external: Option<String> = session.get(SESSION_EXTERNAL_USER_KEY).await?;
+    Ok(user
+        .map(|user| user.name)
+        .or(external)
+        .unwrap_or_else(|| String::from(SHARED_ACCOUNT)))
+}
+
+// Users change their own password with the current one. The shared AIO passphrase is changed in
+// the configuration and users from the reverse proxy without an account have no password here.
+pub async fn change_password(
+    session: &Session,
+    current: &str,
+    new: &str,
+) -> Result<PasswordChange> {
+    let Some(user) = current_user(session).await? else {
+        return Ok(PasswordChange::Rejected(String::from(
+            "You are not logged in.",
+        )));
+    };
+    if user.name == SHARED_ACCOUNT {
+        return Ok(PasswordChange::Rejected(String::from(
+            "The AIO passphrase is changed in the configuration.",
+        )));
+    }
+    let mut users = Users::instance().await.lock().await;
+    if !users.list().iter().any(|account| account.name == user.name) {
+        return Ok(PasswordChange::Rejected(String::from(
+            "Your account has no password here. It is managed by the reverse proxy.",
+        )));
+    }
+    if users.authenticate(&user.name, current).is_none() {
+        return Ok(PasswordChange::WrongPassword);
+    }
+    if let Err(e) = users.set_password(&user.name, new) {
+        return Ok(PasswordChange::Rejected(e));
+    }
+    // The new generation logs out all other sessions of the user, this one continues with it.
+    let generation = users
+        .list()
+        .iter()
+        .find(|account| account.name == user.name)
+        .map(|account| account.generation);
+    drop(users);
+    let session_user: Option<SessionUser> = session.get(SESSION_USER_KEY).await?;
+    if let (Some(session_user), Some(generation)) = (session_user, generation) {
+        let session_user = SessionUser {
+            generation,
+            ..session_user
+        };
+        session.insert(SESSION_USER_KEY, session_user).await?;
+    }
+    let id: Option<String> = session.get(SESSION_ID_KEY).await?;
+    let mut sessions = Sessions::instance().await.lock().await;
+    sessions.revoke_others(id.as_deref(), Some(&user.name));
+    Ok(PasswordChange::Changed)
+}
+
+// Returns false if the session was revoked or has outlived its lifetime, which also logs it out.
+pub async fn track_session(
+    session: &Session,
+    user: &CurrentUser,
+    ip: &str,
+    user_agent: &str,
+) -> Result<bool> {
+    let id: Option<String> = session.get(SESSION_ID_KEY).await?;
+    let Some(id) = id else {
+        let method = session
+            .get(SESSION_METHOD_KEY)
+            .await?
+            .unwrap_or(AuthMethod::Password);
+        let mut sessions = Sessions::instance().await.lock().await;
+        let id = sessions.start(&user.name, method, ip, user_agent);
+        return session.insert(SESSION_ID_KEY, id).await.and(Ok(true));
+    };
+    let lifetimes = Lifetimes::configured().await;
+    let is_active = Sessions::instance()
+        .await
+        .lock()
+        .await
+        .touch(&id, &lifetimes, ip, user_agent);
+    if !is_active {
+        info!(user = user.name, "Ended a revoked or expired session");
+        set_auth_state(session, None).await?;
+    }
+    Ok(is_active)
+}
+
+pub async fn session_id(session: &Session) -> Result<Option<String>> {
+    session.get(SESSION_ID_KEY).await
+}
+
 pub async fn is_authenticated(session: &Session) -> Result<bool> {
-    session.get(SESSION_KEY).await.map(|v| v.unwrap_or(false))
+    current_user(session).await.map(|user| user.is_some())
+}
+
+pub async fn set_session_generation(session: &Session, generation: u64) -> Result<()> {
+    session.insert(SESSION_GENERATION_KEY, generation).await
 }
 
 pub fn can_be_installed() -> bool {
@@ -53,10 +387,18 @@ pub fn can_be_installed() -> bool {
 
 pub async fn setup_password(mut config: MutConfiguration) -> Option<String> {
     if can_be_installed() {
-        let password = generate_password(8);
-        config.password = password.clone();
-        config.commit();
-        Some(password)
+        let password = generate_password(&PasswordOptions::from(&*config));
+        match hash_password(&password) {
+            Ok(hash) => {
+                config.password = hash;
+                config.commit();
+                Some(password)
+            }
+            Err(e) => {
+                error!("Could not hash the AIO passphrase: {}", e);
+                None
+            }
+        }
     } else {
         None
     }
@@ -0,0 +1,264 @@
+use crate::auth::api_tokens::ApiToken;
+use crate::auth::client_ip::{is_trusted, peer_ip};
+use crate::auth::password_hash::constant_time_eq;
+use crate::configuration::StateConfiguration;
+use crate::routes::HttpResponse;
+use axum::body::{to_bytes, Body};
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use tower_sessions::Session;
+use tracing::warn;
+
+const SESSION_KEY: &str = "csrf";
+const TOKEN_BYTES: usize = 32;
+const FORM_LIMIT: usize = 1024 * 1024;
+const FORWARDED_HOST: &str = "x-forwarded-host";
+
+// Only accepted while no configuration exists, so there is no session to bind a token to yet.
+const EXEMPT_ROUTES: &[&str] = &["/api/configuration/import"];
+
+type Result<T> = std::result::Result<T, tower_sessions::session::Error>;
+
+pub struct CsrfKeys {
+    pub name: &'static str,
+    pub value: &'static str,
+}
+
+pub const KEYS: CsrfKeys = CsrfKeys {
+    name: "csrf_name",
+    value: "csrf_value",
+};
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Token {
+    name: String,
+    value: String,
+}
+
+pub struct Csrf {
+    pub keys: CsrfKeys,
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Deserialize)]
+struct CsrfFields {
+    csrf_name: Option<String>,
+    csrf_value: Option<String>,
+}
+
+fn random_hex() -> String {
+    let mut bytes = [0u8; TOKEN_BYTES];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+pub async fn csrf_token(session: &Session) -> Result<Csrf> {
+    let token = match session.get::<Token>(SESSION_KEY).await? {
+        Some(token) => token,
+        None => {
+            let token = Token {
+                name: random_hex(),
+                value: random_hex(),
+            };
+            session.insert(SESSION_KEY, token.clone()).await?;
+            token
+        }
+    };
+    Ok(Csrf {
+        keys: KEYS,
+        name: token.name,
+        value: token.value,
+    })
+}
+
+fn authority(url: &str) -> Option<&str> {
+    let (_, rest) = url.split_once("://")?;
+    rest.split(['/', '?', '#']).next()
+}
+
+// The first proxy records the host the browser asked for, later ones append their own.
+fn forwarded_host(headers: &HeaderMap) -> Option<&str> {
+    if let Some(host) = headers
+        .get(header::FORWARDED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .and_then(|hop| {
+            hop.split(';')
+                .filter_map(|pair| pair.trim().split_once('='))
+                .find(|(key, _)| key.eq_ignore_ascii_case("host"))
+        })
+    {
+        return Some(host.1.trim_matches('"'));
+    }
+    headers
+        .get(FORWARDED_HOST)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split(',').next())
+        .map(str::trim)
+}
+
+// Behind a reverse proxy the Host header names the proxy's upstream, so the host the browser used
+// is taken from the forwarding headers, but only if they come from a trusted proxy.
+fn request_host(headers: &HeaderMap, is_proxied: bool) -> Option<&str> {
+    is_proxied
+        .then(|| forwarded_host(headers))
+        .flatten()
+        .or_else(|| headers.get(header::HOST).and_then(|v| v.to_str().ok()))
+}
+
+// Browsers send Origin on cross-site posts and most same-origin ones. Requests without Origin and
+// Referer, for example from curl, are left to the token check.
+fn is_same_origin(headers: &HeaderMap, is_proxied: bool) -> bool {
+    let Some(host) = request_host(headers, is_proxied) else {
+        return true;
+    };
+    let source = headers
+        .get(header::ORIGIN)
+        .or_else(|| headers.get(header::REFERER))
+        .and_then(|v| v.to_str().ok());
+    match source {
+        Some(source) => authority(source).is_some_and(|a| a.eq_ignore_ascii_case(host)),
+        None => true,
+    }
+}
+
+fn is_form(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/x-www-form-urlencoded"))
+}
+
+pub async fn csrf_middleware(session: Session, request: Request, next: Next) -> Response {
+    if matches!(
+        *request.method(),
+        Method::GET | Method::HEAD | Method::OPTIONS
+    ) || EXEMPT_ROUTES.contains(&request.uri().path())
+        // Bearer tokens are never sent by the browser on its own.
+        || request.extensions().get::<ApiToken>().is_some()
+    {
+        return next.run(request).await;
+    }
+
+    let is_proxied = is_trusted(
+        &peer_ip(request.extensions()),
+        &StateConfiguration::instance_ref().await.trusted_proxies,
+    );
+    if !is_same_origin(request.headers(), is_proxied) {
+        warn!(
+            path = request.uri().path(),
+            "Rejected cross-origin request to a state-changing route"
+        );
+        return HttpResponse::Forbidden("Cross-origin requests are not allowed.").into_response();
+    }
+
+    let expected = match session.get::<Token>(SESSION_KEY).await {
+        Ok(token) => token,
+        Err(e) => return HttpResponse::Error(e.to_string()).into_response(),
+    };
+
+    let (parts, body) = request.into_parts();
+    let bytes = match to_bytes(body, FORM_LIMIT).await {
+        Ok(bytes) => bytes,
+        Err(e) => return HttpResponse::UnprocessableEntity(e.to_string()).into_response(),
+    };
+    let fields = is_form(&parts.headers)
+        .then(|| serde_urlencoded::from_bytes::<CsrfFields>(&bytes).ok())
+        .flatten();
+
+    let is_valid = match (expected, fields) {
+        (
+            Some(expected),
+            Some(CsrfFields {
+                csrf_name: Some(name),
+                csrf_value: Some(value),
+            }),
+        ) => {
+            constant_time_eq(expected.name.as_bytes(), name.as_bytes())
+                && constant_time_eq(expected.value.as_bytes(), value.as_bytes())
+        }
+        _ => false,
+    };
+    if !is_valid {
+        warn!(
+            path = parts.uri.path(),
+            "Rejected request with a missing or invalid CSRF token"
+        );
+        return HttpResponse::Forbidden(
+            "The form has expired. Please reload the page and try again.",
+        )
+        .into_response();
+    }
+
+    next.run(Request::from_parts(parts, Body::from(bytes)))
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.append(
+                header::HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                value.parse().unwrap(),
+            );
+        }
+        headers
+    }
+
+    #[test]
+    fn origins_are_compared_with_the_host() {
+        let direct = headers(&[
+            ("host", "aio.example:8080"),
+            ("origin", "https://aio.example:8080"),
+        ]);
+        assert!(is_same_origin(&direct, false));
+        let foreign = headers(&[
+            ("host", "aio.example:8080"),
+            ("origin", "https://evil.example"),
+        ]);
+        assert!(!is_same_origin(&foreign, false));
+        let referer = headers(&[
+            ("host", "aio.example:8080"),
+            ("referer", "https://evil.example/aio.example:8080"),
+        ]);
+        assert!(!is_same_origin(&referer, false));
+        assert!(is_same_origin(&headers(&[("host", "aio.example")]), false));
+    }
+
+    #[test]
+    fn origins_are_compared_with_the_forwarded_host_behind_a_trusted_proxy() {
+        let proxied = headers(&[
+            ("host", "nextcloud-aio-mastercontainer:8080"),
+            ("x-forwarded-host", "aio.example, proxy.internal"),
+            ("origin", "https://aio.example"),
+        ]);
+        assert!(is_same_origin(&proxied, true));
+        assert!(!is_same_origin(&proxied, false));
+
+        let forwarded = headers(&[
+            ("host", "nextcloud-aio-mastercontainer:8080"),
+            (
+                "forwarded",
+                r#"for=192.0.2.1;host="aio.example";proto=https, for=10.0.0.2"#,
+            ),
+            ("origin", "https://aio.example"),
+        ]);
+        assert!(is_same_origin(&forwarded, true));
+
+        let foreign = headers(&[
+            ("host", "nextcloud-aio-mastercontainer:8080"),
+            ("x-forwarded-host", "aio.example"),
+            ("origin", "https://evil.example"),
+        ]);
+        assert!(!is_same_origin(&foreign, true));
+    }
+}
@@ -0,0 +1,275 @@
+use crate::auth::client_ip::ClientIp;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{info, warn};
+
+// Failures per client that are not slowed down at all.
+const FREE_ATTEMPTS: u32 = 3;
+// Failures per client after which the client is locked out.
+const LOCKOUT_ATTEMPTS: u32 = 10;
+const LOCKOUT: Duration = Duration::from_secs(15 * 60);
+const MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+// Failures from all clients together that trigger a short pause for everyone.
+const GLOBAL_ATTEMPTS: usize = 50;
+const GLOBAL_WINDOW: Duration = Duration::from_secs(10 * 60);
+const GLOBAL_BACKOFF: Duration = Duration::from_secs(30);
+
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    blocked_until: Instant,
+}
+
+pub struct Attempt {
+    client: ClientIp,
+    started: Instant,
+    previous_block: Instant,
+}
+
+pub struct LoginThrottle {
+    clients: HashMap<ClientIp, Attempts>,
+    failures: VecDeque<Instant>,
+    blocked_until: Option<Instant>,
+}
+
+static INSTANCE: OnceCell<Mutex<LoginThrottle>> = OnceCell::const_new();
+
+fn backoff(failures: u32) -> Duration {
+    if failures >= LOCKOUT_ATTEMPTS {
+        LOCKOUT
+    } else if failures > FREE_ATTEMPTS {
+        Duration::from_secs(1 << (failures - FREE_ATTEMPTS)).min(MAX_BACKOFF)
+    } else {
+        Duration::ZERO
+    }
+}
+
+impl LoginThrottle {
+    fn new() -> Self {
+        Self {
+            clients: HashMap::new(),
+            failures: VecDeque::new(),
+            blocked_until: None,
+        }
+    }
+
+    pub async fn instance() -> &'static Mutex<Self> {
+        INSTANCE
+            .get_or_init(|| async { Mutex::new(Self::new()) })
+            .await
+    }
+
+    fn forget_old(&mut self, now: Instant) {
+        self.clients.retain(|_, attempts| {
+            now < attempts.blocked_until.max(attempts.last_failure + LOCKOUT)
+        });
+        while self
+            .failures
+            .front()
+            .is_some_and(|failure| *failure + GLOBAL_WINDOW <= now)
+        {
+            self.failures.pop_front();
+        }
+    }
+
+    // Whole seconds, rounded up so that the client is not told to retry too early.
+    pub fn retry_after(&mut self, client: ClientIp) -> Option<u64> {
+        let now = Instant::now();
+        self.forget_old(now);
+        let client_until = self.clients.get(&client).map(|a| a.blocked_until);
+        client_until
+            .into_iter()
+            .chain(self.blocked_until)
+            .max()
+            .filter(|until| *until > now)
+            .map(|until| {
+                let wait = until - now;
+                wait.as_secs() + u64::from(wait.subsec_nanos() > 0)
+            })
+    }
+
+    // The attempt counts as failed until it is known to be otherwise, so that the password can be
+    // verified without holding the lock while parallel requests still cannot outrun the backoff.
+    pub fn attempt(&mut self, client: ClientIp) -> Result<Attempt, u64> {
+        if let Some(retry_after) = self.retry_after(client) {
+            warn!(
+                event = "login_throttled",
+                %client,
+                retry_after_seconds = retry_after,
+                "Rejected login attempt during backoff"
+            );
+            return Err(retry_after);
+        }
+        let now = Instant::now();
+        let attempts = self.clients.entry(client).or_insert(Attempts {
+            failures: 0,
+            last_failure: now,
+            blocked_until: now,
+        });
+        let previous_block = attempts.blocked_until;
+        attempts.failures += 1;
+        attempts.last_failure = now;
+        attempts.blocked_until = now + backoff(attempts.failures);
+
+        self.failures.push_back(now);
+        if self.failures.len() >= GLOBAL_ATTEMPTS {
+            self.blocked_until = Some(now + GLOBAL_BACKOFF);
+            warn!(
+                event = "login_globally_throttled",
+                failures = self.failures.len(),
+                window_seconds = GLOBAL_WINDOW.as_secs(),
+                "Too many failed logins from all clients, pausing logins"
+            );
+        }
+        Ok(Attempt {
+            client,
+            started: now,
+            previous_block,
+        })
+    }
+
+    pub fn failure(&mut self, attempt: Attempt) {
+        let client = attempt.client;
+        let failures = self.clients.get(&client).map_or(0, |a| a.failures);
+        if failures >= LOCKOUT_ATTEMPTS {
+            warn!(
+                event = "login_locked_out",
+                %client,
+                failures,
+                lockout_seconds = LOCKOUT.as_secs(),
+                "Locked out client after too many failed logins"
+            );
+        } else {
+            warn!(event = "login_failed", %client, failures, "Failed login attempt");
+        }
+    }
+
+    pub fn success(&mut self, attempt: Attempt) {
+        self.clients.remove(&attempt.client);
+        self.forget_attempt(&attempt);
+        info!(event = "login_succeeded", client = %attempt.client, "Successful login");
+    }
+
+    // For attempts that neither failed nor completed the login, like a correct passphrase that
+    // still needs the second factor.
+    pub fn cancel(&mut self, attempt: Attempt) {
+        if let Some(attempts) = self.clients.get_mut(&attempt.client) {
+            attempts.failures = attempts.failures.saturating_sub(1);
+            attempts.blocked_until = attempt.previous_block;
+        }
+        self.forget_attempt(&attempt);
+    }
+
+    fn forget_attempt(&mut self, attempt: &Attempt) {
+        if let Some(i) = self.failures.iter().position(|t| *t == attempt.started) {
+            self.failures.remove(i);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth::client_ip::resolve_client_ip;
+    use axum::http::HeaderMap;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    fn client(last: u8) -> ClientIp {
+        ClientIp(IpAddr::V4(Ipv4Addr::new(192, 0, 2, last)))
+    }
+
+    fn fail(throttle: &mut LoginThrottle, client: ClientIp) {
+        let attempt = throttle.attempt(client).unwrap();
+        throttle.failure(attempt);
+    }
+
+    #[test]
+    fn failures_back_off_per_client() {
+        let mut throttle = LoginThrottle::new();
+        for _ in 0..FREE_ATTEMPTS {
+            fail(&mut throttle, client(1));
+            assert_eq!(throttle.retry_after(client(1)), None);
+        }
+        fail(&mut throttle, client(1));
+        assert_eq!(throttle.retry_after(client(1)), Some(2));
+        assert_eq!(throttle.attempt(client(1)).err(), Some(2));
+        assert_eq!(throttle.retry_after(client(2)), None);
+
+        // A login in progress already counts, so a parallel one has to wait for it.
+        let attempt = throttle.attempt(client(3)).unwrap();
+        assert_eq!(throttle.clients[&client(3)].failures, 1);
+        throttle.success(attempt);
+        assert!(!throttle.clients.contains_key(&client(3)));
+
+        for _ in 0..FREE_ATTEMPTS {
+            fail(&mut throttle, client(4));
+        }
+        let attempt = throttle.attempt(client(4)).unwrap();
+        assert_eq!(throttle.retry_after(client(4)), Some(2));
+        throttle.cancel(attempt);
+        assert_eq!(throttle.retry_after(client(4)), None);
+        assert_eq!(throttle.clients[&client(4)].failures, FREE_ATTEMPTS);
+    }
+
+    #[test]
+    fn lockouts_last_fifteen_minutes() {
+        assert_eq!(backoff(LOCKOUT_ATTEMPTS - 1), Duration::from_secs(64));
+        assert_eq!(backoff(LOCKOUT_ATTEMPTS), LOCKOUT);
+        let mut throttle = LoginThrottle::new();
+        throttle.clients.insert(
+            client(1),
+            Attempts {
+                failures: LOCKOUT_ATTEMPTS,
+                last_failure: Instant::now(),
+                blocked_until: Instant::now() + LOCKOUT,
+            },
+        );
+        assert_eq!(throttle.retry_after(client(1)), Some(LOCKOUT.as_secs()));
+    }
+
+    #[test]
+    fn many_failures_pause_every_client() {
+        let mut throttle = LoginThrottle::new();
+        for i in 0..GLOBAL_ATTEMPTS - 1 {
+            fail(&mut throttle, client(i as u8));
+        }
+        assert_eq!(throttle.retry_after(client(255)), None);
+        fail(&mut throttle, client(254));
+        assert_eq!(
+            throttle.retry_after(client(255)),
+            Some(GLOBAL_BACKOFF.as_secs())
+        );
+        assert_eq!(
+            throttle.attempt(client(255)).err(),
+            Some(GLOBAL_BACKOFF.as_secs())
+        );
+    }
+
+    #[test]
+    fn forged_forwarded_for_headers_share_the_peer_backoff() {
+        let peer = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let trusted = ["10.0.0.0/8".parse().unwrap()];
+        let mut throttle = LoginThrottle::new();
+        for i in 0..=FREE_ATTEMPTS {
+            let mut headers = HeaderMap::new();
+            headers.insert(
+                "x-forwarded-for",
+                format!("198.51.100.{i}").parse().unwrap(),
+            );
+            fail(
+                &mut throttle,
+                ClientIp(resolve_client_ip(peer, &headers, &trusted)),
+            );
+        }
+        assert_eq!(throttle.retry_after(ClientIp(peer)), Some(2));
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "198.51.100.1".parse().unwrap());
+        let proxy = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+        assert_eq!(
+            resolve_client_ip(proxy, &headers, &trusted),
+            IpAddr::V4(Ipv4Addr::new(198, 51, 100, 1))
+        );
+    }
+}
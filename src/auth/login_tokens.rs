@@ -0,0 +1,84 @@
+use crate::data::data_const::get_login_tokens_file;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use time::{Duration, OffsetDateTime};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::error;
+
+pub const LOGIN_TOKEN_TTL: Duration = Duration::minutes(5);
+
+#[derive(Serialize, Deserialize)]
+struct LoginToken {
+    hash: String,
+    #[serde(with = "time::serde::timestamp")]
+    expires_at: OffsetDateTime,
+}
+
+// Only hashes are kept, so the file is useless to someone who can read it. The tokens are random
+// and long enough that a plain SHA-256 is sufficient.
+pub struct LoginTokens {
+    tokens: Vec<LoginToken>,
+}
+
+static INSTANCE: OnceCell<Mutex<LoginTokens>> = OnceCell::const_new();
+
+fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+impl LoginTokens {
+    fn load() -> Self {
+        let tokens = std::fs::read(get_login_tokens_file())
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self { tokens }
+    }
+
+    pub async fn instance() -> &'static Mutex<Self> {
+        INSTANCE
+            .get_or_init(|| async { Mutex::new(Self::load()) })
+            .await
+    }
+
+    pub async fn reload() {
+        *Self::instance().await.lock().await = Self::load();
+    }
+
+    fn persist(&self) {
+        let result = serde_json::to_vec(&self.tokens)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                std::fs::write(get_login_tokens_file(), data).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            error!("Could not write the login tokens file: {}", e);
+        }
+    }
+
+    fn forget_expired(&mut self, now: OffsetDateTime) {
+        self.tokens.retain(|token| token.expires_at > now);
+    }
+
+    pub fn mint(&mut self) -> String {
+        let now = OffsetDateTime::now_utc();
+        self.forget_expired(now);
+        let token = hex::encode(rand::random::<[u8; 24]>());
+        self.tokens.push(LoginToken {
+            hash: hash(&token),
+            expires_at: now + LOGIN_TOKEN_TTL,
+        });
+        self.persist();
+        token
+    }
+
+    pub fn consume(&mut self, token: &str) -> bool {
+        self.forget_expired(OffsetDateTime::now_utc());
+        let hash = hash(token);
+        let count = self.tokens.len();
+        self.tokens.retain(|token| token.hash != hash);
+        let is_valid = self.tokens.len() != count;
+        self.persist();
+        is_valid
+    }
+}
@@ -1,8 +1,22 @@
-mod auth_middleware;
+pub mod api_tokens;
+pub mod audit;
+pub mod auth_middleware;
+pub mod client_ip;
 mod controller;
-mod password_generator;
+pub mod csrf;
+pub mod login_throttle;
+pub mod login_tokens;
+pub mod password_generator;
+pub mod password_hash;
+pub mod session_store;
+pub mod sessions;
+pub mod two_factor;
+pub mod users;
 
+pub use crate::auth::auth_middleware::auth_middleware;
 pub use crate::auth::controller::{
-    can_be_installed, clear_auth, is_authenticated, set_auth_from_password, set_auth_from_token,
-    setup_password,
+    can_be_installed, change_password, clear_auth, current_user, is_authenticated,
+    is_second_factor_pending, login_name, session_id, set_auth_from_password, set_auth_from_proxy,
+    set_auth_from_second_factor, set_auth_from_token, set_session_generation, setup_password,
+    track_session, CurrentUser, PasswordChange, PasswordLogin, ProxyLogin,
 };
@@ -0,0 +1,101 @@
+use argon2::password_hash::rand_core::OsRng;
+use argon2::password_hash::{Error, PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use argon2::Argon2;
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Verification {
+    Valid,
+    ValidPlaintext,
+    Invalid,
+}
+
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub fn hash_password(password: &str) -> Result<String, Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map(|hash| hash.to_string())
+}
+
+// Installations from before hashing store the passphrase itself, which is accepted once so that it
+// can be replaced by a hash.
+pub fn verify_password(stored: &str, password: &str) -> Verification {
+    match PasswordHash::new(stored) {
+        Ok(hash) => match Argon2::default().verify_password(password.as_bytes(), &hash) {
+            Ok(()) => Verification::Valid,
+            Err(_) => Verification::Invalid,
+        },
+        Err(_)
+            if !stored.is_empty() && constant_time_eq(stored.as_bytes(), password.as_bytes()) =>
+        {
+            Verification::ValidPlaintext
+        }
+        Err(_) => Verification::Invalid,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::configuration::{StateConfiguration, PASSWORD_TURN};
+    use crate::data::data_const::use_test_home;
+
+    #[test]
+    fn hashes_verify_only_their_password() {
+        let hash = hash_password("correct horse battery staple").unwrap();
+        assert!(hash.starts_with("$argon2id$"));
+        assert_ne!(hash, hash_password("correct horse battery staple").unwrap());
+        assert_eq!(
+            verify_password(&hash, "correct horse battery staple"),
+            Verification::Valid
+        );
+        assert_eq!(
+            verify_password(&hash, "correct horse battery stable"),
+            Verification::Invalid
+        );
+        assert_eq!(verify_password(&hash, ""), Verification::Invalid);
+    }
+
+    #[test]
+    fn plaintext_is_only_accepted_when_it_matches() {
+        assert_eq!(
+            verify_password("plain words", "plain words"),
+            Verification::ValidPlaintext
+        );
+        assert_eq!(
+            verify_password("plain words", "plain word"),
+            Verification::Invalid
+        );
+        // Installations without a passphrase must not accept an empty one.
+        assert_eq!(verify_password("", ""), Verification::Invalid);
+        assert!(constant_time_eq(b"same", b"same"));
+        assert!(!constant_time_eq(b"same", b"some"));
+        assert!(!constant_time_eq(b"same", b"same "));
+    }
+
+    #[tokio::test]
+    async fn plaintext_passphrases_are_migrated_on_login() {
+        use_test_home();
+        let _turn = PASSWORD_TURN.lock().await;
+        let previous = StateConfiguration::instance_ref().await.password.clone();
+        let plaintext = "an old plaintext passphrase from before hashing";
+        StateConfiguration::instance_mut().await.password = String::from(plaintext);
+
+        assert!(!StateConfiguration::test_password("a wrong passphrase").await);
+        assert_eq!(StateConfiguration::instance_ref().await.password, plaintext);
+
+        assert!(StateConfiguration::test_password(plaintext).await);
+        let stored = StateConfiguration::instance_ref().await.password.clone();
+        assert!(stored.starts_with("$argon2id$"));
+        assert_eq!(verify_password(&stored, plaintext), Verification::Valid);
+        assert!(StateConfiguration::test_password(plaintext).await);
+        assert_eq!(StateConfiguration::instance_ref().await.password, stored);
+
+        let mut config = StateConfiguration::instance_mut().await;
+        config.password = previous;
+        config.commit();
+    }
+}
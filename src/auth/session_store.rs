@@ -0,0 +1,211 @@
+use axum::async_trait;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+use tower_sessions::session::{Id, Record};
+use tower_sessions::session_store::{Error, ExpiredDeletion, Result};
+use tower_sessions::SessionStore;
+use tracing::{error, info};
+
+const PREFIX: &str = "sess_";
+
+#[derive(Clone, Debug)]
+pub struct FileStore {
+    directory: PathBuf,
+}
+
+fn backend(e: impl ToString) -> Error {
+    Error::Backend(e.to_string())
+}
+
+fn is_expired(record: &Record) -> bool {
+    record.expiry_date <= OffsetDateTime::now_utc()
+}
+
+impl FileStore {
+    pub fn new(directory: PathBuf) -> Self {
+        if let Err(e) = std::fs::create_dir_all(&directory) {
+            error!(
+                "Could not create the session directory {}: {}",
+                directory.display(),
+                e
+            );
+        }
+        Self { directory }
+    }
+
+    fn path(&self, id: &Id) -> PathBuf {
+        self.directory.join(format!("{}{}", PREFIX, id))
+    }
+
+    async fn read(&self, path: &Path) -> Result<Option<Record>> {
+        match fs::read(path).await {
+            Ok(data) => serde_json::from_slice(&data)
+                .map(Some)
+                .map_err(|e| Error::Decode(e.to_string())),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(backend(e)),
+        }
+    }
+
+    // Written to a temporary file first so that a crash never leaves a truncated session behind.
+    async fn write(&self, record: &Record, create_new: bool) -> Result<bool> {
+        let data = serde_json::to_vec(record).map_err(|e| Error::Encode(e.to_string()))?;
+        let path = self.path(&record.id);
+        let temporary = self.directory.join(format!(".{}{}.tmp", PREFIX, record.id));
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&temporary)
+            .await
+            .map_err(backend)?;
+        file.write_all(&data).await.map_err(backend)?;
+        file.sync_all().await.map_err(backend)?;
+        if create_new && fs::try_exists(&path).await.map_err(backend)? {
+            fs::remove_file(&temporary).await.map_err(backend)?;
+            return Ok(false);
+        }
+        fs::rename(&temporary, &path).await.map_err(backend)?;
+        Ok(true)
+    }
+
+    pub async fn delete_expired_periodically(self, period: Duration) {
+        let mut interval = tokio::time::interval(period);
+        loop {
+            interval.tick().await;
+            if let Err(e) = self.delete_expired().await {
+                error!("Could not delete expired sessions: {}", e);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl SessionStore for FileStore {
+    async fn create(&self, record: &mut Record) -> Result<()> {
+        while !self.write(record, true).await? {
+            record.id = Id::default();
+        }
+        Ok(())
+    }
+
+    async fn save(&self, record: &Record) -> Result<()> {
+        self.write(record, false).await.map(|_| ())
+    }
+
+    async fn load(&self, session_id: &Id) -> Result<Option<Record>> {
+        match self.read(&self.path(session_id)).await? {
+            Some(record) if is_expired(&record) => {
+                self.delete(session_id).await?;
+                Ok(None)
+            }
+            record => Ok(record),
+        }
+    }
+
+    async fn delete(&self, session_id: &Id) -> Result<()> {
+        match fs::remove_file(self.path(session_id)).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => Err(backend(e)),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl ExpiredDeletion for FileStore {
+    async fn delete_expired(&self) -> Result<()> {
+        let mut entries = fs::read_dir(&self.directory).await.map_err(backend)?;
+        let mut deleted = 0;
+        while let Some(entry) = entries.next_entry().await.map_err(backend)? {
+            let is_session = entry
+                .file_name()
+                .to_str()
+                .is_some_and(|name| name.starts_with(PREFIX));
+            if !is_session {
+                continue;
+            }
+            // Unreadable sessions can never be loaded again, so they are removed as well.
+            let expired = self
+                .read(&entry.path())
+                .await
+                .map_or(true, |record| record.is_some_and(|r| is_expired(&r)));
+            if expired {
+                match fs::remove_file(entry.path()).await {
+                    Err(e) if e.kind() != ErrorKind::NotFound => return Err(backend(e)),
+                    _ => deleted += 1,
+                }
+            }
+        }
+        if deleted > 0 {
+            info!("Deleted {} expired session(s)", deleted);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::os::unix::fs::PermissionsExt;
+    use time::Duration;
+
+    #[tokio::test]
+    async fn file_sessions_are_written_whole_and_expire() {
+        let directory = std::env::temp_dir().join("nextcloud-aio-file-sessions");
+        let _ = std::fs::remove_dir_all(&directory);
+        let store = FileStore::new(directory.clone());
+        let record = |id: i128, user: &str, expiry_date| Record {
+            id: Id(id),
+            data: HashMap::from([(String::from("user"), serde_json::json!(user))]),
+            expiry_date,
+        };
+        let tomorrow = OffsetDateTime::now_utc() + Duration::days(1);
+        let yesterday = OffsetDateTime::now_utc() - Duration::days(1);
+        let name = |id: i128| format!("sess_{}", Id(id));
+        let files = || {
+            let mut names: Vec<String> = std::fs::read_dir(&directory)
+                .unwrap()
+                .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+                .collect();
+            names.sort();
+            names
+        };
+
+        store.save(&record(1, "alice", tomorrow)).await.unwrap();
+        store.save(&record(1, "bob", tomorrow)).await.unwrap();
+        assert_eq!(files(), [name(1)]);
+        let mode = std::fs::metadata(directory.join(name(1)))
+            .unwrap()
+            .permissions();
+        assert_eq!(mode.mode() & 0o777, 0o600);
+
+        // A new session never takes over an existing one.
+        let mut created = record(1, "mallory", tomorrow);
+        store.create(&mut created).await.unwrap();
+        assert_ne!(created.id, Id(1));
+        let loaded = store.load(&Id(1)).await.unwrap().unwrap();
+        assert_eq!(loaded.data["user"], "bob");
+        let loaded = store.load(&created.id).await.unwrap().unwrap();
+        assert_eq!(loaded.data["user"], "mallory");
+        store.delete(&created.id).await.unwrap();
+        assert_eq!(files(), [name(1)]);
+
+        store.save(&record(2, "carol", yesterday)).await.unwrap();
+        assert!(store.load(&Id(2)).await.unwrap().is_none());
+        assert_eq!(files(), [name(1)]);
+
+        // Expired and unreadable sessions are cleaned up, other files are left alone.
+        store.save(&record(3, "dave", yesterday)).await.unwrap();
+        std::fs::write(directory.join(name(4)), "{").unwrap();
+        std::fs::write(directory.join("notes"), "kept").unwrap();
+        store.delete_expired().await.unwrap();
+        assert_eq!(files(), [String::from("notes"), name(1)]);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
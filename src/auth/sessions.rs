@@ -0,0 +1,265 @@
+use crate::auth::api_tokens::{format_time, ApiTokens};
+use crate::configuration::StateConfiguration;
+use crate::data::data_const::get_sessions_file;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use time::{Duration, OffsetDateTime};
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{error, info};
+
+// Writing the file on every request would be wasteful, so the last activity is only this precise.
+const LAST_SEEN_PRECISION: Duration = Duration::minutes(1);
+const TOKEN_PREFIX: &str = "token-";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AuthMethod {
+    Password,
+    LoginToken,
+    Proxy,
+    ApiToken,
+}
+
+impl Display for AuthMethod {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            AuthMethod::Password => "password",
+            AuthMethod::LoginToken => "getlogin token",
+            AuthMethod::Proxy => "reverse proxy",
+            AuthMethod::ApiToken => "API token",
+        })
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: String,
+    pub user: String,
+    pub method: AuthMethod,
+    #[serde(with = "time::serde::timestamp")]
+    pub created_at: OffsetDateTime,
+    #[serde(with = "time::serde::timestamp")]
+    pub last_seen_at: OffsetDateTime,
+    pub ip: String,
+    pub user_agent: String,
+}
+
+impl SessionInfo {
+    pub fn created(&self) -> String {
+        format_time(self.created_at)
+    }
+
+    pub fn last_seen(&self) -> String {
+        format_time(self.last_seen_at)
+    }
+
+    fn seen(&mut self, now: OffsetDateTime, ip: &str, user_agent: &str) -> bool {
+        if now - self.last_seen_at < LAST_SEEN_PRECISION
+            && self.ip == ip
+            && self.user_agent == user_agent
+        {
+            return false;
+        }
+        self.last_seen_at = now;
+        self.ip = String::from(ip);
+        self.user_agent = String::from(user_agent);
+        true
+    }
+
+    // API tokens are listed next to the sessions, revoking them revokes the token.
+    pub fn token_id(&self) -> Option<&str> {
+        self.id.strip_prefix(TOKEN_PREFIX)
+    }
+}
+
+pub struct Lifetimes {
+    pub idle: Duration,
+    pub absolute: Duration,
+}
+
+impl Lifetimes {
+    pub async fn configured() -> Self {
+        let config = StateConfiguration::instance_ref().await;
+        Lifetimes {
+            idle: Duration::minutes(config.session_idle_timeout.into()),
+            absolute: Duration::hours(config.session_lifetime.into()),
+        }
+    }
+}
+
+// Who holds a response open that outlasts the request, like the event stream. Logging out,
+// revoking the session or the token and the end of the session's lifetime all end it.
+pub enum Holder {
+    Session(String),
+    Token(String),
+}
+
+impl Holder {
+    pub async fn is_valid(&self) -> bool {
+        match self {
+            Holder::Session(id) => {
+                let lifetimes = Lifetimes::configured().await;
+                let mut sessions = Sessions::instance().await.lock().await;
+                sessions.is_active(id, &lifetimes)
+            }
+            Holder::Token(id) => {
+                let tokens = ApiTokens::instance().await.lock().await;
+                tokens.list().iter().any(|token| token.id == *id)
+            }
+        }
+    }
+}
+
+// Only the metadata lives here, the sessions themselves stay in the session store. A session whose
+// entry is gone has been revoked.
+pub struct Sessions {
+    sessions: Vec<SessionInfo>,
+}
+
+static INSTANCE: OnceCell<Mutex<Sessions>> = OnceCell::const_new();
+
+impl Sessions {
+    fn load() -> Self {
+        let sessions = std::fs::read(get_sessions_file())
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self { sessions }
+    }
+
+    pub async fn instance() -> &'static Mutex<Self> {
+        INSTANCE
+            .get_or_init(|| async { Mutex::new(Self::load()) })
+            .await
+    }
+
+    pub async fn reload() {
+        *Self::instance().await.lock().await = Self::load();
+    }
+
+    fn persist(&self) {
+        let result = serde_json::to_vec(&self.sessions)
+            .map_err(|e| e.to_string())
+            .and_then(|data| std::fs::write(get_sessions_file(), data).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Could not write the sessions file: {}", e);
+        }
+    }
+
+    // Entries of sessions that expired in the session store are dropped as well.
+    fn forget_expired(&mut self, lifetimes: &Lifetimes, now: OffsetDateTime) -> bool {
+        let count = self.sessions.len();
+        self.sessions.retain(|session| {
+            session.method == AuthMethod::ApiToken
+                || (session.last_seen_at + lifetimes.idle > now
+                    && session.created_at + lifetimes.absolute > now)
+        });
+        self.sessions.len() != count
+    }
+
+    pub fn list(&mut self, lifetimes: &Lifetimes) -> &[SessionInfo] {
+        if self.forget_expired(lifetimes, OffsetDateTime::now_utc()) {
+            self.persist();
+        }
+        &self.sessions
+    }
+
+    pub fn start(&mut self, user: &str, method: AuthMethod, ip: &str, user_agent: &str) -> String {
+        let now = OffsetDateTime::now_utc();
+        let id = hex::encode(rand::random::<[u8; 16]>());
+        self.sessions.push(SessionInfo {
+            id: id.clone(),
+            user: String::from(user),
+            method,
+            created_at: now,
+            last_seen_at: now,
+            ip: String::from(ip),
+            user_agent: String::from(user_agent),
+        });
+        self.persist();
+        id
+    }
+
+    // Returns false once the session was revoked or has outlived its absolute lifetime.
+    pub fn touch(&mut self, id: &str, lifetimes: &Lifetimes, ip: &str, user_agent: &str) -> bool {
+        let now = OffsetDateTime::now_utc();
+        let mut changed = self.forget_expired(lifetimes, now);
+        let Some(session) = self.sessions.iter_mut().find(|session| session.id == id) else {
+            if changed {
+                self.persist();
+            }
+            return false;
+        };
+        changed |= session.seen(now, ip, user_agent);
+        if changed {
+            self.persist();
+        }
+        true
+    }
+
+    // Unlike touch(), this does not count as activity.
+    pub fn is_active(&mut self, id: &str, lifetimes: &Lifetimes) -> bool {
+        if self.forget_expired(lifetimes, OffsetDateTime::now_utc()) {
+            self.persist();
+        }
+        self.sessions.iter().any(|session| session.id == id)
+    }
+
+    pub fn touch_token(&mut self, id: &str, name: &str, ip: &str, user_agent: &str) {
+        let id = format!("{}{}", TOKEN_PREFIX, id);
+        let now = OffsetDateTime::now_utc();
+        match self.sessions.iter_mut().find(|session| session.id == id) {
+            Some(session) => {
+                if !session.seen(now, ip, user_agent) {
+                    return;
+                }
+            }
+            None => self.sessions.push(SessionInfo {
+                id,
+                user: String::from(name),
+                method: AuthMethod::ApiToken,
+                created_at: now,
+                last_seen_at: now,
+                ip: String::from(ip),
+                user_agent: String::from(user_agent),
+            }),
+        }
+        self.persist();
+    }
+
+    pub fn end(&mut self, id: &str) {
+        let count = self.sessions.len();
+        self.sessions.retain(|session| session.id != id);
+        if self.sessions.len() != count {
+            self.persist();
+        }
+    }
+
+    pub fn forget_token(&mut self, id: &str) {
+        self.end(&format!("{}{}", TOKEN_PREFIX, id));
+    }
+
+    pub fn revoke(&mut self, id: &str) -> Option<SessionInfo> {
+        let index = self.sessions.iter().position(|session| session.id == id)?;
+        let session = self.sessions.remove(index);
+        self.persist();
+        info!(session = session.id, user = session.user, "Revoked session");
+        Some(session)
+    }
+
+    // Logs out every browser session except `keep`, or only those of `user` if given.
+    pub fn revoke_others(&mut self, keep: Option<&str>, user: Option<&str>) -> usize {
+        let count = self.sessions.len();
+        self.sessions.retain(|session| {
+            session.method == AuthMethod::ApiToken
+                || Some(session.id.as_str()) == keep
+                || user.is_some_and(|user| session.user != user)
+        });
+        let revoked = count - self.sessions.len();
+        if revoked > 0 {
+            self.persist();
+            info!("Revoked {} other session(s)", revoked);
+        }
+        revoked
+    }
+}
@@ -0,0 +1,265 @@
+use crate::auth::password_hash::constant_time_eq;
+use crate::auth::users::SHARED_ACCOUNT;
+use crate::data::data_const::get_two_factor_file;
+use qrcode::render::svg;
+use qrcode::QrCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use time::OffsetDateTime;
+use tokio::sync::{Mutex, OnceCell};
+use totp_rs::{Algorithm, Secret, TOTP};
+use tracing::{error, info, warn};
+
+const ISSUER: &str = "Nextcloud AIO";
+const DIGITS: usize = 6;
+const STEP: u64 = 30;
+// Codes from one step before and after the current one are accepted to allow for clock drift.
+const SKEW: u64 = 1;
+const RECOVERY_CODES: usize = 10;
+
+// The secret of an enrollment that has not been confirmed with a code yet.
+pub const PENDING_SECRET_SESSION_KEY: &str = "pending_two_factor_secret";
+// Freshly generated recovery codes are kept in the session until the page has shown them once.
+pub const RECOVERY_CODES_SESSION_KEY: &str = "new_recovery_codes";
+
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    secret: Option<String>,
+    recovery_codes: Vec<String>,
+    // The last accepted step, so that a code cannot be used twice.
+    last_step: u64,
+}
+
+// Every account enrolls on its own, the shared AIO passphrase under SHARED_ACCOUNT.
+pub struct TwoFactor {
+    accounts: HashMap<String, State>,
+}
+
+static INSTANCE: OnceCell<Mutex<TwoFactor>> = OnceCell::const_new();
+
+fn hash(code: &str) -> String {
+    hex::encode(Sha256::digest(code.as_bytes()))
+}
+
+fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase()
+}
+
+fn totp(secret: &str) -> Option<TOTP> {
+    let secret = Secret::Encoded(String::from(secret)).to_bytes().ok()?;
+    TOTP::new(Algorithm::SHA1, DIGITS, SKEW as u8, STEP, secret).ok()
+}
+
+pub fn generate_secret() -> String {
+    Secret::Raw(rand::random::<[u8; 20]>().to_vec())
+        .to_encoded()
+        .to_string()
+}
+
+pub fn otpauth_url(secret: &str) -> String {
+    let issuer = ISSUER.replace(' ', "%20");
+    format!(
+        "otpauth://totp/{issuer}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={DIGITS}&period={STEP}"
+    )
+}
+
+pub fn qr_code_svg(secret: &str) -> Result<String, String> {
+    let code = QrCode::new(otpauth_url(secret).as_bytes()).map_err(|e| e.to_string())?;
+    Ok(code.render::<svg::Color>().min_dimensions(200, 200).build())
+}
+
+fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODES)
+        .map(|_| {
+            let code = hex::encode(rand::random::<[u8; 5]>());
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+// Returns the step of the matching code, if it is newer than the last accepted one.
+fn check_code(secret: &str, code: &str, last_step: u64) -> Option<u64> {
+    let totp = totp(secret)?;
+    let now = OffsetDateTime::now_utc().unix_timestamp() as u64 / STEP;
+    (now.saturating_sub(SKEW)..=now + SKEW)
+        .filter(|step| *step > last_step)
+        .find(|step| constant_time_eq(totp.generate(step * STEP).as_bytes(), code.as_bytes()))
+}
+
+impl TwoFactor {
+    fn load() -> Self {
+        let Ok(data) = std::fs::read(get_two_factor_file()) else {
+            return Self {
+                accounts: HashMap::new(),
+            };
+        };
+        if let Ok(accounts) = serde_json::from_slice(&data) {
+            return Self { accounts };
+        }
+        // Older versions had a single enrollment for all logins.
+        let accounts = match serde_json::from_slice::<State>(&data) {
+            Ok(state) => {
+                if state.secret.is_some() {
+                    warn!(
+                        "Two-factor authentication is now set up per account. The existing enrollment only applies to the AIO passphrase login from now on."
+                    );
+                }
+                HashMap::from([(String::from(SHARED_ACCOUNT), state)])
+            }
+            Err(e) => {
+                error!("Could not read the two-factor authentication file: {}", e);
+                HashMap::new()
+            }
+        };
+        Self { accounts }
+    }
+
+    pub async fn instance() -> &'static Mutex<Self> {
+        INSTANCE
+            .get_or_init(|| async { Mutex::new(Self::load()) })
+            .await
+    }
+
+    pub async fn reload() {
+        *Self::instance().await.lock().await = Self::load();
+    }
+
+    fn persist(&self) {
+        let result = serde_json::to_vec(&self.accounts)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                std::fs::write(get_two_factor_file(), data).map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            error!("Could not write the two-factor authentication file: {}", e);
+        }
+    }
+
+    pub fn is_enrolled(&self, account: &str) -> bool {
+        self.accounts
+            .get(account)
+            .is_some_and(|state| state.secret.is_some())
+    }
+
+    pub fn remaining_recovery_codes(&self, account: &str) -> usize {
+        self.accounts
+            .get(account)
+            .map_or(0, |state| state.recovery_codes.len())
+    }
+
+    fn check_totp(&mut self, account: &str, code: &str) -> bool {
+        let Some(state) = self.accounts.get_mut(account) else {
+            return false;
+        };
+        let Some(secret) = &state.secret else {
+            return false;
+        };
+        match check_code(secret, code, state.last_step) {
+            Some(step) => {
+                state.last_step = step;
+                self.persist();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn new_recovery_codes(&mut self, account: &str) -> Vec<String> {
+        let codes = generate_recovery_codes();
+        let state = self.accounts.entry(String::from(account)).or_default();
+        state.recovery_codes = codes.iter().map(|code| hash(code)).collect();
+        codes
+    }
+
+    // The secret comes from the session of the enrollment and only gets stored once the
+    // authenticator app has proven to produce matching codes.
+    pub fn enable(
+        &mut self,
+        account: &str,
+        secret: &str,
+        code: &str,
+    ) -> Result<Vec<String>, String> {
+        if self.is_enrolled(account) {
+            return Err(String::from(
+                "Two-factor authentication is already enabled.",
+            ));
+        }
+        let step = check_code(secret, &normalize(code), 0)
+            .ok_or_else(|| String::from("The code is wrong. Please try again."))?;
+        self.accounts.insert(
+            String::from(account),
+            State {
+                secret: Some(String::from(secret)),
+                recovery_codes: Vec::new(),
+                last_step: step,
+            },
+        );
+        let codes = self.new_recovery_codes(account);
+        self.persist();
+        info!(account, "Enabled two-factor authentication");
+        Ok(codes)
+    }
+
+    pub fn disable(&mut self, account: &str, code: &str) -> Result<(), String> {
+        if !self.check_totp(account, &normalize(code)) {
+            return Err(String::from("The code is wrong. Please try again."));
+        }
+        self.accounts.remove(account);
+        self.persist();
+        info!(account, "Disabled two-factor authentication");
+        Ok(())
+    }
+
+    // For accounts that are removed, which needs no code.
+    pub fn remove(&mut self, account: &str) {
+        if self.accounts.remove(account).is_some() {
+            self.persist();
+            info!(account, "Removed the two-factor authentication");
+        }
+    }
+
+    pub fn regenerate_recovery_codes(
+        &mut self,
+        account: &str,
+        code: &str,
+    ) -> Result<Vec<String>, String> {
+        if !self.check_totp(account, &normalize(code)) {
+            return Err(String::from("The code is wrong. Please try again."));
+        }
+        let codes = self.new_recovery_codes(account);
+        self.persist();
+        info!(account, "Generated new two-factor recovery codes");
+        Ok(codes)
+    }
+
+    // Accepts a code from the authenticator app or one of the recovery codes, which is used up.
+    pub fn verify(&mut self, account: &str, code: &str) -> bool {
+        let code = normalize(code);
+        if self.check_totp(account, &code) {
+            return true;
+        }
+        let Some(state) = self.accounts.get_mut(account) else {
+            return false;
+        };
+        let hash = hash(&code);
+        let position = state
+            .recovery_codes
+            .iter()
+            .position(|c| constant_time_eq(c.as_bytes(), hash.as_bytes()));
+        let Some(index) = position else {
+            return false;
+        };
+        state.recovery_codes.remove(index);
+        let remaining = state.recovery_codes.len();
+        self.persist();
+        warn!(
+            account,
+            remaining, "Logged in with a two-factor recovery code"
+        );
+        true
+    }
+}
@@ -0,0 +1,193 @@
+use crate::auth::api_tokens::format_time;
+use crate::auth::password_generator::check_password_strength;
+use crate::auth::password_hash::{hash_password, verify_password, Verification};
+use crate::data::data_const::get_users_file;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use time::OffsetDateTime;
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{error, info};
+
+// Sessions logged in with the AIO passphrase, a login token or an unknown proxy user are attributed
+// to this name, so no account may use it.
+pub const SHARED_ACCOUNT: &str = "aio";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Viewer,
+    Operator,
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 3] = [Role::Viewer, Role::Operator, Role::Admin];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Operator => "operator",
+            Role::Admin => "admin",
+        }
+    }
+}
+
+impl Display for Role {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct User {
+    pub name: String,
+    hash: String,
+    pub role: Role,
+    #[serde(with = "time::serde::timestamp")]
+    pub created_at: OffsetDateTime,
+    // Bumped on every password reset, which logs out the sessions of the user.
+    #[serde(default)]
+    pub generation: u64,
+}
+
+impl User {
+    pub fn created(&self) -> String {
+        format_time(self.created_at)
+    }
+}
+
+pub struct Users {
+    users: Vec<User>,
+}
+
+static INSTANCE: OnceCell<Mutex<Users>> = OnceCell::const_new();
+
+fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() {
+        return Err(String::from("Please enter a name for the user."));
+    }
+    if !name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '@'))
+    {
+        return Err(String::from(
+            "The name may only contain the characters a-z, A-Z, 0-9, '.', '_', '-' and '@'.",
+        ));
+    }
+    if name == SHARED_ACCOUNT {
+        return Err(format!("The name '{}' is reserved.", SHARED_ACCOUNT));
+    }
+    Ok(())
+}
+
+fn hash(password: &str) -> Result<String, String> {
+    check_password_strength(password)?;
+    hash_password(password).map_err(|e| e.to_string())
+}
+
+impl Users {
+    fn load() -> Self {
+        let users = std::fs::read(get_users_file())
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        Self { users }
+    }
+
+    pub async fn instance() -> &'static Mutex<Self> {
+        INSTANCE
+            .get_or_init(|| async { Mutex::new(Self::load()) })
+            .await
+    }
+
+    pub async fn reload() {
+        *Self::instance().await.lock().await = Self::load();
+    }
+
+    fn persist(&self) {
+        let result = serde_json::to_vec(&self.users)
+            .map_err(|e| e.to_string())
+            .and_then(|data| std::fs::write(get_users_file(), data).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            error!("Could not write the users file: {}", e);
+        }
+    }
+
+    fn find_mut(&mut self, name: &str) -> Result<&mut User, String> {
+        self.users
+            .iter_mut()
+            .find(|user| user.name == name)
+            .ok_or_else(|| format!("There is no user named '{}'.", name))
+    }
+
+    pub fn list(&self) -> &[User] {
+        &self.users
+    }
+
+    pub fn create(&mut self, name: &str, password: &str, role: Role) -> Result<(), String> {
+        let name = name.trim();
+        check_name(name)?;
+        if self.users.iter().any(|user| user.name == name) {
+            return Err(format!("A user named '{}' already exists.", name));
+        }
+        self.users.push(User {
+            name: String::from(name),
+            hash: hash(password)?,
+            role,
+            created_at: OffsetDateTime::now_utc(),
+            generation: 0,
+        });
+        self.persist();
+        info!(account = name, %role, "Created user");
+        Ok(())
+    }
+
+    pub fn set_role(&mut self, name: &str, role: Role) -> Result<(), String> {
+        self.find_mut(name)?.role = role;
+        self.persist();
+        info!(account = name, %role, "Changed the role of user");
+        Ok(())
+    }
+
+    pub fn set_password(&mut self, name: &str, password: &str) -> Result<(), String> {
+        let hash = hash(password)?;
+        let user = self.find_mut(name)?;
+        user.hash = hash;
+        user.generation += 1;
+        self.persist();
+        info!(account = name, "Reset the password of user");
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        let Some(index) = self.users.iter().position(|user| user.name == name) else {
+            return false;
+        };
+        self.users.remove(index);
+        self.persist();
+        info!(account = name, "Removed user");
+        true
+    }
+
+    pub fn authenticate(&self, name: &str, password: &str) -> Option<&User> {
+        let user = self.users.iter().find(|user| user.name == name.trim())?;
+        (verify_password(&user.hash, password) == Verification::Valid).then_some(user)
+    }
+
+    // The role of a session's user, unless the user was removed or got a new password since.
+    pub fn role(&self, name: &str, generation: u64) -> Option<Role> {
+        self.users
+            .iter()
+            .find(|user| user.name == name && user.generation == generation)
+            .map(|user| user.role)
+    }
+
+    // Users authenticated by a reverse proxy need an account with the same name, unless no accounts
+    // exist at all.
+    pub fn external_role(&self, name: &str) -> Option<Role> {
+        match self.users.iter().find(|user| user.name == name) {
+            Some(user) => Some(user.role),
+            None => self.users.is_empty().then_some(Role::Admin),
+        }
+    }
+}
@@ -0,0 +1,441 @@
+use crate::auth::password_generator::check_password_strength;
+use crate::auth::password_hash::hash_password;
+use crate::configuration::{
+    parse_addresses, Configuration, ConfigurationSource, StateConfiguration, KEYS, SECRET_KEYS,
+};
+use crate::container::controller::DockerController;
+use crate::container::lock::{self, LockError};
+use crate::cron::{self, CronError};
+use crate::data::bundle::{export_bundle, import_bundle, BundleError};
+use crate::data::data_const::{
+    set_containers_file, set_home_directory, DEFAULT_CONTAINERS_FILE, DEFAULT_HOME_DIRECTORY,
+};
+use crate::server::{self, ServerError};
+use serde_json::Value;
+use std::fmt::{Display, Formatter};
+use std::io::{stdin, BufRead};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing::{error, info};
+
+// Who holds the operation lock while a command runs, shown to anyone who has to wait for it.
+const OWNER: &str = "the command line";
+
+fn usage() -> String {
+    format!(
+        "Usage: nextcloud_all_in_one [OPTIONS] [COMMAND]
+
+Commands:
+  serve [SERVER OPTIONS]        Serve the web interface, the default command
+  start [--no-pull]             Start the containers, pulling their images unless --no-pull is given
+  stop                          Stop the containers
+  backup                        Stop the containers and create a backup
+  check-backup                  Check the integrity of the backup archives
+  status                        Show the state of every container
+  logs <id>                     Print the logs of a container, e.g. nextcloud-aio-nextcloud
+  config get [key]              Print a configuration value, or all of them
+  config set <key> <value>      Change a configuration value
+  cron <job>                    Run a scheduled job
+  unlock                        Remove the operation lock left behind by a crashed operation
+  export-configuration <file>   Write an encrypted configuration bundle
+  import-configuration <file>   Restore an encrypted configuration bundle
+  help                          Print this help
+
+Options:
+  --data-dir <directory>        The mastercontainer volume, holding data/ and session/ [{}]
+  --containers-file <file>      The container definitions [{}]
+  --docker-socket <path>        The Docker socket handed to the containers [/var/run/docker.sock]
+
+Server options:
+  --listen <addresses>          Plain HTTP addresses, e.g. \"0.0.0.0:8080 [::]:8080\"
+  --tls-listen <addresses>      HTTPS addresses, e.g. 0.0.0.0:8443
+  --tls-certificate <file>      PEM certificate, a self-signed one is generated otherwise
+  --tls-key <file>              PEM private key of the certificate",
+        DEFAULT_HOME_DIRECTORY, DEFAULT_CONTAINERS_FILE
+    )
+}
+
+#[derive(Debug)]
+enum CliError {
+    Usage(String),
+    Bundle(BundleError),
+    Docker(reqwest::Error),
+    Lock(LockError),
+    Configuration(String),
+    Server(ServerError),
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CliError::Usage(e) => write!(f, "{}", e),
+            CliError::Bundle(e) => write!(f, "{}", e),
+            CliError::Docker(e) => write!(f, "Could not reach Docker: {}", e),
+            CliError::Lock(e) => write!(f, "{}", e),
+            CliError::Configuration(e) => write!(f, "{}", e),
+            CliError::Server(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<BundleError> for CliError {
+    fn from(e: BundleError) -> Self {
+        CliError::Bundle(e)
+    }
+}
+
+impl From<reqwest::Error> for CliError {
+    fn from(e: reqwest::Error) -> Self {
+        CliError::Docker(e)
+    }
+}
+
+impl From<CronError> for CliError {
+    fn from(e: CronError) -> Self {
+        match e {
+            CronError::Docker(e) => CliError::Docker(e),
+            CronError::Lock(e) => CliError::Lock(e),
+        }
+    }
+}
+
+impl From<ServerError> for CliError {
+    fn from(e: ServerError) -> Self {
+        CliError::Server(e)
+    }
+}
+
+#[derive(Default)]
+struct GlobalOptions {
+    data_dir: Option<PathBuf>,
+    containers_file: Option<PathBuf>,
+    docker_socket: Option<String>,
+}
+
+enum Command {
+    Serve(Vec<String>),
+    Start { pull: bool },
+    Stop,
+    Backup,
+    CheckBackup,
+    Status,
+    Logs(String),
+    ConfigGet(Option<String>),
+    ConfigSet(String, String),
+    Cron(String),
+    Unlock,
+    ExportConfiguration(String),
+    ImportConfiguration(String),
+    Help,
+}
+
+fn read_passphrase() -> String {
+    eprintln!("Bundle passphrase:");
+    let mut passphrase = String::new();
+    stdin()
+        .lock()
+        .read_line(&mut passphrase)
+        .map_or(String::new(), |_| passphrase.trim_end().to_string())
+}
+
+fn export_configuration(file: &str) -> Result<(), BundleError> {
+    let bundle = export_bundle(&read_passphrase())?;
+    std::fs::write(file, bundle)?;
+    info!("configuration exported to {}", file);
+    Ok(())
+}
+
+fn import_configuration(file: &str) -> Result<(), BundleError> {
+    let bundle = std::fs::read(file)?;
+    import_bundle(&bundle, &read_passphrase())?;
+    info!("configuration imported from {}", file);
+    Ok(())
+}
+
+// The global options may appear anywhere, everything else is the command and its arguments.
+fn parse(args: &[String]) -> Result<(GlobalOptions, Command), CliError> {
+    let mut options = GlobalOptions::default();
+    let mut rest = Vec::new();
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let target = match arg.as_str() {
+            "--data-dir" | "--containers-file" | "--docker-socket" => arg.as_str(),
+            _ => {
+                rest.push(arg.as_str());
+                continue;
+            }
+        };
+        let value = args
+            .next()
+            .ok_or_else(|| CliError::Usage(format!("{} needs a value.", target)))?;
+        match target {
+            "--data-dir" => options.data_dir = Some(PathBuf::from(value)),
+            "--containers-file" => options.containers_file = Some(PathBuf::from(value)),
+            _ => options.docker_socket = Some(value.clone()),
+        }
+    }
+
+    let command = match rest.as_slice() {
+        ["help" | "--help" | "-h", ..] => Command::Help,
+        [] => Command::Serve(Vec::new()),
+        ["serve", server_options @ ..] => {
+            Command::Serve(server_options.iter().map(|s| s.to_string()).collect())
+        }
+        // Server options without a command, as the binary was started before it had commands.
+        [first, ..] if first.starts_with("--") => {
+            Command::Serve(rest.iter().map(|s| s.to_string()).collect())
+        }
+        ["start"] => Command::Start { pull: true },
+        ["start", "--no-pull"] => Command::Start { pull: false },
+        ["stop"] => Command::Stop,
+        ["backup"] => Command::Backup,
+        ["check-backup"] => Command::CheckBackup,
+        ["status"] => Command::Status,
+        ["logs", id] => Command::Logs(id.to_string()),
+        ["config", "get"] => Command::ConfigGet(None),
+        ["config", "get", key] => Command::ConfigGet(Some(key.to_string())),
+        ["config", "set", key, value] => Command::ConfigSet(key.to_string(), value.to_string()),
+        ["cron", job] => Command::Cron(job.to_string()),
+        ["unlock"] => Command::Unlock,
+        ["export-configuration", file] => Command::ExportConfiguration(file.to_string()),
+        ["import-configuration", file] => Command::ImportConfiguration(file.to_string()),
+        [command, ..] => {
+            return Err(CliError::Usage(format!(
+                "Unknown command or wrong arguments: {}",
+                command
+            )))
+        }
+    };
+    Ok((options, command))
+}
+
+// Options of the server itself, e.g. `--listen 0.0.0.0:8080 --tls-listen 0.0.0.0:8443`. They take
+// precedence over the environment and the configuration file.
+pub fn apply_server_options(args: &[String], config: &mut Configuration) -> Result<(), String> {
+    let mut args = args.iter();
+    while let Some(option) = args.next() {
+        let value = args
+            .next()
+            .ok_or_else(|| format!("{} needs a value.", option))?;
+        match option.as_str() {
+            "--listen" => config.listen = parse_addresses(value)?,
+            "--tls-listen" => config.tls_listen = parse_addresses(value)?,
+            "--tls-certificate" => config.tls_certificate = Some(value.clone()),
+            "--tls-key" => config.tls_key = Some(value.clone()),
+            _ => return Err(format!("Unknown option {}.", option)),
+        }
+    }
+    Ok(())
+}
+
+fn describe(source: ConfigurationSource) -> String {
+    match source {
+        ConfigurationSource::Default => String::from("default"),
+        ConfigurationSource::File => String::from("configuration file"),
+        ConfigurationSource::Environment(variable) => format!("environment variable {}", variable),
+    }
+}
+
+fn format_value(value: Option<Value>) -> String {
+    match value {
+        Some(Value::String(value)) => value,
+        Some(value) => value.to_string(),
+        None => String::new(),
+    }
+}
+
+async fn config_get(key: Option<String>) -> Result<(), CliError> {
+    let config = StateConfiguration::instance_ref().await;
+    match key {
+        Some(key) => {
+            let value = config.get(&key).map_err(CliError::Configuration)?;
+            println!("{}", format_value(value));
+        }
+        None => {
+            for key in KEYS {
+                // Only printed when asked for by name.
+                let value = if SECRET_KEYS.contains(key) {
+                    String::from("(hidden)")
+                } else {
+                    format_value(config.get(key).map_err(CliError::Configuration)?)
+                };
+                println!("{} = {} ({})", key, value, describe(config.source(key)));
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn config_set(key: &str, value: &str) -> Result<(), CliError> {
+    let mut config = StateConfiguration::instance_mut().await;
+    if let ConfigurationSource::Environment(variable) = config.source(key) {
+        return Err(CliError::Configuration(format!(
+            "{key} is set by the environment variable {variable} and cannot be changed here."
+        )));
+    }
+    if key == "password" {
+        check_password_strength(value).map_err(CliError::Configuration)?;
+        config.password =
+            hash_password(value).map_err(|e| CliError::Configuration(e.to_string()))?;
+        config.session_generation += 1;
+    } else {
+        config.set(key, value).map_err(CliError::Configuration)?;
+    }
+    config.commit();
+    info!("{} changed", key);
+    Ok(())
+}
+
+async fn execute(command: Command) -> Result<(), CliError> {
+    match command {
+        Command::Serve(options) => {
+            apply_server_options(&options, &mut *StateConfiguration::instance_mut().await)
+                .map_err(CliError::Usage)?;
+            info!("initializing router...");
+            server::serve().await?
+        }
+        Command::Start { pull: true } => cron::start_and_update_containers::run(OWNER).await?,
+        Command::Start { pull: false } => cron::start_containers::run(OWNER).await?,
+        Command::Stop => cron::stop_containers::run(OWNER).await?,
+        Command::Backup => cron::create_backup::run(OWNER).await?,
+        Command::CheckBackup => cron::check_backup::run(OWNER).await?,
+        Command::Status => {
+            for (container, state) in DockerController::status().await? {
+                println!("{:<40} {}", container.identifier, state);
+            }
+            if let Some(operation) = lock::current() {
+                println!(
+                    "Running operation: {} started to {} at {}",
+                    operation.owner,
+                    operation.operation,
+                    operation.started()
+                );
+            }
+        }
+        Command::Logs(id) => {
+            if !id.starts_with("nextcloud-aio-") {
+                return Err(CliError::Usage(format!("{} is not an AIO container.", id)));
+            }
+            print!("{}", DockerController::logs(&id).await?);
+        }
+        Command::ConfigGet(key) => config_get(key).await?,
+        Command::ConfigSet(key, value) => config_set(&key, &value).await?,
+        Command::Cron(job) => cron::run(&job).await.ok_or_else(|| {
+            CliError::Usage(format!(
+                "There is no job called {}. Available jobs: {}.",
+                job,
+                cron::JOBS.join(", ")
+            ))
+        })??,
+        Command::Unlock => match lock::release() {
+            Some(lock) => info!(
+                "Removed the lock of {}, who started to {} at {}",
+                lock.owner,
+                lock.operation,
+                lock.started()
+            ),
+            None => info!("No operation is running"),
+        },
+        Command::ExportConfiguration(file) => export_configuration(&file)?,
+        Command::ImportConfiguration(file) => import_configuration(&file)?,
+        Command::Help => println!("{}", usage()),
+    }
+    Ok(())
+}
+
+pub async fn run(args: &[String]) -> ExitCode {
+    let (options, command) = match parse(args) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, usage());
+            return ExitCode::from(2);
+        }
+    };
+    // Before anything is read, as the configuration and the container definitions are loaded once.
+    if let Some(directory) = options.data_dir {
+        set_home_directory(directory);
+    }
+    if let Some(file) = options.containers_file {
+        set_containers_file(file);
+    }
+    if let Some(socket) = options.docker_socket {
+        StateConfiguration::instance_mut().await.docker_socket_path = socket;
+    }
+
+    match execute(command).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e @ CliError::Usage(_)) => {
+            eprintln!("{}\n\n{}", e, usage());
+            ExitCode::from(2)
+        }
+        Err(e) => {
+            error!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::data_const::use_test_home;
+
+    fn args(command: &str) -> Vec<String> {
+        std::iter::once("nextcloud_all_in_one")
+            .chain(command.split(' '))
+            .map(String::from)
+            .collect()
+    }
+
+    #[test]
+    fn config_commands_are_parsed() {
+        let (_, command) = parse(&args("config get timezone")).unwrap();
+        assert!(matches!(command, Command::ConfigGet(Some(key)) if key == "timezone"));
+        let (_, command) = parse(&args("config get")).unwrap();
+        assert!(matches!(command, Command::ConfigGet(None)));
+        let (options, command) =
+            parse(&args("config set --data-dir /srv/aio timezone UTC")).unwrap();
+        assert_eq!(options.data_dir, Some(PathBuf::from("/srv/aio")));
+        assert!(
+            matches!(command, Command::ConfigSet(key, value) if key == "timezone" && value == "UTC")
+        );
+        assert!(matches!(
+            parse(&args("config set timezone")),
+            Err(CliError::Usage(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn config_set_refuses_invalid_values() {
+        use_test_home();
+        let value = |key: &'static str| async move {
+            StateConfiguration::instance_ref().await.get(key).unwrap()
+        };
+
+        let code = run(&args("config set nextcloud_upload_limit 2G")).await;
+        assert_eq!(code, ExitCode::SUCCESS);
+        assert_eq!(
+            value("nextcloud_upload_limit").await,
+            Some(Value::from("2G"))
+        );
+
+        for command in [
+            "config set nextcloud_upload_limit 2X",
+            "config set nextcloud_upload_limit 10K",
+            "config set no_such_key 1",
+            "config set password_word_list eff_short_missing",
+            "config get no_such_key",
+        ] {
+            assert_eq!(run(&args(command)).await, ExitCode::FAILURE, "{command}");
+        }
+        assert_eq!(
+            value("nextcloud_upload_limit").await,
+            Some(Value::from("2G"))
+        );
+        assert_eq!(
+            run(&args("config get nextcloud_upload_limit")).await,
+            ExitCode::SUCCESS
+        );
+        assert_eq!(run(&args("config get")).await, ExitCode::SUCCESS);
+    }
+}
@@ -0,0 +1,243 @@
+use serde::{Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+use std::str::FromStr;
+use time_tz::{timezones, TimeZone};
+
+// Dictionaries shipped with the Collabora container, see
+// https://git.libreoffice.org/dictionaries/+/refs/heads/master
+const COLLABORA_DICTIONARIES: &[&str] = &[
+    "af_ZA", "an_ES", "ar", "be_BY", "bg_BG", "bn_BD", "bo", "br_FR", "bs_BA", "ca", "ckb",
+    "cs_CZ", "da_DK", "de_AT", "de_CH", "de_DE", "el_GR", "en_AU", "en_CA", "en_GB", "en_NZ",
+    "en_US", "en_ZA", "eo", "es", "es_ES", "et_EE", "fa_IR", "fr_FR", "gd_GB", "gl_ES", "gu_IN",
+    "gug", "he_IL", "hi_IN", "hr_HR", "hu_HU", "id", "is", "it", "ko_KR", "lo_LA", "lt_LT",
+    "lv_LV", "mn_MN", "mr_IN", "nb_NO", "ne_NP", "nl", "nl_NL", "nn_NO", "no", "oc_FR", "pl_PL",
+    "pt_BR", "pt_PT", "ro", "ro_RO", "ru", "ru_RU", "si_LK", "sk_SK", "sl_SI", "sq_AL", "sr",
+    "sr_Latn", "sv_FI", "sv_SE", "sw_TZ", "te_IN", "th_TH", "tr_TR", "uk_UA", "vi",
+];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Timezone(String);
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct CollaboraDictionaries(Vec<String>);
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum LocaleError {
+    InvalidTimezone(String, Vec<String>),
+    InvalidDictionaries(Vec<(String, Vec<String>)>),
+}
+
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+fn suggestions<'a>(value: &str, candidates: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let prefix = format!("{}_", value.to_lowercase());
+    let scored: Vec<(usize, &str)> = candidates
+        .map(|candidate| {
+            if candidate.to_lowercase().starts_with(&prefix) {
+                (0, candidate)
+            } else {
+                (distance(value, candidate), candidate)
+            }
+        })
+        .collect();
+    let max = (value.chars().count() / 3).max(1);
+    let best = scored
+        .iter()
+        .map(|(distance, _)| *distance)
+        .min()
+        .filter(|best| *best <= max);
+    let mut found: Vec<&str> = scored
+        .into_iter()
+        .filter(|(distance, _)| Some(*distance) == best)
+        .map(|(_, candidate)| candidate)
+        .collect();
+    found.sort();
+    found.into_iter().take(3).map(String::from).collect()
+}
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        String::new()
+    } else {
+        format!(" Did you mean {}?", suggestions.join(" or "))
+    }
+}
+
+impl CollaboraDictionaries {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = LocaleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match timezones::get_by_name(s) {
+            Some(timezone) if timezone.name() == s => Ok(Self(s.to_string())),
+            _ => Err(LocaleError::InvalidTimezone(
+                s.to_string(),
+                suggestions(s, timezones::iter().map(|timezone| timezone.name())),
+            )),
+        }
+    }
+}
+
+impl FromStr for CollaboraDictionaries {
+    type Err = LocaleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let dictionaries: Vec<String> = s.split_whitespace().map(String::from).collect();
+        let invalid: Vec<(String, Vec<String>)> = dictionaries
+            .iter()
+            .filter(|dictionary| !COLLABORA_DICTIONARIES.contains(&dictionary.as_str()))
+            .map(|dictionary| {
+                (
+                    dictionary.clone(),
+                    suggestions(dictionary, COLLABORA_DICTIONARIES.iter().copied()),
+                )
+            })
+            .collect();
+        if invalid.is_empty() {
+            Ok(Self(dictionaries))
+        } else {
+            Err(LocaleError::InvalidDictionaries(invalid))
+        }
+    }
+}
+
+impl Deref for Timezone {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Deref for CollaboraDictionaries {
+    type Target = [String];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for Timezone {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Display for CollaboraDictionaries {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join(" "))
+    }
+}
+
+impl Display for LocaleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LocaleError::InvalidTimezone(value, suggestions) => write!(
+                f,
+                "{} is not a valid timezone from the IANA time zone database.{}",
+                value,
+                did_you_mean(suggestions)
+            ),
+            LocaleError::InvalidDictionaries(invalid) => {
+                for (i, (value, suggestions)) in invalid.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " ")?;
+                    }
+                    write!(
+                        f,
+                        "{} is not a supported Collabora dictionary.{}",
+                        value,
+                        did_you_mean(suggestions)
+                    )?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Serialize for CollaboraDictionaries {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timezones_are_checked_with_suggestions() {
+        let timezone: Timezone = " Europe/Berlin ".parse().unwrap();
+        assert_eq!(timezone.to_string(), "Europe/Berlin");
+        assert_eq!(
+            "Europe/Berln".parse::<Timezone>(),
+            Err(LocaleError::InvalidTimezone(
+                String::from("Europe/Berln"),
+                vec![String::from("Europe/Berlin")]
+            ))
+        );
+        assert_eq!(
+            "Europe/Berln".parse::<Timezone>().unwrap_err().to_string(),
+            "Europe/Berln is not a valid timezone from the IANA time zone database. \
+             Did you mean Europe/Berlin?"
+        );
+        assert!("europe/berlin".parse::<Timezone>().is_err());
+        assert_eq!(
+            "Mars/Olympus_Mons".parse::<Timezone>(),
+            Err(LocaleError::InvalidTimezone(
+                String::from("Mars/Olympus_Mons"),
+                Vec::new()
+            ))
+        );
+    }
+
+    #[test]
+    fn dictionaries_are_checked_with_suggestions() {
+        let dictionaries: CollaboraDictionaries = "de_DE  en_GB nl".parse().unwrap();
+        assert_eq!(dictionaries.to_string(), "de_DE en_GB nl");
+        assert!("".parse::<CollaboraDictionaries>().unwrap().is_empty());
+
+        assert_eq!(
+            "de_DE de_DX en".parse::<CollaboraDictionaries>(),
+            Err(LocaleError::InvalidDictionaries(vec![
+                (String::from("de_DX"), vec![String::from("de_DE")]),
+                (
+                    String::from("en"),
+                    vec![
+                        String::from("en_AU"),
+                        String::from("en_CA"),
+                        String::from("en_GB")
+                    ]
+                ),
+            ]))
+        );
+        assert_eq!(
+            "xx_XX"
+                .parse::<CollaboraDictionaries>()
+                .unwrap_err()
+                .to_string(),
+            "xx_XX is not a supported Collabora dictionary."
+        );
+    }
+}
@@ -1,33 +1,44 @@
-pub use crate::configuration::models::{BackupMode, Configuration};
+use crate::auth::password_generator::{generate_password, PasswordOptions, Separator};
+use crate::auth::password_hash::{hash_password, verify_password, Verification};
+pub use crate::configuration::models::{parse_addresses, BackupMode, Configuration};
 pub use crate::configuration::read::RefConfiguration;
+pub use crate::configuration::retention::RetentionPolicy;
+pub use crate::configuration::source::{ConfigurationSource, API_WRITABLE_KEYS, KEYS, SECRET_KEYS};
 pub use crate::configuration::write::MutConfiguration;
-use std::fs;
+use crate::data::data_const::get_config_file;
+use argon2::password_hash;
 use tokio::sync::{OnceCell, RwLock};
-use tracing::error;
+use tracing::{error, info};
 
+pub mod locale;
 mod models;
+mod placeholders;
 mod read;
+pub mod retention;
+mod source;
+pub mod units;
 mod write;
 
 pub struct StateConfiguration(RwLock<Configuration>);
 
 static STATE: OnceCell<StateConfiguration> = OnceCell::const_new();
-const FILE: &str = "config.json";
 
-fn read_file() -> serde_json::Result<Configuration> {
-    match fs::read(FILE) {
-        Ok(data) => serde_json::from_slice(&data),
-        Err(data) => Err(serde_json::error::Error::io(data)),
-    }
+// Tests that change the shared AIO passphrase take turns.
+#[cfg(test)]
+pub static PASSWORD_TURN: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+fn load() -> Configuration {
+    let mut config = Configuration::read(get_config_file()).unwrap_or_else(|e| {
+        error!("Failed to read configuration file: {}", e);
+        Configuration::default()
+    });
+    config.update_from_env();
+    config
 }
 
 impl StateConfiguration {
     fn new() -> Self {
-        let config = read_file().unwrap_or_else(|e| {
-            error!("Failed to read configuration file: {}", e);
-            Configuration::default()
-        });
-        Self(RwLock::new(config))
+        Self(RwLock::new(load()))
     }
 
     async fn instance() -> &'static Self {
@@ -41,31 +52,50 @@ impl StateConfiguration {
     }
 
     pub async fn instance_mut() -> MutConfiguration {
-        MutConfiguration::new(Self::instance().await.0.write().await, FILE)
-    }
-
-    pub async fn set_password(password: String) {
-        Self::instance_mut().await.password = password;
+        MutConfiguration::new(Self::instance().await.0.write().await, get_config_file())
     }
 
-    pub async fn set_aio_token(token: String) {
-        Self::instance_mut().await.aio_token = Some(token);
+    pub async fn reload() {
+        *Self::instance().await.0.write().await = load();
     }
 
-    pub async fn clear_aio_token() {
-        Self::instance_mut().await.aio_token = None;
+    pub async fn set_password(password: &str) -> Result<(), password_hash::Error> {
+        let mut config = Self::instance_mut().await;
+        config.password = hash_password(password)?;
+        config.commit();
+        Ok(())
     }
 
     pub async fn test_password(password: &str) -> bool {
-        Self::instance_ref().await.password == password
+        let verification = verify_password(&Self::instance_ref().await.password, password);
+        match verification {
+            Verification::Valid => true,
+            Verification::ValidPlaintext => {
+                match Self::set_password(password).await {
+                    Ok(()) => info!("Migrated the plaintext AIO passphrase to a hash."),
+                    Err(e) => error!("Could not hash the AIO passphrase: {}", e),
+                }
+                true
+            }
+            Verification::Invalid => false,
+        }
     }
 
-    pub async fn test_token(token: &str) -> bool {
-        if let Some(aio_token) = Self::instance_ref().await.aio_token.as_deref() {
-            aio_token == token
-        } else {
-            false
+    // Secrets end up in environment variables and commands of the containers, so their words are
+    // never joined by spaces.
+    pub async fn get_and_generate_secret(name: &str) -> String {
+        let mut config = Self::instance_mut().await;
+        if let Some(secret) = config.secrets.get(name) {
+            return secret.clone();
+        }
+        let mut options = PasswordOptions::from(&*config);
+        if options.separator == Separator::Space {
+            options.separator = Separator::Hyphen;
         }
+        let secret = generate_password(&options);
+        config.secrets.insert(name.to_string(), secret.clone());
+        config.commit();
+        secret
     }
 
     pub async fn set_backup_mode(backup_mode: BackupMode) {
@@ -1,8 +1,23 @@
+use crate::auth::password_generator::{
+    check_word_list, Capitalization, Separator, DEFAULT_WORD_LIST,
+};
+use crate::configuration::locale::{CollaboraDictionaries, Timezone};
+use crate::configuration::retention::RetentionPolicy;
+use crate::configuration::source::{ConfigurationSource, KEYS};
+use crate::configuration::units::{MaxTime, MemoryLimit, UploadLimit};
+use ipnet::IpNet;
 use serde::{Deserialize, Serialize};
-use serde_json::{to_vec, Error, Result};
+use serde_json::{to_vec, Error, Result, Value};
+use std::collections::{HashMap, HashSet};
 use std::env::var;
+use std::fmt::Display;
 use std::fs::write;
+use std::net::{IpAddr, SocketAddr};
 use std::path::Path;
+use std::str::FromStr;
+use tracing::error;
+
+type Lookup<'a> = &'a dyn Fn(&str) -> Option<String>;
 
 mod int_bool {
     use serde::{Deserialize, Deserializer, Serializer};
@@ -14,11 +29,22 @@ mod int_bool {
         serializer.serialize_i32(if *value { 1 } else { 0 })
     }
 
+    // JSON booleans are taken as well, as clients of the API send those.
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum IntOrBool {
+        Int(i32),
+        Bool(bool),
+    }
+
     pub fn deserialize<'de, D>(deserializer: D) -> Result<bool, D::Error>
     where
         D: Deserializer<'de>,
     {
-        Deserialize::deserialize(deserializer).map(|data: i32| data == 1)
+        Deserialize::deserialize(deserializer).map(|data| match data {
+            IntOrBool::Int(data) => data == 1,
+            IntOrBool::Bool(data) => data,
+        })
     }
 }
 mod string_bool {
@@ -60,6 +86,55 @@ mod string_vec {
         })
     }
 }
+mod net_vec {
+    use ipnet::IpNet;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(value: &[IpNet], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let value: Vec<String> = value.iter().map(IpNet::to_string).collect();
+        serializer.serialize_str(value.join(" ").as_str())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<IpNet>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer).map(|data: &str| super::parse_networks(data))
+    }
+}
+mod addr_vec {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::net::SocketAddr;
+    use tracing::error;
+
+    pub fn serialize<S>(value: &[SocketAddr], serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let value: Vec<String> = value.iter().map(SocketAddr::to_string).collect();
+        serializer.serialize_str(value.join(" ").as_str())
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Vec<SocketAddr>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Deserialize::deserialize(deserializer).map(|data: &str| {
+            data.split([' ', ','])
+                .filter(|x| !x.is_empty())
+                .filter_map(|x| {
+                    super::parse_address(x)
+                        .map_err(|e| error!("Ignoring invalid listen address: {}", e))
+                        .ok()
+                })
+                .collect()
+        })
+    }
+}
+
 mod int_string {
     use serde::{Deserialize, Deserializer, Serializer};
 
@@ -78,11 +153,201 @@ mod int_string {
             .and_then(|data: &str| data.parse().map_err(serde::de::Error::custom))
     }
 }
+mod or_default {
+    use serde::{Deserialize, Deserializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+    use tracing::error;
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr + Default,
+        T::Err: Display,
+    {
+        Deserialize::deserialize(deserializer).map(|data: &str| {
+            data.parse().unwrap_or_else(|e| {
+                error!("Ignoring invalid configuration value '{}': {}", data, e);
+                T::default()
+            })
+        })
+    }
+}
+
+mod empty_none {
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::fmt::Display;
+    use std::str::FromStr;
+    use tracing::error;
+
+    pub fn serialize<S, T>(value: &Option<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+        T: Display,
+    {
+        match value {
+            Some(value) => serializer.serialize_str(value.to_string().as_str()),
+            None => serializer.serialize_str(""),
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr,
+        T::Err: Display,
+    {
+        Deserialize::deserialize(deserializer).map(|data: &str| {
+            if data.is_empty() {
+                None
+            } else {
+                data.parse()
+                    .map_err(|e| error!("Ignoring invalid configuration value '{}': {}", data, e))
+                    .ok()
+            }
+        })
+    }
+}
 
 fn not(value: &bool) -> bool {
     !*value
 }
 
+fn no_backup(value: &BackupMode) -> bool {
+    *value == BackupMode::None
+}
+
+fn is_zero(value: &u64) -> bool {
+    *value == 0
+}
+
+fn keys_of(value: &Value) -> HashSet<String> {
+    value
+        .as_object()
+        .map(|o| o.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+fn parse_or_log<T>(variable: &'static str) -> impl FnOnce(String) -> Option<T>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    move |data| {
+        data.parse()
+            .map_err(|e| error!("Ignoring invalid {}: {}", variable, e))
+            .ok()
+    }
+}
+
+fn parse_positive(variable: &'static str) -> impl FnOnce(String) -> Option<u32> {
+    move |data| match data.parse() {
+        Ok(0) | Err(_) => {
+            error!(
+                "Ignoring invalid {}: {} is not a positive number",
+                variable, data
+            );
+            None
+        }
+        Ok(value) => Some(value),
+    }
+}
+
+fn parse_network(data: &str) -> std::result::Result<IpNet, String> {
+    data.parse()
+        .or_else(|_| data.parse::<IpAddr>().map(IpNet::from))
+        .map_err(|_| format!("'{}' is not an address or a network", data))
+}
+
+// Accepts single addresses as well as CIDR ranges, separated by spaces or commas.
+fn parse_networks(data: &str) -> Vec<IpNet> {
+    data.split([' ', ','])
+        .filter(|x| !x.is_empty())
+        .filter_map(|x| {
+            parse_network(x)
+                .map_err(|e| error!("Ignoring invalid network: {}", e))
+                .ok()
+        })
+        .collect()
+}
+
+fn check<T>(value: &str) -> std::result::Result<(), String>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    value.parse::<T>().map(drop).map_err(|e| e.to_string())
+}
+
+// The file is read leniently, an invalid value there falls back to its default with an error in
+// the log. A value that is set is checked first, so that a typo is refused instead.
+fn check_value(key: &str, value: &str) -> std::result::Result<(), String> {
+    match key {
+        "nextcloud_max_time" => check::<MaxTime>(value),
+        "nextcloud_upload_limit" => check::<UploadLimit>(value),
+        "nextcloud_memory_limit" => check::<MemoryLimit>(value),
+        "borg_retention_policy" => check::<RetentionPolicy>(value),
+        "collabora_dictionaries" => check::<CollaboraDictionaries>(value),
+        "timezone" if !value.is_empty() => check::<Timezone>(value),
+        "password_separator" => check::<Separator>(value),
+        "password_capitalization" => check::<Capitalization>(value),
+        "password_word_list" => check_word_list(value).map_err(|e| e.to_string()),
+        "listen" | "tls_listen" => parse_addresses(value).map(drop),
+        "trusted_proxies" => value
+            .split([' ', ','])
+            .filter(|x| !x.is_empty())
+            .try_for_each(|x| parse_network(x).map(drop)),
+        _ => Ok(()),
+    }
+}
+
+// Accepts an address with a port, or only a port to listen on all interfaces.
+fn parse_address(data: &str) -> std::result::Result<SocketAddr, String> {
+    data.parse()
+        .or_else(|_| {
+            data.parse()
+                .map(|port| SocketAddr::from(([0, 0, 0, 0], port)))
+        })
+        .map_err(|_| format!("'{}' is not an address with a port or a port", data))
+}
+
+pub fn parse_addresses(data: &str) -> std::result::Result<Vec<SocketAddr>, String> {
+    data.split([' ', ','])
+        .filter(|x| !x.is_empty())
+        .map(parse_address)
+        .collect()
+}
+
+fn parse_addresses_or_log(
+    variable: &'static str,
+) -> impl FnOnce(String) -> Option<Vec<SocketAddr>> {
+    move |data| {
+        parse_addresses(&data)
+            .map_err(|e| error!("Ignoring invalid {}: {}", variable, e))
+            .ok()
+    }
+}
+
+#[cfg(not(feature = "development"))]
+fn default_listen() -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+    (
+        vec![SocketAddr::from(([0, 0, 0, 0], 8080))],
+        vec![SocketAddr::from(([0, 0, 0, 0], 8443))],
+    )
+}
+
+#[cfg(feature = "development")]
+fn default_listen() -> (Vec<SocketAddr>, Vec<SocketAddr>) {
+    (vec![SocketAddr::from(([127, 0, 0, 1], 3000))], Vec::new())
+}
+
+fn split_list(data: String) -> Vec<String> {
+    data.split(' ')
+        .map(String::from)
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
 #[derive(Eq, PartialEq, Default, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum BackupMode {
@@ -99,6 +364,9 @@ pub enum BackupMode {
 #[serde(default)]
 pub struct Configuration {
     pub password: String,
+    // Bumped whenever all sessions but the current one are logged out, e.g. on a passphrase change.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub session_generation: u64,
     #[cfg(not(target_arch = "arm"))]
     #[serde(
         rename = "isClamavEnabled",
@@ -175,8 +443,8 @@ pub struct Configuration {
     pub apache_port: u16,
     #[serde(with = "int_string")]
     pub talk_port: u16,
-    #[serde(with = "int_string")]
-    pub nextcloud_max_time: u16,
+    #[serde(deserialize_with = "or_default::deserialize")]
+    pub nextcloud_max_time: MaxTime,
 
     #[serde(with = "string_vec", skip_serializing_if = "Vec::is_empty")]
     pub nextcloud_additional_apks: Vec<String>,
@@ -185,9 +453,19 @@ pub struct Configuration {
     #[serde(with = "string_vec", skip_serializing_if = "Vec::is_empty")]
     pub aio_community_containers: Vec<String>,
 
-    pub nextcloud_upload_limit: String,
-    pub nextcloud_memory_limit: String,
-    pub borg_retention_policy: String,
+    #[serde(deserialize_with = "or_default::deserialize")]
+    pub nextcloud_upload_limit: UploadLimit,
+    #[serde(deserialize_with = "or_default::deserialize")]
+    pub nextcloud_memory_limit: MemoryLimit,
+    #[serde(deserialize_with = "or_default::deserialize")]
+    pub borg_retention_policy: RetentionPolicy,
+    #[serde(
+        deserialize_with = "or_default::deserialize",
+        skip_serializing_if = "CollaboraDictionaries::is_empty"
+    )]
+    pub collabora_dictionaries: CollaboraDictionaries,
+    #[serde(with = "empty_none", skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<Timezone>,
     pub docker_socket_path: String,
     pub nextcloud_datadir: String,
 
@@ -198,16 +476,53 @@ pub struct Configuration {
     pub borg_backup_host_location: Option<String>,
     #[serde(rename = "AIO_URL")]
     pub aio_url: Option<String>,
-    pub aio_token: Option<String>,
+    #[serde(with = "net_vec", skip_serializing_if = "Vec::is_empty")]
+    pub trusted_proxies: Vec<IpNet>,
+    // Requests from a trusted proxy carrying this header, e.g. Remote-User, are logged in as that user.
+    pub auth_proxy_header: Option<String>,
+    // Minutes without a request after which a session ends.
+    pub session_idle_timeout: u32,
+    // Hours after the login after which a session ends, however active it is.
+    pub session_lifetime: u32,
+    // Days after which entries are removed from the audit log.
+    pub audit_retention: u32,
+    pub password_word_list: String,
+    // Bits of entropy of generated passphrases and secrets.
+    pub password_entropy: u32,
+    #[serde(deserialize_with = "or_default::deserialize")]
+    pub password_separator: Separator,
+    #[serde(deserialize_with = "or_default::deserialize")]
+    pub password_capitalization: Capitalization,
+    // Addresses the interface is served on, without and with TLS.
+    #[serde(with = "addr_vec")]
+    pub listen: Vec<SocketAddr>,
+    #[serde(with = "addr_vec")]
+    pub tls_listen: Vec<SocketAddr>,
+    // PEM files that replace the generated self-signed certificate.
+    pub tls_certificate: Option<String>,
+    pub tls_key: Option<String>,
+    #[serde(skip_serializing_if = "no_backup")]
     pub backup_mode: BackupMode,
     pub domain: Option<String>,
     pub nextcloud_password: Option<String>,
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub secrets: HashMap<String, String>,
+
+    #[serde(skip)]
+    file_keys: HashSet<String>,
+    #[serde(skip)]
+    env_overrides: HashMap<&'static str, &'static str>,
+    // What the file held for keys the environment overrides, written back in their place.
+    #[serde(skip)]
+    file_values: HashMap<&'static str, Value>,
 }
 
 impl Default for Configuration {
     fn default() -> Self {
+        let (listen, tls_listen) = default_listen();
         Configuration {
             password: String::new(),
+            session_generation: 0,
             #[cfg(not(target_arch = "arm"))]
             is_clamav_enabled: false,
             is_docker_socket_proxy_enabled: false,
@@ -232,101 +547,402 @@ impl Default for Configuration {
             nextcloud_enable_dri_device: false,
             apache_port: 443,
             talk_port: 3478,
-            nextcloud_upload_limit: String::from("10G"),
-            nextcloud_memory_limit: String::from("512M"),
-            nextcloud_max_time: 3600,
-            borg_retention_policy: String::from(
-                "--keep-within=7d --keep-weekly=4 --keep-monthly=6",
-            ),
+            nextcloud_upload_limit: UploadLimit::default(),
+            nextcloud_memory_limit: MemoryLimit::default(),
+            nextcloud_max_time: MaxTime::default(),
+            borg_retention_policy: RetentionPolicy::default(),
+            collabora_dictionaries: CollaboraDictionaries::default(),
+            timezone: None,
             docker_socket_path: String::from("/var/run/docker.sock"),
             nextcloud_additional_apks: Vec::from([String::from("imagemagick")]),
             nextcloud_additional_php_extensions: Vec::from([String::from("imagick")]),
             borg_backup_host_location: None,
             aio_url: None,
-            aio_token: None,
+            trusted_proxies: Vec::new(),
+            auth_proxy_header: None,
+            session_idle_timeout: 30,
+            session_lifetime: 24,
+            audit_retention: 90,
+            password_word_list: String::from(DEFAULT_WORD_LIST),
+            password_entropy: 100,
+            password_separator: Separator::default(),
+            password_capitalization: Capitalization::default(),
+            listen,
+            tls_listen,
+            tls_certificate: None,
+            tls_key: None,
             backup_mode: BackupMode::None,
             domain: None,
             nextcloud_password: None,
+            secrets: HashMap::new(),
+            file_keys: HashSet::new(),
+            env_overrides: HashMap::new(),
+            file_values: HashMap::new(),
         }
     }
 }
 
 impl Configuration {
     pub fn read<P: AsRef<Path>>(path: P) -> Result<Self> {
-        serde_json::from_slice(std::fs::read(path).map_err(Error::io)?.as_slice())
+        let data = std::fs::read(path).map_err(Error::io)?;
+        let value: Value = serde_json::from_slice(data.as_slice())?;
+        let file_keys = keys_of(&value);
+        // From a reference, as some fields are read as borrowed strings.
+        let mut config = Self::deserialize(&value)?;
+        config.file_keys = file_keys;
+        Ok(config)
     }
 
-    pub fn write<P: AsRef<Path>>(&self, path: P) -> Result<()> {
-        write(path, to_vec(self)?).map_err(Error::io)
+    pub fn write<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let mut value = serde_json::to_value(&*self)?;
+        if let Some(object) = value.as_object_mut() {
+            for key in self.env_overrides.keys() {
+                match self.file_values.get(key) {
+                    Some(file_value) => object.insert(String::from(*key), file_value.clone()),
+                    None => object.remove(*key),
+                };
+            }
+        }
+        self.file_keys = keys_of(&value);
+        write(path, to_vec(&value)?).map_err(Error::io)
+    }
+
+    pub fn source(&self, key: &str) -> ConfigurationSource {
+        if let Some(variable) = self.env_overrides.get(key) {
+            ConfigurationSource::Environment(variable)
+        } else if self.file_keys.contains(key) {
+            ConfigurationSource::File
+        } else {
+            ConfigurationSource::Default
+        }
+    }
+
+    pub fn sources(&self) -> HashMap<&'static str, ConfigurationSource> {
+        KEYS.iter().map(|&key| (key, self.source(key))).collect()
+    }
+
+    // The value as stored in the configuration file, None if the key is left out.
+    pub fn get(&self, key: &str) -> std::result::Result<Option<Value>, String> {
+        if !KEYS.contains(&key) {
+            return Err(format!("There is no configuration key called {}.", key));
+        }
+        let value = serde_json::to_value(self).map_err(|e| e.to_string())?;
+        Ok(value.get(key).cloned())
+    }
+
+    // The value is taken as a string first and as JSON if that does not fit, so that both
+    // `timezone Europe/Berlin` and `audit_retention 30` work.
+    pub fn set(&mut self, key: &str, value: &str) -> std::result::Result<(), String> {
+        if !KEYS.contains(&key) {
+            return Err(format!("There is no configuration key called {}.", key));
+        }
+        check_value(key, value)
+            .map_err(|e| format!("The value '{value}' is not valid for {key}: {e}"))?;
+        let mut data = serde_json::to_value(&*self).map_err(|e| e.to_string())?;
+        data[key] = Value::String(value.to_string());
+        let invalid = || format!("The value '{value}' is not valid for {key}.");
+        // Through a string, as some fields are read as borrowed strings.
+        let mut config = match serde_json::from_str::<Self>(&data.to_string()) {
+            Ok(config) => config,
+            Err(_) => {
+                data[key] = serde_json::from_str(value).map_err(|_| invalid())?;
+                serde_json::from_str::<Self>(&data.to_string()).map_err(|_| invalid())?
+            }
+        };
+        config.file_keys = std::mem::take(&mut self.file_keys);
+        config.file_keys.insert(String::from(key));
+        config.env_overrides = std::mem::take(&mut self.env_overrides);
+        config.file_values = std::mem::take(&mut self.file_values);
+        *self = config;
+        Ok(())
+    }
+
+    pub fn env_overrides(&self) -> &HashMap<&'static str, &'static str> {
+        &self.env_overrides
+    }
+
+    fn env_override<T, F>(
+        &mut self,
+        lookup: Lookup<'_>,
+        key: &'static str,
+        variable: &'static str,
+        parse: F,
+    ) -> Option<T>
+    where
+        F: FnOnce(String) -> Option<T>,
+    {
+        let value = lookup(variable).and_then(parse);
+        if value.is_some() {
+            self.env_overrides.insert(key, variable);
+        }
+        value
     }
 
     pub fn update_from_env(&mut self) {
-        if let Ok(Ok(data)) = var("APACHE_PORT").map(|x| x.parse()) {
+        self.update_from(&|variable| var(variable).ok());
+    }
+
+    // Tests pass their own variables, the process environment is shared by all of them.
+    fn update_from(&mut self, lookup: Lookup<'_>) {
+        let file = serde_json::to_value(&*self).unwrap_or_default();
+        self.apply_env(lookup);
+        for key in self.env_overrides.keys() {
+            if let Some(value) = file.get(*key).filter(|_| self.file_keys.contains(*key)) {
+                self.file_values.insert(key, value.clone());
+            }
+        }
+    }
+
+    fn apply_env(&mut self, lookup: Lookup<'_>) {
+        if let Some(data) =
+            self.env_override(lookup, "apache_port", "APACHE_PORT", |x| x.parse().ok())
+        {
             self.apache_port = data
         }
-        if let Ok(Ok(data)) = var("TALK_PORT").map(|x| x.parse()) {
+        if let Some(data) = self.env_override(lookup, "talk_port", "TALK_PORT", |x| x.parse().ok())
+        {
             self.talk_port = data
         }
-        if let Ok(Ok(data)) = var("NEXTCLOUD_MAX_TIME").map(|x| x.parse()) {
+        if let Some(data) = self.env_override(
+            lookup,
+            "nextcloud_max_time",
+            "NEXTCLOUD_MAX_TIME",
+            parse_or_log("NEXTCLOUD_MAX_TIME"),
+        ) {
             self.nextcloud_max_time = data
         }
 
-        if let Ok(data) = var("AIO_DISABLE_BACKUP_SECTION") {
+        if let Some(data) = self.env_override(
+            lookup,
+            "disable_backup_section",
+            "AIO_DISABLE_BACKUP_SECTION",
+            Some,
+        ) {
             self.disable_backup_section = data == "true";
         }
-        if let Ok(data) = var("COLLABORA_SECCOMP_DISABLED") {
+        if let Some(data) = self.env_override(
+            lookup,
+            "collabora_seccomp_disabled",
+            "COLLABORA_SECCOMP_DISABLED",
+            Some,
+        ) {
             self.collabora_seccomp_disabled = data == "true";
         }
-        if let Ok(data) = var("NEXTCLOUD_ENABLE_DRI_DEVICE") {
+        if let Some(data) = self.env_override(
+            lookup,
+            "nextcloud_enable_dri_device",
+            "NEXTCLOUD_ENABLE_DRI_DEVICE",
+            Some,
+        ) {
             self.nextcloud_enable_dri_device = data == "true";
         }
 
-        if let Ok(data) = var("NEXTCLOUD_MOUNT") {
+        if let Some(data) = self.env_override(lookup, "nextcloud_mount", "NEXTCLOUD_MOUNT", Some) {
             self.nextcloud_mount = Some(data);
         }
-        if let Ok(data) = var("NEXTCLOUD_TRUSTED_CACERTS_DIR") {
+        if let Some(data) = self.env_override(
+            lookup,
+            "trusted_cacerts_dir",
+            "NEXTCLOUD_TRUSTED_CACERTS_DIR",
+            Some,
+        ) {
             self.trusted_cacerts_dir = Some(data);
         }
-        if let Ok(data) = var("APACHE_IP_BINDING") {
+        if let Some(data) =
+            self.env_override(lookup, "apache_ip_binding", "APACHE_IP_BINDING", Some)
+        {
             self.apache_ip_binding = Some(data);
         }
-        if let Ok(data) = var("NEXTCLOUD_KEEP_DISABLED_APPS") {
+        if let Some(data) = self.env_override(
+            lookup,
+            "nextcloud_keep_disabled_apps",
+            "NEXTCLOUD_KEEP_DISABLED_APPS",
+            Some,
+        ) {
             self.nextcloud_keep_disabled_apps = Some(data);
         }
 
-        if let Ok(data) = var("NEXTCLOUD_UPLOAD_LIMIT") {
+        if let Some(data) = self.env_override(
+            lookup,
+            "nextcloud_upload_limit",
+            "NEXTCLOUD_UPLOAD_LIMIT",
+            parse_or_log("NEXTCLOUD_UPLOAD_LIMIT"),
+        ) {
             self.nextcloud_upload_limit = data;
         }
-        if let Ok(data) = var("NEXTCLOUD_MEMORY_LIMIT") {
+        if let Some(data) = self.env_override(
+            lookup,
+            "nextcloud_memory_limit",
+            "NEXTCLOUD_MEMORY_LIMIT",
+            parse_or_log("NEXTCLOUD_MEMORY_LIMIT"),
+        ) {
             self.nextcloud_memory_limit = data;
         }
-        if let Ok(data) = var("BORG_RETENTION_POLICY") {
+        if let Some(data) = self.env_override(
+            lookup,
+            "borg_retention_policy",
+            "BORG_RETENTION_POLICY",
+            parse_or_log("BORG_RETENTION_POLICY"),
+        ) {
             self.borg_retention_policy = data;
         }
-        if let Ok(data) = var("WATCHTOWER_DOCKER_SOCKET_PATH") {
+        if let Some(data) = self.env_override(
+            lookup,
+            "docker_socket_path",
+            "WATCHTOWER_DOCKER_SOCKET_PATH",
+            Some,
+        ) {
             self.docker_socket_path = data;
         }
 
-        if let Ok(data) = var("NEXTCLOUD_ADDITIONAL_APKS") {
-            self.nextcloud_additional_apks = data
-                .split(' ')
-                .map(String::from)
-                .filter(|x| !x.is_empty())
-                .collect();
+        if let Some(data) = self.env_override(
+            lookup,
+            "nextcloud_additional_apks",
+            "NEXTCLOUD_ADDITIONAL_APKS",
+            Some,
+        ) {
+            self.nextcloud_additional_apks = split_list(data);
         }
-        if let Ok(data) = var("NEXTCLOUD_ADDITIONAL_PHP_EXTENSIONS") {
-            self.nextcloud_additional_php_extensions = data
-                .split(' ')
-                .map(String::from)
-                .filter(|x| !x.is_empty())
-                .collect();
+        if let Some(data) = self.env_override(
+            lookup,
+            "nextcloud_additional_php_extensions",
+            "NEXTCLOUD_ADDITIONAL_PHP_EXTENSIONS",
+            Some,
+        ) {
+            self.nextcloud_additional_php_extensions = split_list(data);
         }
-        if let Ok(data) = var("AIO_COMMUNITY_CONTAINERS") {
-            self.aio_community_containers = data
-                .split(' ')
-                .map(String::from)
-                .filter(|x| !x.is_empty())
-                .collect();
+        if let Some(data) = self.env_override(
+            lookup,
+            "aio_community_containers",
+            "AIO_COMMUNITY_CONTAINERS",
+            Some,
+        ) {
+            self.aio_community_containers = split_list(data);
         }
+        if let Some(data) =
+            self.env_override(lookup, "trusted_proxies", "AIO_TRUSTED_PROXIES", Some)
+        {
+            self.trusted_proxies = parse_networks(&data);
+        }
+        if let Some(data) = self.env_override(
+            lookup,
+            "session_idle_timeout",
+            "AIO_SESSION_IDLE_TIMEOUT",
+            parse_positive("AIO_SESSION_IDLE_TIMEOUT"),
+        ) {
+            self.session_idle_timeout = data;
+        }
+        if let Some(data) = self.env_override(
+            lookup,
+            "session_lifetime",
+            "AIO_SESSION_LIFETIME",
+            parse_positive("AIO_SESSION_LIFETIME"),
+        ) {
+            self.session_lifetime = data;
+        }
+        if let Some(data) = self.env_override(
+            lookup,
+            "audit_retention",
+            "AIO_AUDIT_RETENTION",
+            parse_positive("AIO_AUDIT_RETENTION"),
+        ) {
+            self.audit_retention = data;
+        }
+        if let Some(data) =
+            self.env_override(lookup, "password_word_list", "AIO_PASSWORD_WORD_LIST", Some)
+        {
+            self.password_word_list = data;
+        }
+        if let Some(data) = self.env_override(
+            lookup,
+            "password_entropy",
+            "AIO_PASSWORD_ENTROPY",
+            parse_positive("AIO_PASSWORD_ENTROPY"),
+        ) {
+            self.password_entropy = data;
+        }
+        if let Some(data) = self.env_override(
+            lookup,
+            "password_separator",
+            "AIO_PASSWORD_SEPARATOR",
+            parse_or_log("AIO_PASSWORD_SEPARATOR"),
+        ) {
+            self.password_separator = data;
+        }
+        if let Some(data) = self.env_override(
+            lookup,
+            "password_capitalization",
+            "AIO_PASSWORD_CAPITALIZATION",
+            parse_or_log("AIO_PASSWORD_CAPITALIZATION"),
+        ) {
+            self.password_capitalization = data;
+        }
+        if let Some(data) = self.env_override(
+            lookup,
+            "listen",
+            "AIO_LISTEN",
+            parse_addresses_or_log("AIO_LISTEN"),
+        ) {
+            self.listen = data;
+        }
+        if let Some(data) = self.env_override(
+            lookup,
+            "tls_listen",
+            "AIO_TLS_LISTEN",
+            parse_addresses_or_log("AIO_TLS_LISTEN"),
+        ) {
+            self.tls_listen = data;
+        }
+        if let Some(data) =
+            self.env_override(lookup, "tls_certificate", "AIO_TLS_CERTIFICATE", Some)
+        {
+            self.tls_certificate = Some(data).filter(|file| !file.is_empty());
+        }
+        if let Some(data) = self.env_override(lookup, "tls_key", "AIO_TLS_KEY", Some) {
+            self.tls_key = Some(data).filter(|file| !file.is_empty());
+        }
+        if let Some(data) =
+            self.env_override(lookup, "auth_proxy_header", "AIO_AUTH_PROXY_HEADER", Some)
+        {
+            self.auth_proxy_header = Some(data).filter(|header| !header.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn environment_overrides_are_not_written_to_the_file() {
+        let file = std::env::temp_dir().join("nextcloud-aio-environment-config.json");
+        std::fs::write(
+            &file,
+            r#"{"talk_port": "3480", "timezone": "Europe/Berlin"}"#,
+        )
+        .unwrap();
+        let environment = HashMap::from([
+            ("TALK_PORT", "3481"),
+            ("NEXTCLOUD_KEEP_DISABLED_APPS", "true"),
+            ("APACHE_PORT", "not a port"),
+        ]);
+        let mut config = Configuration::read(&file).unwrap();
+        config.update_from(&|variable| environment.get(variable).map(|v| v.to_string()));
+        assert_eq!(config.talk_port, 3481);
+        assert_eq!(config.apache_port, 443);
+        assert_eq!(config.source("apache_port"), ConfigurationSource::Default);
+        config.set("timezone", "Europe/Paris").unwrap();
+        config.write(&file).unwrap();
+
+        let written: Value = serde_json::from_slice(&std::fs::read(&file).unwrap()).unwrap();
+        assert_eq!(written["talk_port"], "3480");
+        assert_eq!(written["timezone"], "Europe/Paris");
+        assert!(written.get("nextcloud_keep_disabled_apps").is_none());
+        assert_eq!(config.talk_port, 3481);
+        assert_eq!(
+            config.source("talk_port"),
+            ConfigurationSource::Environment("TALK_PORT")
+        );
+        std::fs::remove_file(&file).unwrap();
     }
 }
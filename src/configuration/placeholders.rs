@@ -0,0 +1,134 @@
+use crate::configuration::Configuration;
+
+fn yes(value: bool) -> String {
+    String::from(if value { "yes" } else { "" })
+}
+
+impl Configuration {
+    pub fn placeholder(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "NC_DOMAIN" => self.domain.clone().unwrap_or_default(),
+            "AIO_URL" => self.aio_url.clone().unwrap_or_default(),
+            "APACHE_PORT" => self.apache_port.to_string(),
+            "APACHE_IP_BINDING" => self.apache_ip_binding.clone().unwrap_or_default(),
+            "TALK_PORT" => self.talk_port.to_string(),
+            "BORGBACKUP_HOST_LOCATION" => {
+                self.borg_backup_host_location.clone().unwrap_or_default()
+            }
+            "BORG_RETENTION_POLICY" => self.borg_retention_policy.to_string(),
+            "NEXTCLOUD_MOUNT" => self.nextcloud_mount.clone().unwrap_or_default(),
+            "NEXTCLOUD_DATADIR" => self.nextcloud_datadir.clone(),
+            "NEXTCLOUD_UPLOAD_LIMIT" => self.nextcloud_upload_limit.to_string(),
+            "NEXTCLOUD_MEMORY_LIMIT" => self.nextcloud_memory_limit.to_string(),
+            "NEXTCLOUD_MAX_TIME" => self.nextcloud_max_time.to_string(),
+            "TIMEZONE" => self.timezone.as_deref().unwrap_or_default().to_string(),
+            "COLLABORA_DICTIONARIES" => self.collabora_dictionaries.to_string(),
+            "NEXTCLOUD_TRUSTED_CACERTS_DIR" => self.trusted_cacerts_dir.clone().unwrap_or_default(),
+            "NEXTCLOUD_ADDITIONAL_APKS" => self.nextcloud_additional_apks.join(" "),
+            "NEXTCLOUD_ADDITIONAL_PHP_EXTENSIONS" => {
+                self.nextcloud_additional_php_extensions.join(" ")
+            }
+            "AIO_COMMUNITY_CONTAINERS" => self.aio_community_containers.join(" "),
+            "INSTALL_LATEST_MAJOR" => yes(self.install_latest_major),
+            #[cfg(not(target_arch = "arm"))]
+            "CLAMAV_ENABLED" => yes(self.is_clamav_enabled),
+            "COLLABORA_ENABLED" => yes(self.is_collabora_enabled),
+            "DOCKER_SOCKET_PROXY_ENABLED" => yes(self.is_docker_socket_proxy_enabled),
+            "FULLTEXTSEARCH_ENABLED" => yes(self.is_fulltextsearch_enabled),
+            "IMAGINARY_ENABLED" => yes(self.is_imaginary_enabled),
+            "ONLYOFFICE_ENABLED" => yes(self.is_onlyoffice_enabled),
+            "TALK_ENABLED" => yes(self.is_talk_enabled),
+            "TALK_RECORDING_ENABLED" => yes(self.is_talk_recording_enabled),
+            "WHITEBOARD_ENABLED" => yes(self.is_whiteboard_enabled),
+            _ => return None,
+        })
+    }
+
+    pub fn substitute_placeholders(&self, value: &str) -> String {
+        let mut result = String::new();
+        let mut rest = value;
+        while let Some(start) = rest.find('%') {
+            let (before, after) = rest.split_at(start);
+            result.push_str(before);
+            match after[1..].find('%') {
+                Some(end) => match self.placeholder(&after[1..end + 1]) {
+                    Some(replacement) => {
+                        result.push_str(&replacement);
+                        rest = &after[end + 2..];
+                    }
+                    None => {
+                        result.push('%');
+                        rest = &after[1..];
+                    }
+                },
+                None => {
+                    result.push_str(after);
+                    rest = "";
+                }
+            }
+        }
+        result.push_str(rest);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::Configuration;
+    use crate::container::models::Container;
+
+    fn configuration() -> Configuration {
+        let mut config = Configuration::default();
+        config.domain = Some(String::from("cloud.example"));
+        config.nextcloud_upload_limit = "16G".parse().unwrap();
+        config.nextcloud_memory_limit = "2g".parse().unwrap();
+        config.nextcloud_max_time = "2h".parse().unwrap();
+        config.timezone = Some("Europe/Berlin".parse().unwrap());
+        config.is_talk_enabled = true;
+        config
+    }
+
+    #[test]
+    fn typed_values_are_rendered_for_the_containers() {
+        let config = configuration();
+        assert_eq!(config.placeholder("NEXTCLOUD_UPLOAD_LIMIT").unwrap(), "16G");
+        assert_eq!(config.placeholder("NEXTCLOUD_MEMORY_LIMIT").unwrap(), "2G");
+        assert_eq!(config.placeholder("NEXTCLOUD_MAX_TIME").unwrap(), "7200");
+        assert_eq!(config.placeholder("TIMEZONE").unwrap(), "Europe/Berlin");
+        assert_eq!(config.placeholder("TALK_ENABLED").unwrap(), "yes");
+        assert_eq!(config.placeholder("COLLABORA_ENABLED").unwrap(), "");
+        assert_eq!(config.placeholder("NO_SUCH_PLACEHOLDER"), None);
+
+        let defaults = Configuration::default();
+        assert_eq!(
+            defaults.placeholder("NEXTCLOUD_UPLOAD_LIMIT").unwrap(),
+            "10G"
+        );
+        assert_eq!(defaults.placeholder("NEXTCLOUD_MAX_TIME").unwrap(), "3600");
+    }
+
+    #[test]
+    fn placeholders_are_substituted_in_environment_variables() {
+        let config = configuration();
+        let container = Container {
+            container_environment_variables: vec![
+                String::from("PHP_UPLOAD_LIMIT=%NEXTCLOUD_UPLOAD_LIMIT%"),
+                String::from("LIMITS=%NEXTCLOUD_MEMORY_LIMIT%/%NEXTCLOUD_MAX_TIME%"),
+                String::from("OVERWRITEHOST=%NC_DOMAIN%"),
+                String::from("DISCOUNT=100%"),
+                String::from("UNKNOWN=%UNKNOWN%%TIMEZONE%"),
+            ],
+            ..Container::default()
+        };
+        assert_eq!(
+            container.environment(&config),
+            [
+                "PHP_UPLOAD_LIMIT=16G",
+                "LIMITS=2G/7200",
+                "OVERWRITEHOST=cloud.example",
+                "DISCOUNT=100%",
+                "UNKNOWN=%UNKNOWN%Europe/Berlin",
+            ]
+        );
+    }
+}
@@ -0,0 +1,500 @@
+use crate::data::backup_archives::BackupArchive;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+use time::{Duration, OffsetDateTime};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IntervalUnit {
+    Hours,
+    Days,
+    Weeks,
+    Months,
+    Years,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Interval {
+    pub amount: u32,
+    pub unit: IntervalUnit,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Rule {
+    Within,
+    Secondly,
+    Minutely,
+    Hourly,
+    Daily,
+    Weekly,
+    Monthly,
+    Yearly,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RetentionPolicy {
+    pub within: Option<Interval>,
+    pub secondly: Option<u32>,
+    pub minutely: Option<u32>,
+    pub hourly: Option<u32>,
+    pub daily: Option<u32>,
+    pub weekly: Option<u32>,
+    pub monthly: Option<u32>,
+    pub yearly: Option<u32>,
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum RetentionPolicyError {
+    Empty,
+    UnknownFlag(String),
+    MissingValue(String),
+    InvalidValue(String, String),
+    Duplicate(String),
+}
+
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct KeepReason {
+    pub rule: Rule,
+    pub rank: usize,
+    pub oldest: bool,
+}
+
+pub struct PruneDecision<'a> {
+    pub archive: &'a BackupArchive,
+    pub kept_by: Option<KeepReason>,
+}
+
+impl Rule {
+    const COUNTED: [Rule; 7] = [
+        Rule::Secondly,
+        Rule::Minutely,
+        Rule::Hourly,
+        Rule::Daily,
+        Rule::Weekly,
+        Rule::Monthly,
+        Rule::Yearly,
+    ];
+
+    fn flag(&self) -> &'static str {
+        match self {
+            Rule::Within => "--keep-within",
+            Rule::Secondly => "--keep-secondly",
+            Rule::Minutely => "--keep-minutely",
+            Rule::Hourly => "--keep-hourly",
+            Rule::Daily => "--keep-daily",
+            Rule::Weekly => "--keep-weekly",
+            Rule::Monthly => "--keep-monthly",
+            Rule::Yearly => "--keep-yearly",
+        }
+    }
+
+    fn from_flag(flag: &str) -> Option<Self> {
+        match flag {
+            "--keep-within" => Some(Rule::Within),
+            // Like in borg, keeping the last archives is the same as keeping one per second.
+            "--keep-last" | "--keep-secondly" => Some(Rule::Secondly),
+            "--keep-minutely" => Some(Rule::Minutely),
+            "-H" | "--keep-hourly" => Some(Rule::Hourly),
+            "-d" | "--keep-daily" => Some(Rule::Daily),
+            "-w" | "--keep-weekly" => Some(Rule::Weekly),
+            "-m" | "--keep-monthly" => Some(Rule::Monthly),
+            "-y" | "--keep-yearly" => Some(Rule::Yearly),
+            _ => None,
+        }
+    }
+
+    // Archives that fall into the same period are considered duplicates of each other by borg.
+    // --keep-within is not counted in periods.
+    fn period(&self, time: OffsetDateTime) -> (i64, u8, u8, u8, u8, u8) {
+        let (year, month, day) = (time.year() as i64, time.month() as u8, time.day());
+        let (hour, minute, second) = (time.hour(), time.minute(), time.second());
+        match self {
+            Rule::Within | Rule::Secondly => (year, month, day, hour, minute, second),
+            Rule::Minutely => (year, month, day, hour, minute, 0),
+            Rule::Hourly => (year, month, day, hour, 0, 0),
+            Rule::Daily => (year, month, day, 0, 0, 0),
+            Rule::Weekly => {
+                let (iso_year, week, _) = time.to_iso_week_date();
+                (iso_year as i64, week, 0, 0, 0, 0)
+            }
+            Rule::Monthly => (year, month, 0, 0, 0, 0),
+            Rule::Yearly => (year, 0, 0, 0, 0, 0),
+        }
+    }
+}
+
+impl Interval {
+    pub fn duration(&self) -> Duration {
+        let amount = self.amount as i64;
+        match self.unit {
+            IntervalUnit::Hours => Duration::hours(amount),
+            IntervalUnit::Days => Duration::days(amount),
+            IntervalUnit::Weeks => Duration::weeks(amount),
+            IntervalUnit::Months => Duration::days(amount * 31),
+            IntervalUnit::Years => Duration::days(amount * 365),
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let unit = match s.chars().last() {
+            Some('H') => IntervalUnit::Hours,
+            Some('d') => IntervalUnit::Days,
+            Some('w') => IntervalUnit::Weeks,
+            Some('m') => IntervalUnit::Months,
+            Some('y') => IntervalUnit::Years,
+            _ => return Err(()),
+        };
+        match s[..s.len() - 1].parse() {
+            Ok(amount) if amount > 0 => Ok(Self { amount, unit }),
+            _ => Err(()),
+        }
+    }
+}
+
+impl Display for Interval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let unit = match self.unit {
+            IntervalUnit::Hours => 'H',
+            IntervalUnit::Days => 'd',
+            IntervalUnit::Weeks => 'w',
+            IntervalUnit::Months => 'm',
+            IntervalUnit::Years => 'y',
+        };
+        write!(f, "{}{}", self.amount, unit)
+    }
+}
+
+impl Default for RetentionPolicy {
+    fn default() -> Self {
+        RetentionPolicy {
+            within: Some(Interval {
+                amount: 7,
+                unit: IntervalUnit::Days,
+            }),
+            weekly: Some(4),
+            monthly: Some(6),
+            ..RetentionPolicy::EMPTY
+        }
+    }
+}
+
+impl RetentionPolicy {
+    const EMPTY: RetentionPolicy = RetentionPolicy {
+        within: None,
+        secondly: None,
+        minutely: None,
+        hourly: None,
+        daily: None,
+        weekly: None,
+        monthly: None,
+        yearly: None,
+    };
+
+    fn count(&self, rule: Rule) -> Option<u32> {
+        match rule {
+            Rule::Within => None,
+            Rule::Secondly => self.secondly,
+            Rule::Minutely => self.minutely,
+            Rule::Hourly => self.hourly,
+            Rule::Daily => self.daily,
+            Rule::Weekly => self.weekly,
+            Rule::Monthly => self.monthly,
+            Rule::Yearly => self.yearly,
+        }
+    }
+
+    fn count_mut(&mut self, rule: Rule) -> Option<&mut Option<u32>> {
+        match rule {
+            Rule::Within => None,
+            Rule::Secondly => Some(&mut self.secondly),
+            Rule::Minutely => Some(&mut self.minutely),
+            Rule::Hourly => Some(&mut self.hourly),
+            Rule::Daily => Some(&mut self.daily),
+            Rule::Weekly => Some(&mut self.weekly),
+            Rule::Monthly => Some(&mut self.monthly),
+            Rule::Yearly => Some(&mut self.yearly),
+        }
+    }
+
+    pub fn simulate<'a>(
+        &self,
+        archives: &'a [BackupArchive],
+        now: OffsetDateTime,
+    ) -> Vec<PruneDecision<'a>> {
+        let mut sorted: Vec<(usize, &BackupArchive)> = archives.iter().enumerate().collect();
+        sorted.sort_by_key(|&(_, archive)| std::cmp::Reverse(archive.time));
+
+        let mut kept_because: HashMap<usize, KeepReason> = HashMap::new();
+
+        if let Some(within) = self.within {
+            let target = now - within.duration();
+            let mut rank = 0;
+            for (index, archive) in &sorted {
+                if archive.time > target {
+                    rank += 1;
+                    kept_because.insert(
+                        *index,
+                        KeepReason {
+                            rule: Rule::Within,
+                            rank,
+                            oldest: false,
+                        },
+                    );
+                }
+            }
+        }
+
+        for rule in Rule::COUNTED {
+            let Some(n) = self.count(rule).map(|n| n as usize).filter(|n| *n > 0) else {
+                continue;
+            };
+            let mut last = None;
+            let mut kept = 0;
+            for (index, archive) in &sorted {
+                let period = rule.period(archive.time);
+                if last != Some(period) {
+                    last = Some(period);
+                    if !kept_because.contains_key(index) {
+                        kept += 1;
+                        kept_because.insert(
+                            *index,
+                            KeepReason {
+                                rule,
+                                rank: kept,
+                                oldest: false,
+                            },
+                        );
+                        if kept == n {
+                            break;
+                        }
+                    }
+                }
+            }
+            // Like borg, keep the oldest archive when the target retention count was not reached.
+            if let Some((index, _)) = sorted.last() {
+                if kept < n && !kept_because.contains_key(index) {
+                    kept_because.insert(
+                        *index,
+                        KeepReason {
+                            rule,
+                            rank: kept + 1,
+                            oldest: true,
+                        },
+                    );
+                }
+            }
+        }
+
+        sorted
+            .into_iter()
+            .map(|(index, archive)| PruneDecision {
+                archive,
+                kept_by: kept_because.get(&index).copied(),
+            })
+            .collect()
+    }
+}
+
+impl Display for RetentionPolicyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetentionPolicyError::Empty => write!(
+                f,
+                "At least one of the --keep-within, --keep-last, --keep-secondly, --keep-minutely, --keep-hourly, --keep-daily, --keep-weekly, --keep-monthly or --keep-yearly settings must be specified."
+            ),
+            RetentionPolicyError::UnknownFlag(flag) => {
+                write!(f, "{} is not a supported borg prune option.", flag)
+            }
+            RetentionPolicyError::MissingValue(flag) => write!(f, "{} needs a value.", flag),
+            RetentionPolicyError::InvalidValue(flag, value) => {
+                write!(f, "{} is not a valid value for {}.", value, flag)
+            }
+            RetentionPolicyError::Duplicate(flag) => {
+                write!(f, "{} was specified more than once.", flag)
+            }
+        }
+    }
+}
+
+impl FromStr for RetentionPolicy {
+    type Err = RetentionPolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut policy = RetentionPolicy::EMPTY;
+        let mut tokens = s.split_whitespace();
+        while let Some(token) = tokens.next() {
+            let (flag, value) = match token.split_once('=') {
+                Some((flag, value)) => (flag, Some(value)),
+                None => (token, None),
+            };
+            let rule = Rule::from_flag(flag)
+                .ok_or_else(|| RetentionPolicyError::UnknownFlag(flag.to_string()))?;
+            let value = value
+                .or_else(|| tokens.next())
+                .ok_or_else(|| RetentionPolicyError::MissingValue(rule.flag().to_string()))?;
+            let invalid =
+                || RetentionPolicyError::InvalidValue(rule.flag().to_string(), value.to_string());
+            let duplicate = RetentionPolicyError::Duplicate(rule.flag().to_string());
+            if let Some(count) = policy.count_mut(rule) {
+                if count.is_some() {
+                    return Err(duplicate);
+                }
+                *count = Some(value.parse().map_err(|_| invalid())?);
+            } else {
+                if policy.within.is_some() {
+                    return Err(duplicate);
+                }
+                policy.within = Some(value.parse().map_err(|_| invalid())?);
+            }
+        }
+        if policy == RetentionPolicy::EMPTY {
+            Err(RetentionPolicyError::Empty)
+        } else {
+            Ok(policy)
+        }
+    }
+}
+
+impl Display for RetentionPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let mut flags = Vec::new();
+        if let Some(within) = self.within {
+            flags.push(format!("{}={}", Rule::Within.flag(), within));
+        }
+        for rule in Rule::COUNTED {
+            if let Some(count) = self.count(rule) {
+                flags.push(format!("{}={}", rule.flag(), count));
+            }
+        }
+        write!(f, "{}", flags.join(" "))
+    }
+}
+
+impl Serialize for RetentionPolicy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.to_string().as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for RetentionPolicy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn retention_policies_are_parsed_like_borg() {
+        let policy: RetentionPolicy = "--keep-within=7d --keep-weekly=4 --keep-monthly=6"
+            .parse()
+            .unwrap();
+        assert_eq!(policy, RetentionPolicy::default());
+        assert_eq!(policy.to_string().parse::<RetentionPolicy>(), Ok(policy));
+
+        let policy: RetentionPolicy = "-d 7 -w=4  -y 1".parse().unwrap();
+        assert_eq!(
+            (policy.daily, policy.weekly, policy.yearly),
+            (Some(7), Some(4), Some(1))
+        );
+        assert_eq!(
+            policy.to_string(),
+            "--keep-daily=7 --keep-weekly=4 --keep-yearly=1"
+        );
+
+        // --keep-last is another name for --keep-secondly.
+        let policy: RetentionPolicy = "--keep-last=3".parse().unwrap();
+        assert_eq!(policy, "--keep-secondly 3".parse().unwrap());
+        assert_eq!(policy.to_string(), "--keep-secondly=3");
+
+        let errors = [
+            ("", RetentionPolicyError::Empty),
+            (
+                "--keep-forever=1",
+                RetentionPolicyError::UnknownFlag(String::from("--keep-forever")),
+            ),
+            (
+                "--keep-daily",
+                RetentionPolicyError::MissingValue(String::from("--keep-daily")),
+            ),
+            (
+                "-d x",
+                RetentionPolicyError::InvalidValue(String::from("--keep-daily"), String::from("x")),
+            ),
+            (
+                "--keep-within=0d",
+                RetentionPolicyError::InvalidValue(
+                    String::from("--keep-within"),
+                    String::from("0d"),
+                ),
+            ),
+            (
+                "--keep-last=3 --keep-secondly=2",
+                RetentionPolicyError::Duplicate(String::from("--keep-secondly")),
+            ),
+        ];
+        for (policy, error) in errors {
+            assert_eq!(policy.parse::<RetentionPolicy>(), Err(error), "{policy}");
+        }
+    }
+
+    #[test]
+    fn retention_simulation_keeps_like_borg() {
+        let now = datetime!(2024-05-10 18:00 UTC);
+        // One archive a day at noon, and a second one in the morning of the oldest day.
+        let mut archives: Vec<BackupArchive> = (0..10)
+            .map(|day| BackupArchive {
+                name: format!("day-{day}"),
+                time: now - Duration::days(day) - Duration::hours(6),
+            })
+            .collect();
+        archives.push(BackupArchive {
+            name: String::from("day-9-early"),
+            time: now - Duration::days(9) - Duration::hours(10),
+        });
+        let kept = |policy: &str| {
+            let policy: RetentionPolicy = policy.parse().unwrap();
+            policy
+                .simulate(&archives, now)
+                .into_iter()
+                .filter_map(|decision| {
+                    let reason = decision.kept_by?;
+                    Some((decision.archive.name.clone(), reason.rule, reason.oldest))
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            kept("--keep-daily=3"),
+            [
+                (String::from("day-0"), Rule::Daily, false),
+                (String::from("day-1"), Rule::Daily, false),
+                (String::from("day-2"), Rule::Daily, false),
+            ]
+        );
+
+        // Archives kept by --keep-within do not count for the other rules.
+        let within = kept("--keep-within=2d --keep-daily=1");
+        assert_eq!(within.len(), 3);
+        assert_eq!(within[2], (String::from("day-2"), Rule::Daily, false));
+
+        // Without enough periods, the oldest archive is kept as well.
+        let all = kept("-d 20");
+        assert_eq!(all.len(), 11);
+        assert_eq!(all[10], (String::from("day-9-early"), Rule::Daily, true));
+
+        // Like in borg, every archive has its own second.
+        assert_eq!(kept("--keep-last=11").len(), 11);
+    }
+}
@@ -0,0 +1,88 @@
+use serde::Serialize;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Serialize)]
+#[serde(tag = "source", content = "variable", rename_all = "kebab-case")]
+pub enum ConfigurationSource {
+    Default,
+    File,
+    Environment(&'static str),
+}
+
+// Never listed with their values.
+pub const SECRET_KEYS: &[&str] = &["password", "nextcloud_password", "secrets"];
+
+// The values that can be changed through the JSON API. Logins, proxies, listeners, TLS and host
+// paths are left out, with those a token could widen its own access or reach into the host.
+pub const API_WRITABLE_KEYS: &[&str] = &[
+    "isClamavEnabled",
+    "isWhiteboardEnabled",
+    "isImaginaryEnabled",
+    "isFulltextsearchEnabled",
+    "isOnlyofficeEnabled",
+    "isCollaboraEnabled",
+    "isTalkEnabled",
+    "isTalkRecordingEnabled",
+    "install_latest_major",
+    "nextcloud_max_time",
+    "nextcloud_upload_limit",
+    "nextcloud_memory_limit",
+    "borg_retention_policy",
+    "collabora_dictionaries",
+    "timezone",
+];
+
+pub const KEYS: &[&str] = &[
+    "password",
+    "session_generation",
+    "isClamavEnabled",
+    "isDockerSocketProxyEnabled",
+    "isWhiteboardEnabled",
+    "isImaginaryEnabled",
+    "isFulltextsearchEnabled",
+    "isOnlyofficeEnabled",
+    "isCollaboraEnabled",
+    "isTalkEnabled",
+    "isTalkRecordingEnabled",
+    "wasStartButtonClicked",
+    "install_latest_major",
+    "instance_restore_attempt",
+    "collabora_seccomp_disabled",
+    "disable_backup_section",
+    "nextcloud_enable_dri_device",
+    "apache_port",
+    "talk_port",
+    "nextcloud_max_time",
+    "nextcloud_additional_apks",
+    "nextcloud_additional_php_extensions",
+    "aio_community_containers",
+    "nextcloud_upload_limit",
+    "nextcloud_memory_limit",
+    "borg_retention_policy",
+    "collabora_dictionaries",
+    "timezone",
+    "docker_socket_path",
+    "nextcloud_datadir",
+    "nextcloud_mount",
+    "trusted_cacerts_dir",
+    "apache_ip_binding",
+    "nextcloud_keep_disabled_apps",
+    "borg_backup_host_location",
+    "AIO_URL",
+    "trusted_proxies",
+    "auth_proxy_header",
+    "session_idle_timeout",
+    "session_lifetime",
+    "audit_retention",
+    "password_word_list",
+    "password_entropy",
+    "password_separator",
+    "password_capitalization",
+    "listen",
+    "tls_listen",
+    "tls_certificate",
+    "tls_key",
+    "backup_mode",
+    "domain",
+    "nextcloud_password",
+    "secrets",
+];
@@ -0,0 +1,323 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt::{Display, Formatter};
+use std::ops::Deref;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ByteUnit {
+    Bytes,
+    Kilobytes,
+    Megabytes,
+    Gigabytes,
+    Terabytes,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct ByteSize {
+    pub amount: u64,
+    pub unit: ByteUnit,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct Seconds(pub u64);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct UploadLimit(ByteSize);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MemoryLimit(ByteSize);
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct MaxTime(Seconds);
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum UnitError {
+    InvalidSize(String),
+    InvalidDuration(String),
+    TooSmall(&'static str, String),
+}
+
+impl ByteUnit {
+    fn factor(&self) -> u64 {
+        match self {
+            ByteUnit::Bytes => 1,
+            ByteUnit::Kilobytes => 1 << 10,
+            ByteUnit::Megabytes => 1 << 20,
+            ByteUnit::Gigabytes => 1 << 30,
+            ByteUnit::Terabytes => 1 << 40,
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            ByteUnit::Bytes => "",
+            ByteUnit::Kilobytes => "K",
+            ByteUnit::Megabytes => "M",
+            ByteUnit::Gigabytes => "G",
+            ByteUnit::Terabytes => "T",
+        }
+    }
+}
+
+impl ByteSize {
+    pub const fn new(amount: u64, unit: ByteUnit) -> Self {
+        Self { amount, unit }
+    }
+
+    pub fn bytes(&self) -> u64 {
+        self.amount.saturating_mul(self.unit.factor())
+    }
+}
+
+// Same shorthand notation as PHP ini values, e.g. "512M" or "10G".
+impl FromStr for ByteSize {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (amount, unit) = match s.char_indices().last() {
+            Some((i, 'k' | 'K')) => (&s[..i], ByteUnit::Kilobytes),
+            Some((i, 'm' | 'M')) => (&s[..i], ByteUnit::Megabytes),
+            Some((i, 'g' | 'G')) => (&s[..i], ByteUnit::Gigabytes),
+            Some((i, 't' | 'T')) => (&s[..i], ByteUnit::Terabytes),
+            _ => (s, ByteUnit::Bytes),
+        };
+        amount
+            .parse()
+            .map(|amount| Self { amount, unit })
+            .map_err(|_| UnitError::InvalidSize(s.to_string()))
+    }
+}
+
+impl Display for ByteSize {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.amount, self.unit.suffix())
+    }
+}
+
+// Accepts plain seconds as well as a single unit suffix, e.g. "3600", "90m", "12h" or "2d".
+impl FromStr for Seconds {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (amount, factor) = match s.char_indices().last() {
+            Some((i, 's')) => (&s[..i], 1),
+            Some((i, 'm')) => (&s[..i], 60),
+            Some((i, 'h')) => (&s[..i], 60 * 60),
+            Some((i, 'd')) => (&s[..i], 24 * 60 * 60),
+            _ => (s, 1),
+        };
+        amount
+            .parse::<u64>()
+            .ok()
+            .and_then(|amount| amount.checked_mul(factor))
+            .map(Self)
+            .ok_or_else(|| UnitError::InvalidDuration(s.to_string()))
+    }
+}
+
+impl Display for Seconds {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl UploadLimit {
+    const MIN: ByteSize = ByteSize::new(1, ByteUnit::Megabytes);
+}
+
+impl MemoryLimit {
+    const MIN: ByteSize = ByteSize::new(512, ByteUnit::Megabytes);
+}
+
+impl MaxTime {
+    const MIN: Seconds = Seconds(30);
+}
+
+impl Default for UploadLimit {
+    fn default() -> Self {
+        Self(ByteSize::new(10, ByteUnit::Gigabytes))
+    }
+}
+
+impl Default for MemoryLimit {
+    fn default() -> Self {
+        Self(Self::MIN)
+    }
+}
+
+impl Default for MaxTime {
+    fn default() -> Self {
+        Self(Seconds(3600))
+    }
+}
+
+impl FromStr for UploadLimit {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size: ByteSize = s.parse()?;
+        if size.bytes() < Self::MIN.bytes() {
+            Err(UnitError::TooSmall("upload limit", Self::MIN.to_string()))
+        } else {
+            Ok(Self(size))
+        }
+    }
+}
+
+impl FromStr for MemoryLimit {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let size: ByteSize = s.parse()?;
+        if size.bytes() < Self::MIN.bytes() {
+            Err(UnitError::TooSmall("memory limit", Self::MIN.to_string()))
+        } else {
+            Ok(Self(size))
+        }
+    }
+}
+
+impl FromStr for MaxTime {
+    type Err = UnitError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let time: Seconds = s.parse()?;
+        if time < Self::MIN {
+            Err(UnitError::TooSmall(
+                "timeout",
+                format!("{} seconds", Self::MIN),
+            ))
+        } else {
+            Ok(Self(time))
+        }
+    }
+}
+
+impl Deref for UploadLimit {
+    type Target = ByteSize;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Deref for MemoryLimit {
+    type Target = ByteSize;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Deref for MaxTime {
+    type Target = Seconds;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Display for UploadLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Display for MemoryLimit {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Display for MaxTime {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl Display for UnitError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnitError::InvalidSize(value) => write!(
+                f,
+                "{} is not a valid size. Use a number followed by K, M, G or T, e.g. 512M.",
+                value
+            ),
+            UnitError::InvalidDuration(value) => write!(
+                f,
+                "{} is not a valid duration. Use a number of seconds or a number followed by s, m, h or d.",
+                value
+            ),
+            UnitError::TooSmall(what, min) => write!(f, "The {} must be at least {}.", what, min),
+        }
+    }
+}
+
+macro_rules! string_serde {
+    ($($t:ty),*) => {$(
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.to_string().as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                String::deserialize(deserializer)?
+                    .parse()
+                    .map_err(serde::de::Error::custom)
+            }
+        }
+    )*};
+}
+
+string_serde!(UploadLimit, MemoryLimit, MaxTime);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sizes_and_durations_are_parsed_with_limits() {
+        let size: ByteSize = " 512m ".parse().unwrap();
+        assert_eq!(size, ByteSize::new(512, ByteUnit::Megabytes));
+        assert_eq!(size.bytes(), 512 << 20);
+        assert_eq!(size.to_string(), "512M");
+        assert_eq!("2048".parse::<ByteSize>().unwrap().bytes(), 2048);
+        for invalid in ["", "M", "12X", "-1G", "1.5G"] {
+            assert!(invalid.parse::<ByteSize>().is_err(), "{invalid}");
+        }
+
+        assert_eq!("90m".parse::<Seconds>(), Ok(Seconds(5400)));
+        assert_eq!("2d".parse::<Seconds>(), Ok(Seconds(172800)));
+        assert_eq!("45".parse::<Seconds>(), Ok(Seconds(45)));
+        for invalid in [
+            "",
+            "h",
+            "1w",
+            "99999999999999999999d",
+            "1844674407370955161m",
+        ] {
+            assert!(invalid.parse::<Seconds>().is_err(), "{invalid}");
+        }
+
+        assert_eq!("1M".parse::<UploadLimit>().unwrap().to_string(), "1M");
+        assert_eq!(
+            "1023K".parse::<UploadLimit>(),
+            Err(UnitError::TooSmall("upload limit", String::from("1M")))
+        );
+        assert!("512M".parse::<MemoryLimit>().is_ok());
+        assert!("1G".parse::<MemoryLimit>().is_ok());
+        assert!("511M".parse::<MemoryLimit>().is_err());
+        assert_eq!("30".parse::<MaxTime>().unwrap().to_string(), "30");
+        assert!("29s".parse::<MaxTime>().is_err());
+        assert_eq!(*"1h".parse::<MaxTime>().unwrap(), Seconds(3600));
+
+        // The configuration file holds the same notation.
+        let limit: UploadLimit = serde_json::from_str("\"16G\"").unwrap();
+        assert_eq!(serde_json::to_string(&limit).unwrap(), "\"16G\"");
+        assert!(serde_json::from_str::<MemoryLimit>("\"1M\"").is_err());
+    }
+}
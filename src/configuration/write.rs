@@ -1,17 +1,18 @@
 use crate::configuration::Configuration;
 use std::ops::{Deref, DerefMut};
+use std::path::PathBuf;
 use tokio::sync::RwLockWriteGuard;
 use tracing::error;
 
-pub struct MutConfiguration(RwLockWriteGuard<'static, Configuration>, &'static str);
+pub struct MutConfiguration(RwLockWriteGuard<'static, Configuration>, PathBuf);
 
 impl MutConfiguration {
-    pub fn new(inner: RwLockWriteGuard<'static, Configuration>, file: &'static str) -> Self {
+    pub fn new(inner: RwLockWriteGuard<'static, Configuration>, file: PathBuf) -> Self {
         Self(inner, file)
     }
 
-    pub fn commit(self) {
-        if let Err(e) = self.0.write(self.1) {
+    pub fn commit(mut self) {
+        if let Err(e) = self.0.write(&self.1) {
             error!("Error writing config file: {}", e)
         }
     }
@@ -1,18 +1,24 @@
 use crate::configuration::{BackupMode, MutConfiguration, StateConfiguration};
 use crate::container::definition::ContainerDefinition;
+use crate::container::jobs::{self, JobKind, Phase, Step};
+use crate::container::lock::{self, LockError, LockGuard};
+use crate::container::models::Container;
 use crate::docker_client::{
     connect_container_to_network, create_container, get_databasecontainer_exit_code, DockerClient,
     Result,
 };
+use serde::Serialize;
+use std::fmt::{Display, Formatter};
 use tracing::error;
 
 const TOP_CONTAINER: &str = "nextcloud-aio-apache";
-const BACKUP_CONTAINER: &str = "nextcloud-aio-borgbackup";
+pub(crate) const BACKUP_CONTAINER: &str = "nextcloud-aio-borgbackup";
 const DOMAINCHECK_CONTAINER: &str = "nextcloud-aio-domaincheck";
 const DATABASE_CONTAINER: &str = "nextcloud-aio-database";
 const WATCHTOWER_CONTAINER: &str = "nextcloud-aio-watchtower";
 
-#[derive(Eq, PartialEq)]
+#[derive(Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
 pub enum ContainerState {
     ImageDoesNotExist,
     NotRestarting,
@@ -21,12 +27,39 @@ pub enum ContainerState {
     Starting,
     Stopped,
 }
+impl Display for ContainerState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ContainerState::ImageDoesNotExist => "does not exist",
+            ContainerState::NotRestarting => "not restarting",
+            ContainerState::Restarting => "restarting",
+            ContainerState::Running => "running",
+            ContainerState::Starting => "starting",
+            ContainerState::Stopped => "stopped",
+        })
+    }
+}
+
 #[derive(Eq, PartialEq)]
 pub enum VersionState {
     Different,
     Equal,
 }
 
+#[derive(Clone, Serialize)]
+pub struct ContainerUpdate {
+    pub id: String,
+    pub image: String,
+    pub update_available: bool,
+}
+
+#[derive(Clone, Serialize)]
+pub struct UpdateReport {
+    pub channel: String,
+    pub nextcloud_image_outdated: bool,
+    pub containers: Vec<ContainerUpdate>,
+}
+
 pub struct DockerController {
     client: DockerClient,
     definition: &'static ContainerDefinition,
@@ -41,33 +74,50 @@ impl DockerController {
     }
 
     async fn recursive_start(&self, id: &str, pull_image: bool) -> Result<()> {
+        jobs::phase(Phase::Starting).await;
         for c in self.definition.dependency_list(id).iter().rev() {
+            if jobs::is_cancelled().await {
+                return Ok(());
+            }
             match self.client.container_get_running_state(&c).await? {
                 ContainerState::ImageDoesNotExist => {
-                    error!("Not starting {} because it does not exist.", c.identifier)
+                    jobs::skip(
+                        &c.identifier,
+                        format!("Not starting {} because it does not exist.", c.identifier),
+                    )
+                    .await
                 }
                 ContainerState::Stopped => {
                     if id == DATABASE_CONTAINER && get_databasecontainer_exit_code() > 0 {
-                        error!("Not pulling the latest database image because the container was not correctly shut down.");
+                        jobs::skip(&c.identifier, String::from("Not pulling the latest database image because the container was not correctly shut down.")).await;
                     } else {
                         if pull_image && !self.client.repository_is_reachable(&c).await {
-                            error!("Not pulling the image for the {} container because docker hub does not seem to be reachable.", c.container_name);
+                            jobs::skip(&c.identifier, format!("Not pulling the image for the {} container because docker hub does not seem to be reachable.", c.container_name)).await;
                         } else {
                             self.client.container_delete(&c).await?;
                             self.client.volumes_create(c.volumes.as_slice()).await?;
                             if pull_image {
+                                jobs::progress(&c.identifier, Step::Pulling).await;
                                 self.client.image_pull(&c).await?;
                             }
+                            jobs::progress(&c.identifier, Step::Starting).await;
                             create_container(&c);
                             self.client.container_start(&c.identifier).await?;
                             connect_container_to_network(&c);
+                            jobs::progress(&c.identifier, Step::Started).await;
                         }
                     }
                 }
-                _ => error!(
-                    "Not starting {} because it was already started.",
-                    c.identifier
-                ),
+                _ => {
+                    jobs::skip(
+                        &c.identifier,
+                        format!(
+                            "Not starting {} because it was already started.",
+                            c.identifier
+                        ),
+                    )
+                    .await
+                }
             }
         }
         Ok(())
@@ -104,16 +154,34 @@ impl DockerController {
     }
 
     async fn recursive_stop(&self, id: &str) -> Result<()> {
+        jobs::phase(Phase::Stopping).await;
         for c in self.definition.dependency_list(id) {
+            if jobs::is_cancelled().await {
+                return Ok(());
+            }
             match self.client.container_get_running_state(c).await? {
                 ContainerState::ImageDoesNotExist => {
-                    error!("Not stopping {} because it does not exist.", c.identifier)
+                    jobs::skip(
+                        &c.identifier,
+                        format!("Not stopping {} because it does not exist.", c.identifier),
+                    )
+                    .await
+                }
+                ContainerState::Stopped => {
+                    jobs::skip(
+                        &c.identifier,
+                        format!(
+                            "Not stopping {} because it was already stopped.",
+                            c.identifier
+                        ),
+                    )
+                    .await
+                }
+                _ => {
+                    jobs::progress(&c.identifier, Step::Stopping).await;
+                    self.client.container_stop(c).await?;
+                    jobs::progress(&c.identifier, Step::Stopped).await;
                 }
-                ContainerState::Stopped => error!(
-                    "Not stopping {} because it was already stopped.",
-                    c.identifier
-                ),
-                _ => self.client.container_stop(c).await?,
             }
         }
         Ok(())
@@ -216,7 +284,13 @@ impl DockerController {
         }
     }
 
-    pub async fn check_backup(mut config: MutConfiguration) -> Result<()> {
+    // Every operation that stops or starts containers takes the lock first, which is held until the
+    // operation returns.
+    pub fn lock(operation: JobKind, owner: &str) -> std::result::Result<LockGuard, LockError> {
+        lock::acquire(operation, owner)
+    }
+
+    pub async fn check_backup(_lock: LockGuard, mut config: MutConfiguration) -> Result<()> {
         config.backup_mode = BackupMode::Check;
         config.commit();
         Self::new()
@@ -225,15 +299,18 @@ impl DockerController {
             .await
     }
 
-    pub async fn start_watchtower() -> Result<()> {
+    pub async fn start_watchtower(_lock: LockGuard) -> Result<()> {
         Self::new()
             .await?
             .recursive_start(WATCHTOWER_CONTAINER, true)
             .await
     }
 
-    pub async fn start_top_container(mut config: MutConfiguration, pull_image: bool) -> Result<()> {
-        config.aio_token = Some(hex::encode(rand::random::<[u8; 24]>()));
+    pub async fn start_top_container(
+        _lock: LockGuard,
+        config: MutConfiguration,
+        pull_image: bool,
+    ) -> Result<()> {
         config.commit();
         Self::new()
             .await?
@@ -241,7 +318,7 @@ impl DockerController {
             .await
     }
 
-    pub async fn start_backup(mut config: MutConfiguration) -> Result<()> {
+    pub async fn start_backup(_lock: LockGuard, mut config: MutConfiguration) -> Result<()> {
         config.backup_mode = BackupMode::Backup;
         config.commit();
         Self::new()
@@ -259,15 +336,63 @@ impl DockerController {
         //     }
     }
 
-    pub async fn stop_top_container() -> Result<()> {
+    pub async fn stop_top_container(_lock: LockGuard) -> Result<()> {
         Self::new().await?.recursive_stop(TOP_CONTAINER).await
     }
 
+    pub async fn status() -> Result<Vec<(&'static Container, ContainerState)>> {
+        let controller = Self::new().await?;
+        let mut status = Vec::new();
+        for c in controller.definition.all() {
+            status.push((c, controller.client.container_get_running_state(c).await?));
+        }
+        Ok(status)
+    }
+
+    // Containers that were never created are left out, they get the latest image when started.
+    pub async fn update_report() -> Result<UpdateReport> {
+        let controller = Self::new().await?;
+        let mut containers = Vec::new();
+        for c in controller.definition.all() {
+            if controller.client.container_get_running_state(c).await?
+                == ContainerState::ImageDoesNotExist
+            {
+                continue;
+            }
+            let image = controller.client.build_image_name(c).await;
+            let (name, tag) = image.rsplit_once(':').unwrap_or((&image, "latest"));
+            let latest = controller
+                .client
+                .get_latest_digest_of_tag(name, tag)
+                .await?;
+            let update_available = !controller
+                .client
+                .image_digests(&image)
+                .await?
+                .iter()
+                .any(|digest| digest.ends_with(&latest));
+            containers.push(ContainerUpdate {
+                id: c.identifier.clone(),
+                image,
+                update_available,
+            });
+        }
+        Ok(UpdateReport {
+            channel: controller.client.get_current_channel().await?,
+            nextcloud_image_outdated: controller.client.is_nextcloud_image_outdated().await,
+            containers,
+        })
+    }
+
+    pub async fn logs(id: &str) -> Result<String> {
+        Self::new().await?.client.container_logs(id).await
+    }
+
     pub async fn is_login_allowed() -> Result<bool> {
         Self::new().await?.is_container_running(TOP_CONTAINER).await
     }
 
-    pub async fn repair_backup(mut config: MutConfiguration) -> Result<()> {
+    pub async fn repair_backup(_lock: LockGuard, mut config: MutConfiguration) -> Result<()> {
         config.backup_mode = BackupMode::CheckRepair;
         config.commit();
         Self::new()
@@ -279,7 +404,7 @@ impl DockerController {
         config.commit();
         Ok(())
     }
-    pub async fn test_backup(mut config: MutConfiguration) -> Result<()> {
+    pub async fn test_backup(_lock: LockGuard, mut config: MutConfiguration) -> Result<()> {
         config.backup_mode = BackupMode::Test;
         config.commit();
         Self::new()
@@ -52,6 +52,12 @@ impl ContainerDefinition {
         self.0.get(id)
     }
 
+    pub fn all(&self) -> Vec<&Container> {
+        let mut containers: Vec<&Container> = self.0.values().collect();
+        containers.sort_by(|a, b| a.identifier.cmp(&b.identifier));
+        containers
+    }
+
     pub fn dependency_list(&self, id: &str) -> Vec<&Container> {
         let mut acc = Vec::new();
         let mut done = HashSet::new();
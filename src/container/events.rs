@@ -0,0 +1,183 @@
+use crate::configuration::{BackupMode, StateConfiguration};
+use crate::container::controller::{
+    ContainerState, DockerController, UpdateReport, BACKUP_CONTAINER,
+};
+use crate::container::jobs::Job;
+use crate::docker_client::{DockerClient, Result};
+use bollard_stubs::models::EventMessage;
+use reqwest::Response;
+use serde::Serialize;
+use std::sync::{Once, OnceLock};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{error, warn};
+
+// Clients that fall further behind than this are told to load the page again.
+const CAPACITY: usize = 256;
+// Docker closes the event stream when it restarts, it is followed again after this.
+const RECONNECT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Serialize)]
+pub struct ContainerStatus {
+    pub id: String,
+    pub state: ContainerState,
+}
+
+#[derive(Clone, Serialize)]
+pub struct BackupStatus {
+    pub running: bool,
+    pub mode: Option<BackupMode>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Clone, Serialize)]
+#[serde(untagged)]
+pub enum Event {
+    Container(ContainerStatus),
+    Job(Job),
+    Backup(BackupStatus),
+    Updates(UpdateReport),
+}
+
+impl Event {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Container(_) => "container",
+            Event::Job(_) => "job",
+            Event::Backup(_) => "backup",
+            Event::Updates(_) => "updates",
+        }
+    }
+}
+
+static CHANNEL: OnceLock<broadcast::Sender<Event>> = OnceLock::new();
+
+fn channel() -> &'static broadcast::Sender<Event> {
+    CHANNEL.get_or_init(|| broadcast::channel(CAPACITY).0)
+}
+
+// Without subscribers the event is dropped.
+pub fn publish(event: Event) {
+    let _ = channel().send(event);
+}
+
+// The Docker events are followed from the first subscriber on, for as long as the process runs.
+pub fn subscribe() -> broadcast::Receiver<Event> {
+    static WATCHER: Once = Once::new();
+    WATCHER.call_once(|| {
+        tokio::spawn(follow_docker());
+    });
+    channel().subscribe()
+}
+
+// Checking the registries takes a while, so nobody has to wait for it if nobody is listening.
+pub async fn publish_updates() {
+    if channel().receiver_count() == 0 {
+        return;
+    }
+    match DockerController::update_report().await {
+        Ok(report) => publish(Event::Updates(report)),
+        Err(e) => error!("Could not check for updates: {}", e),
+    }
+}
+
+async fn follow_docker() {
+    let mut failing = false;
+    loop {
+        match connect().await {
+            Ok(response) => {
+                failing = false;
+                if let Err(e) = forward(response).await {
+                    warn!("The Docker event stream broke off: {}", e);
+                }
+            }
+            Err(e) => {
+                if !failing {
+                    warn!(
+                        "Could not follow the Docker events, trying again every {} seconds: {}",
+                        RECONNECT.as_secs(),
+                        e
+                    );
+                }
+                failing = true;
+            }
+        }
+        tokio::time::sleep(RECONNECT).await;
+    }
+}
+
+async fn connect() -> Result<Response> {
+    DockerClient::new()?.events().await
+}
+
+async fn forward(mut response: Response) -> Result<()> {
+    let mut buffer = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        buffer.extend_from_slice(&chunk);
+        while let Some(end) = buffer.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = buffer.drain(..=end).collect();
+            match serde_json::from_slice::<EventMessage>(&line) {
+                Ok(message) => handle(message).await,
+                Err(e) => warn!("Could not read a Docker event: {}", e),
+            }
+        }
+    }
+    Ok(())
+}
+
+// Containers with a healthcheck are only running once it passed, which is an event of its own.
+async fn started_state(id: &str) -> ContainerState {
+    let result = match DockerClient::new() {
+        Ok(client) => client.container_has_healthcheck(id).await,
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(true) => ContainerState::Starting,
+        Ok(false) => ContainerState::Running,
+        Err(e) => {
+            warn!("Could not inspect the started container {}: {}", id, e);
+            ContainerState::Running
+        }
+    }
+}
+
+async fn handle(message: EventMessage) {
+    let Some(action) = message.action else {
+        return;
+    };
+    let mut attributes = message
+        .actor
+        .and_then(|actor| actor.attributes)
+        .unwrap_or_default();
+    let Some(id) = attributes.remove("name") else {
+        return;
+    };
+    if !id.starts_with("nextcloud-aio-") {
+        return;
+    }
+    // Like on the containers page, an unhealthy container counts as still starting.
+    let state = match action.as_str() {
+        "start" => started_state(&id).await,
+        "health_status: healthy" => ContainerState::Running,
+        "health_status: unhealthy" => ContainerState::Starting,
+        "restart" => ContainerState::Restarting,
+        "die" => ContainerState::Stopped,
+        "destroy" => ContainerState::ImageDoesNotExist,
+        _ => return,
+    };
+    if id == BACKUP_CONTAINER && matches!(action.as_str(), "start" | "die") {
+        let mode = match &StateConfiguration::instance_ref().await.backup_mode {
+            BackupMode::None => None,
+            mode => Some(mode.clone()),
+        };
+        publish(Event::Backup(BackupStatus {
+            running: action == "start",
+            mode,
+            exit_code: attributes
+                .get("exitCode")
+                .and_then(|code| code.parse().ok()),
+        }));
+    }
+    publish(Event::Container(ContainerStatus { id, state }));
+}
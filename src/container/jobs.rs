@@ -0,0 +1,425 @@
+use crate::auth::api_tokens::format_time;
+use crate::container::events::{self, Event};
+use crate::data::data_const::get_jobs_file;
+use crate::docker_client::Result;
+use serde::{Deserialize, Serialize};
+use std::any::Any;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::future::Future;
+use time::OffsetDateTime;
+use tokio::sync::{Mutex, OnceCell};
+use tracing::{error, info};
+
+// Finished jobs beyond this are forgotten, the oldest first.
+const HISTORY: usize = 50;
+// A start pulls and creates every container, which stays far below this.
+const LOG_LIMIT: usize = 500;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobKind {
+    Start,
+    Stop,
+    Backup,
+    BackupCheck,
+    BackupCheckRepair,
+    BackupTest,
+    Watchtower,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum JobState {
+    Running,
+    Cancelling,
+    Completed,
+    Failed,
+    Cancelled,
+    Interrupted,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Phase {
+    Preparing,
+    Stopping,
+    Starting,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Step {
+    Stopping,
+    Stopped,
+    Pulling,
+    Starting,
+    Started,
+    Skipped,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ContainerProgress {
+    pub id: String,
+    pub step: Step,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LogLine {
+    #[serde(with = "time::serde::rfc3339")]
+    pub time: OffsetDateTime,
+    pub message: String,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub kind: JobKind,
+    pub actor: String,
+    pub state: JobState,
+    pub phase: Phase,
+    pub cancellable: bool,
+    pub containers: Vec<ContainerProgress>,
+    pub logs: Vec<LogLine>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_at: OffsetDateTime,
+    #[serde(default, with = "time::serde::rfc3339::option")]
+    pub finished_at: Option<OffsetDateTime>,
+}
+
+#[derive(Debug)]
+pub enum JobError {
+    NotFound(u64),
+    NotCancellable(JobKind),
+    Finished(u64),
+}
+
+impl JobKind {
+    // Stopping between two containers leaves nothing behind that the next start does not fix. The
+    // backups are not cancelled, the borgbackup container runs to its end on its own anyway.
+    pub fn is_cancellable(&self) -> bool {
+        matches!(self, JobKind::Start | JobKind::Stop | JobKind::Watchtower)
+    }
+}
+
+impl Display for JobKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            JobKind::Start => "start the containers",
+            JobKind::Stop => "stop the containers",
+            JobKind::Backup => "create a backup",
+            JobKind::BackupCheck => "check the backups",
+            JobKind::BackupCheckRepair => "check and repair the backups",
+            JobKind::BackupTest => "test the backup location",
+            JobKind::Watchtower => "update the mastercontainer",
+        })
+    }
+}
+
+impl JobState {
+    pub fn is_finished(&self) -> bool {
+        !matches!(self, JobState::Running | JobState::Cancelling)
+    }
+}
+
+impl Display for JobState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            JobState::Running => "running",
+            JobState::Cancelling => "cancelling",
+            JobState::Completed => "completed",
+            JobState::Failed => "failed",
+            JobState::Cancelled => "cancelled",
+            JobState::Interrupted => "interrupted",
+        })
+    }
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Phase::Preparing => "preparing",
+            Phase::Stopping => "stopping containers",
+            Phase::Starting => "starting containers",
+        })
+    }
+}
+
+impl Display for Step {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Step::Stopping => "stopping",
+            Step::Stopped => "stopped",
+            Step::Pulling => "pulling the image",
+            Step::Starting => "starting",
+            Step::Started => "started",
+            Step::Skipped => "skipped",
+        })
+    }
+}
+
+impl Display for JobError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JobError::NotFound(id) => write!(f, "There is no job {}.", id),
+            JobError::NotCancellable(kind) => {
+                write!(f, "A job to {} cannot be cancelled once it started.", kind)
+            }
+            JobError::Finished(id) => write!(f, "Job {} has already finished.", id),
+        }
+    }
+}
+
+impl LogLine {
+    pub fn formatted_time(&self) -> String {
+        format_time(self.time)
+    }
+}
+
+impl Job {
+    pub fn started(&self) -> String {
+        format_time(self.started_at)
+    }
+
+    pub fn finished(&self) -> Option<String> {
+        self.finished_at.map(format_time)
+    }
+
+    pub fn can_be_cancelled(&self) -> bool {
+        self.cancellable && self.state == JobState::Running
+    }
+
+    fn log(&mut self, message: impl Into<String>) {
+        if self.logs.len() < LOG_LIMIT {
+            self.logs.push(LogLine {
+                time: OffsetDateTime::now_utc(),
+                message: message.into(),
+            });
+        }
+    }
+
+    fn progress(&mut self, id: &str, step: Step) {
+        match self.containers.iter_mut().find(|c| c.id == id) {
+            Some(progress) => progress.step = step,
+            None => self.containers.push(ContainerProgress {
+                id: String::from(id),
+                step,
+            }),
+        }
+    }
+}
+
+// Jobs that were running when the mastercontainer stopped are marked as interrupted on the next
+// start, there is nothing left that could finish them.
+pub struct Jobs {
+    jobs: Vec<Job>,
+}
+
+static INSTANCE: OnceCell<Mutex<Jobs>> = OnceCell::const_new();
+
+tokio::task_local! {
+    static CURRENT: u64;
+}
+
+impl Jobs {
+    fn load() -> Self {
+        let mut jobs: Vec<Job> = fs::read(get_jobs_file())
+            .ok()
+            .and_then(|data| serde_json::from_slice(&data).ok())
+            .unwrap_or_default();
+        let mut interrupted = false;
+        for job in jobs.iter_mut().filter(|job| !job.state.is_finished()) {
+            job.state = JobState::Interrupted;
+            job.log("The mastercontainer stopped while the job was running.");
+            interrupted = true;
+        }
+        let jobs = Self { jobs };
+        if interrupted {
+            jobs.persist();
+        }
+        jobs
+    }
+
+    pub async fn instance() -> &'static Mutex<Self> {
+        INSTANCE
+            .get_or_init(|| async { Mutex::new(Self::load()) })
+            .await
+    }
+
+    // Written next to the file and renamed, so that a crash never leaves half the history behind.
+    fn persist(&self) {
+        let file = get_jobs_file();
+        let temporary = file.with_extension("tmp");
+        let result = serde_json::to_vec(&self.jobs)
+            .map_err(|e| e.to_string())
+            .and_then(|data| {
+                fs::write(&temporary, data)
+                    .and_then(|()| fs::rename(&temporary, &file))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            error!("Could not write the jobs file: {}", e);
+        }
+    }
+
+    // Newest first.
+    pub fn list(&self) -> Vec<Job> {
+        self.jobs.iter().rev().cloned().collect()
+    }
+
+    pub fn get(&self, id: u64) -> Option<&Job> {
+        self.jobs.iter().find(|job| job.id == id)
+    }
+
+    fn create(&mut self, kind: JobKind, actor: &str) -> Job {
+        let job = Job {
+            id: self.jobs.iter().map(|job| job.id).max().unwrap_or_default() + 1,
+            kind,
+            actor: String::from(actor),
+            state: JobState::Running,
+            phase: Phase::Preparing,
+            cancellable: kind.is_cancellable(),
+            containers: Vec::new(),
+            logs: Vec::new(),
+            error: None,
+            started_at: OffsetDateTime::now_utc(),
+            finished_at: None,
+        };
+        self.jobs.push(job.clone());
+        events::publish(Event::Job(job.clone()));
+        let finished = self
+            .jobs
+            .iter()
+            .filter(|job| job.state.is_finished())
+            .count();
+        if finished > HISTORY {
+            let mut excess = finished - HISTORY;
+            self.jobs.retain(|job| {
+                let forget = excess > 0 && job.state.is_finished();
+                if forget {
+                    excess -= 1;
+                }
+                !forget
+            });
+        }
+        self.persist();
+        job
+    }
+
+    fn update(&mut self, id: u64, change: impl FnOnce(&mut Job)) {
+        if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+            change(job);
+            events::publish(Event::Job(job.clone()));
+            self.persist();
+        }
+    }
+
+    fn finish(&mut self, id: u64, error: Option<String>) {
+        self.update(id, |job| {
+            job.state = match (&error, job.state) {
+                (Some(_), _) => JobState::Failed,
+                (None, JobState::Cancelling) => JobState::Cancelled,
+                (None, _) => JobState::Completed,
+            };
+            if let Some(error) = &error {
+                job.log(error.as_str());
+            }
+            job.error = error;
+            job.finished_at = Some(OffsetDateTime::now_utc());
+        });
+    }
+
+    // The job stops before the next container, the one it is working on is finished first.
+    pub fn cancel(&mut self, id: u64) -> std::result::Result<Job, JobError> {
+        let job = self.get(id).ok_or(JobError::NotFound(id))?;
+        if job.state.is_finished() {
+            return Err(JobError::Finished(id));
+        }
+        if !job.cancellable {
+            return Err(JobError::NotCancellable(job.kind));
+        }
+        self.update(id, |job| {
+            job.state = JobState::Cancelling;
+            job.log("Cancelling after the current container.");
+        });
+        self.get(id).cloned().ok_or(JobError::NotFound(id))
+    }
+}
+
+async fn update(change: impl FnOnce(&mut Job)) {
+    if let Ok(id) = CURRENT.try_with(|id| *id) {
+        Jobs::instance().await.lock().await.update(id, change);
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    panic
+        .downcast_ref::<&str>()
+        .map(|message| message.to_string())
+        .or_else(|| panic.downcast_ref::<String>().cloned())
+        .unwrap_or_else(|| String::from("The job panicked."))
+}
+
+// The operation runs in its own task, so that the job is marked as failed even if it panics. Outside
+// of a job, like on the command line or in cron, the reporting functions below do nothing.
+pub async fn spawn<F>(kind: JobKind, actor: &str, operation: F) -> Job
+where
+    F: Future<Output = Result<()>> + Send + 'static,
+{
+    let job = Jobs::instance().await.lock().await.create(kind, actor);
+    let id = job.id;
+    info!("Job {} to {} started by {}", id, kind, actor);
+    tokio::spawn(async move {
+        let error = match tokio::spawn(CURRENT.scope(id, operation)).await {
+            Ok(Ok(())) => None,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(e) if e.is_panic() => Some(panic_message(e.into_panic())),
+            Err(e) => Some(e.to_string()),
+        };
+        match &error {
+            Some(e) => error!("Job {} to {} failed: {}", id, kind, e),
+            None => info!("Job {} to {} finished", id, kind),
+        }
+        Jobs::instance().await.lock().await.finish(id, error);
+        if matches!(kind, JobKind::Start | JobKind::Watchtower) {
+            events::publish_updates().await;
+        }
+    });
+    job
+}
+
+pub async fn phase(phase: Phase) {
+    update(|job| job.phase = phase).await;
+}
+
+pub async fn progress(id: &str, step: Step) {
+    update(|job| {
+        job.progress(id, step);
+        job.log(format!("{}: {}", id, step));
+    })
+    .await;
+}
+
+// Logged like before, and kept with the job to explain why the container was left alone.
+pub async fn skip(id: &str, reason: String) {
+    error!("{}", reason);
+    update(|job| {
+        job.progress(id, Step::Skipped);
+        job.log(reason);
+    })
+    .await;
+}
+
+pub async fn is_cancelled() -> bool {
+    let Ok(id) = CURRENT.try_with(|id| *id) else {
+        return false;
+    };
+    Jobs::instance()
+        .await
+        .lock()
+        .await
+        .get(id)
+        .is_some_and(|job| job.state == JobState::Cancelling)
+}
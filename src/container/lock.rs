@@ -0,0 +1,162 @@
+use crate::auth::api_tokens::format_time;
+use crate::container::jobs::JobKind;
+use crate::data::data_const::get_operation_lock_file;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io::ErrorKind;
+use time::OffsetDateTime;
+use tracing::{error, warn};
+
+// The lock is a file, so that the cron jobs and the command line, which run in their own process,
+// see the operations of the web interface and the other way round.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OperationLock {
+    pub operation: JobKind,
+    pub owner: String,
+    pub pid: u32,
+    #[serde(with = "time::serde::rfc3339")]
+    pub started_at: OffsetDateTime,
+    process_start: Option<String>,
+    token: String,
+}
+
+// Releases the lock when dropped, which also happens when the operation panics.
+pub struct LockGuard {
+    token: String,
+}
+
+#[derive(Debug)]
+pub enum LockError {
+    Busy(OperationLock),
+    Io(std::io::Error),
+}
+
+impl Display for LockError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LockError::Busy(lock) => write!(
+                f,
+                "Another operation is running: {} started to {} at {}. Please wait until it has finished.",
+                lock.owner,
+                lock.operation,
+                lock.started()
+            ),
+            LockError::Io(e) => write!(f, "Could not take the operation lock: {}", e),
+        }
+    }
+}
+
+// The start time of a process tells it apart from a later one that got the same id, e.g. after the
+// mastercontainer was restarted.
+fn process_start(pid: u32) -> Option<String> {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).ok()?;
+    let (_, fields) = stat.rsplit_once(')')?;
+    fields.split_whitespace().nth(19).map(String::from)
+}
+
+fn read() -> Option<OperationLock> {
+    let data = fs::read(get_operation_lock_file()).ok()?;
+    serde_json::from_slice(&data).ok()
+}
+
+impl OperationLock {
+    pub fn started(&self) -> String {
+        format_time(self.started_at)
+    }
+
+    // Without /proc, a lock can only be released with the unlock command.
+    fn is_stale(&self) -> bool {
+        match (&self.process_start, process_start(self.pid)) {
+            (Some(recorded), Some(current)) => *recorded != current,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+// The lock is written next to its file and linked into place, which fails if another process got
+// there first and never leaves a half written lock behind.
+pub fn acquire(operation: JobKind, owner: &str) -> Result<LockGuard, LockError> {
+    let pid = std::process::id();
+    let lock = OperationLock {
+        operation,
+        owner: String::from(owner),
+        pid,
+        started_at: OffsetDateTime::now_utc(),
+        process_start: process_start(pid),
+        token: hex::encode(rand::random::<[u8; 16]>()),
+    };
+    let file = get_operation_lock_file();
+    let temporary = file.with_extension(&lock.token);
+    let data = serde_json::to_vec(&lock).map_err(|e| LockError::Io(e.into()))?;
+    fs::write(&temporary, data).map_err(LockError::Io)?;
+    let result = link(&temporary, &lock);
+    if let Err(e) = fs::remove_file(&temporary) {
+        error!("Could not remove {}: {}", temporary.display(), e);
+    }
+    result
+}
+
+fn link(temporary: &std::path::Path, lock: &OperationLock) -> Result<LockGuard, LockError> {
+    let file = get_operation_lock_file();
+    // A stale lock is removed once, a second conflict means that someone else just took it.
+    for _ in 0..2 {
+        match fs::hard_link(temporary, &file) {
+            Ok(()) => {
+                return Ok(LockGuard {
+                    token: lock.token.clone(),
+                })
+            }
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => match read() {
+                Some(current) if !current.is_stale() => return Err(LockError::Busy(current)),
+                current => {
+                    if let Some(current) = &current {
+                        warn!(
+                            "Removing the lock of {}, whose process {} has ended.",
+                            current.owner, current.pid
+                        );
+                    }
+                    remove_if(|candidate| {
+                        candidate.map(|lock| &lock.token)
+                            == current.as_ref().map(|lock| &lock.token)
+                    })
+                }
+            },
+            Err(e) => return Err(LockError::Io(e)),
+        }
+    }
+    match read() {
+        Some(current) => Err(LockError::Busy(current)),
+        None => Err(LockError::Io(ErrorKind::AlreadyExists.into())),
+    }
+}
+
+fn remove_if(matches: impl FnOnce(Option<&OperationLock>) -> bool) {
+    if !matches(read().as_ref()) {
+        return;
+    }
+    match fs::remove_file(get_operation_lock_file()) {
+        Ok(()) => {}
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => error!("Could not remove the operation lock: {}", e),
+    }
+}
+
+// The running operation, if there is one.
+pub fn current() -> Option<OperationLock> {
+    read().filter(|lock| !lock.is_stale())
+}
+
+// For locks that are stuck anyway, e.g. because the process that held it could not be checked.
+pub fn release() -> Option<OperationLock> {
+    let lock = read();
+    remove_if(|_| true);
+    lock
+}
+
+impl Drop for LockGuard {
+    fn drop(&mut self) {
+        remove_if(|lock| lock.is_some_and(|lock| lock.token == self.token));
+    }
+}
@@ -1,3 +1,6 @@
 pub mod controller;
 mod definition;
+pub mod events;
+pub mod jobs;
+pub mod lock;
 pub mod models;
@@ -1,3 +1,4 @@
+use crate::configuration::Configuration;
 use serde::{Deserialize, Serialize};
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -99,6 +100,15 @@ impl Default for Container {
     }
 }
 
+impl Container {
+    pub fn environment(&self, config: &Configuration) -> Vec<String> {
+        self.container_environment_variables
+            .iter()
+            .map(|variable| config.substitute_placeholders(variable))
+            .collect()
+    }
+}
+
 impl PartialEq for Container {
     fn eq(&self, other: &Self) -> bool {
         self.identifier == other.identifier
@@ -1,3 +1,13 @@
+use crate::configuration::StateConfiguration;
+use crate::container::controller::DockerController;
+use crate::container::jobs::JobKind;
+use crate::cron::CronError;
+
+pub async fn run(owner: &str) -> Result<(), CronError> {
+    let lock = DockerController::lock(JobKind::Backup, owner)?;
+    Ok(DockerController::start_backup(lock, StateConfiguration::instance_mut().await).await?)
+}
+
 // declare(strict_types=1);
 // ini_set('memory_limit', '2048M');
 // require __DIR__ . '/../../vendor/autoload.php';
@@ -1,3 +1,6 @@
+use crate::container::lock::LockError;
+use std::fmt::{Display, Formatter};
+
 pub mod backup_notification;
 pub mod check_backup;
 pub mod check_free_disk_space;
@@ -8,3 +11,53 @@ pub mod start_containers;
 pub mod stop_containers;
 pub mod update_mastercontainer;
 pub mod update_notification;
+
+// The notification jobs are not ported yet and can only be run by the PHP scripts.
+pub const JOBS: [&str; 6] = [
+    "check-backup",
+    "create-backup",
+    "start-and-update-containers",
+    "start-containers",
+    "stop-containers",
+    "update-mastercontainer",
+];
+
+#[derive(Debug)]
+pub enum CronError {
+    Docker(reqwest::Error),
+    Lock(LockError),
+}
+
+impl Display for CronError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CronError::Docker(e) => write!(f, "Could not reach Docker: {}", e),
+            CronError::Lock(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<reqwest::Error> for CronError {
+    fn from(e: reqwest::Error) -> Self {
+        CronError::Docker(e)
+    }
+}
+
+impl From<LockError> for CronError {
+    fn from(e: LockError) -> Self {
+        CronError::Lock(e)
+    }
+}
+
+pub async fn run(job: &str) -> Option<Result<(), CronError>> {
+    let owner = format!("the {} cron job", job);
+    Some(match job {
+        "check-backup" => check_backup::run(&owner).await,
+        "create-backup" => create_backup::run(&owner).await,
+        "start-and-update-containers" => start_and_update_containers::run(&owner).await,
+        "start-containers" => start_containers::run(&owner).await,
+        "stop-containers" => stop_containers::run(&owner).await,
+        "update-mastercontainer" => update_mastercontainer::run(&owner).await,
+        _ => return None,
+    })
+}
@@ -1,3 +1,16 @@
+use crate::configuration::StateConfiguration;
+use crate::container::controller::DockerController;
+use crate::container::jobs::JobKind;
+use crate::cron::CronError;
+
+pub async fn run(owner: &str) -> Result<(), CronError> {
+    let lock = DockerController::lock(JobKind::Start, owner)?;
+    Ok(
+        DockerController::start_top_container(lock, StateConfiguration::instance_mut().await, true)
+            .await?,
+    )
+}
+
 // declare(strict_types=1);
 // ini_set('memory_limit', '2048M');
 // ini_set('log_errors_max_len', '0');
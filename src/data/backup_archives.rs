@@ -0,0 +1,41 @@
+use crate::data::data_const::get_backup_archives_list;
+use std::fs;
+use time::macros::format_description;
+use time::{OffsetDateTime, PrimitiveDateTime};
+use tracing::error;
+
+#[derive(Clone, Debug)]
+pub struct BackupArchive {
+    pub name: String,
+    pub time: OffsetDateTime,
+}
+
+impl BackupArchive {
+    fn parse(line: &str) -> Option<Self> {
+        let (name, time) = line.split_once(',')?;
+        let format = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+        PrimitiveDateTime::parse(time.trim(), format)
+            .map_err(|e| error!("Could not parse the time of backup archive {}: {}", name, e))
+            .ok()
+            .map(|time| Self {
+                name: name.trim().to_string(),
+                time: time.assume_utc(),
+            })
+    }
+
+    pub fn time_string(&self) -> String {
+        let format = format_description!("[year]-[month]-[day] [hour]:[minute]:[second]");
+        self.time.format(format).unwrap_or_default()
+    }
+}
+
+pub fn get_backup_archives() -> Vec<BackupArchive> {
+    match fs::read_to_string(get_backup_archives_list()) {
+        Ok(content) => content
+            .lines()
+            .filter(|line| !line.is_empty())
+            .filter_map(BackupArchive::parse)
+            .collect(),
+        Err(_) => Vec::new(),
+    }
+}
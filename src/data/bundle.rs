@@ -0,0 +1,244 @@
+use crate::auth::api_tokens::ApiTokens;
+use crate::auth::login_tokens::LoginTokens;
+use crate::auth::sessions::Sessions;
+use crate::auth::two_factor::TwoFactor;
+use crate::auth::users::Users;
+use crate::configuration::StateConfiguration;
+use crate::data::data_const::{
+    get_audit_log_file, get_backup_archives_list, get_config_file, get_daily_backup_block_file,
+    get_data_directory, get_jobs_file, get_login_tokens_file, get_operation_lock_file,
+    get_session_date_file, get_sessions_file,
+};
+use argon2::Argon2;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rand::RngCore;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const MAGIC: &[u8] = b"AIO-BUNDLE-1\n";
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+
+#[derive(Debug)]
+pub enum BundleError {
+    Io(io::Error),
+    EmptyPassphrase,
+    AlreadyInstalled,
+    InvalidBundle,
+    WrongPassphrase,
+}
+
+impl Display for BundleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BundleError::Io(e) => write!(f, "Could not access the data directory: {}", e),
+            BundleError::EmptyPassphrase => write!(f, "Please provide a passphrase."),
+            BundleError::AlreadyInstalled => write!(
+                f,
+                "A configuration bundle can only be imported on a fresh installation."
+            ),
+            BundleError::InvalidBundle => write!(f, "The file is not a configuration bundle."),
+            BundleError::WrongPassphrase => write!(
+                f,
+                "The bundle could not be decrypted. Please check the passphrase."
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for BundleError {
+    fn from(e: io::Error) -> Self {
+        BundleError::Io(e)
+    }
+}
+
+// The place of a file of the data directory in `directory`, so that the bundle can be tested
+// without the data directory of the process.
+fn relocate(directory: &Path, file: PathBuf) -> PathBuf {
+    match file.strip_prefix(get_data_directory()) {
+        Ok(name) => directory.join(name),
+        Err(_) => file,
+    }
+}
+
+// State that only makes sense on the host that produced it.
+fn is_excluded(directory: &Path, path: &Path) -> bool {
+    [
+        get_daily_backup_block_file(),
+        get_session_date_file(),
+        get_login_tokens_file(),
+        get_sessions_file(),
+        get_jobs_file(),
+        get_operation_lock_file(),
+        get_audit_log_file(),
+        get_backup_archives_list(),
+    ]
+    .into_iter()
+    .any(|excluded| relocate(directory, excluded) == path)
+}
+
+fn collect_files(root: &Path, directory: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    for entry in fs::read_dir(directory)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if path.is_file() && !is_excluded(root, &path) {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn cipher(passphrase: &str, salt: &[u8]) -> Result<XChaCha20Poly1305, BundleError> {
+    let mut key = [0u8; 32];
+    Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|_| BundleError::InvalidBundle)?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+pub fn export_bundle(passphrase: &str) -> Result<Vec<u8>, BundleError> {
+    export_from(&get_data_directory(), passphrase)
+}
+
+fn export_from(data_directory: &Path, passphrase: &str) -> Result<Vec<u8>, BundleError> {
+    if passphrase.is_empty() {
+        return Err(BundleError::EmptyPassphrase);
+    }
+
+    let mut files = Vec::new();
+    collect_files(data_directory, data_directory, &mut files)?;
+    files.sort();
+
+    let mut archive = tar::Builder::new(GzEncoder::new(Vec::new(), Compression::default()));
+    for file in files {
+        if let Ok(name) = file.strip_prefix(data_directory) {
+            archive.append_path_with_name(&file, name)?;
+        }
+    }
+    let plain = archive.into_inner()?.finish()?;
+
+    let mut salt = [0u8; SALT_LENGTH];
+    let mut nonce = [0u8; NONCE_LENGTH];
+    rand::thread_rng().fill_bytes(&mut salt);
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let encrypted = cipher(passphrase, &salt)?
+        .encrypt(XNonce::from_slice(&nonce), plain.as_slice())
+        .map_err(|_| BundleError::InvalidBundle)?;
+
+    Ok([MAGIC, &salt, &nonce, &encrypted].concat())
+}
+
+// The imported files only take effect with `reload`.
+pub fn import_bundle(bundle: &[u8], passphrase: &str) -> Result<(), BundleError> {
+    import_into(&get_data_directory(), bundle, passphrase)
+}
+
+fn import_into(data_directory: &Path, bundle: &[u8], passphrase: &str) -> Result<(), BundleError> {
+    if relocate(data_directory, get_config_file()).is_file() {
+        return Err(BundleError::AlreadyInstalled);
+    }
+    if passphrase.is_empty() {
+        return Err(BundleError::EmptyPassphrase);
+    }
+
+    let rest = bundle
+        .strip_prefix(MAGIC)
+        .filter(|rest| rest.len() > SALT_LENGTH + NONCE_LENGTH)
+        .ok_or(BundleError::InvalidBundle)?;
+    let (salt, rest) = rest.split_at(SALT_LENGTH);
+    let (nonce, encrypted) = rest.split_at(NONCE_LENGTH);
+    let plain = cipher(passphrase, salt)?
+        .decrypt(XNonce::from_slice(nonce), encrypted)
+        .map_err(|_| BundleError::WrongPassphrase)?;
+
+    fs::create_dir_all(data_directory)?;
+    tar::Archive::new(GzDecoder::new(plain.as_slice())).unpack(data_directory)?;
+    Ok(())
+}
+
+// Everything read from the data directory is kept in memory and written back on the next change,
+// which would undo an import that is not read again.
+pub async fn reload() {
+    StateConfiguration::reload().await;
+    Users::reload().await;
+    ApiTokens::reload().await;
+    TwoFactor::reload().await;
+    LoginTokens::reload().await;
+    Sessions::reload().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("nextcloud-aio-bundle-{name}"));
+        let _ = fs::remove_dir_all(&directory);
+        fs::create_dir_all(&directory).unwrap();
+        directory
+    }
+
+    fn installation(name: &str) -> PathBuf {
+        let directory = directory(name);
+        fs::write(
+            relocate(&directory, get_config_file()),
+            r#"{"domain":"a.example"}"#,
+        )
+        .unwrap();
+        fs::write(directory.join("users.json"), "[]").unwrap();
+        fs::create_dir_all(directory.join("wordlists")).unwrap();
+        fs::write(directory.join("wordlists/de.txt"), "apfel").unwrap();
+        fs::write(relocate(&directory, get_sessions_file()), "{}").unwrap();
+        directory
+    }
+
+    #[test]
+    fn bundles_restore_the_data_directory() {
+        let source = installation("export");
+        let bundle = export_from(&source, "bundle passphrase").unwrap();
+        let target = directory("import");
+        import_into(&target, &bundle, "bundle passphrase").unwrap();
+
+        for name in ["configuration.json", "users.json", "wordlists/de.txt"] {
+            assert_eq!(
+                fs::read(source.join(name)).unwrap(),
+                fs::read(target.join(name)).unwrap(),
+                "{name}"
+            );
+        }
+        assert!(!relocate(&target, get_sessions_file()).exists());
+        fs::remove_dir_all(source).unwrap();
+        fs::remove_dir_all(target).unwrap();
+    }
+
+    #[test]
+    fn bundles_need_the_passphrase_and_a_fresh_installation() {
+        let source = installation("refused");
+        let bundle = export_from(&source, "bundle passphrase").unwrap();
+        let target = directory("refused-target");
+
+        let result = import_into(&target, &bundle, "another passphrase");
+        assert!(matches!(result, Err(BundleError::WrongPassphrase)));
+        assert!(matches!(
+            import_into(&target, b"not a bundle", "bundle passphrase"),
+            Err(BundleError::InvalidBundle)
+        ));
+        assert_eq!(fs::read_dir(&target).unwrap().count(), 0);
+
+        let result = import_into(&source, &bundle, "bundle passphrase");
+        assert!(matches!(result, Err(BundleError::AlreadyInstalled)));
+        assert!(matches!(
+            export_from(&source, ""),
+            Err(BundleError::EmptyPassphrase)
+        ));
+        fs::remove_dir_all(source).unwrap();
+        fs::remove_dir_all(target).unwrap();
+    }
+}
@@ -1,13 +1,42 @@
 use std::path::PathBuf;
+use std::sync::OnceLock;
 
 #[cfg(not(feature = "development"))]
-fn get_home_directory() -> PathBuf {
-    PathBuf::from("/mnt/docker-aio-config")
+pub const DEFAULT_HOME_DIRECTORY: &str = "/mnt/docker-aio-config";
+#[cfg(feature = "development")]
+pub const DEFAULT_HOME_DIRECTORY: &str = ".";
+pub const DEFAULT_CONTAINERS_FILE: &str = "containers.json";
+
+// Both can be changed once on the command line, before anything is read from them.
+static HOME_DIRECTORY: OnceLock<PathBuf> = OnceLock::new();
+static CONTAINERS_FILE: OnceLock<PathBuf> = OnceLock::new();
+
+pub fn set_home_directory(path: PathBuf) {
+    let _ = HOME_DIRECTORY.set(path);
+}
+
+pub fn set_containers_file(path: PathBuf) {
+    let _ = CONTAINERS_FILE.set(path);
+}
+
+// The tests get a data directory of their own, emptied once per run, so that the files they write,
+// like the operation lock, neither need nor touch a real installation.
+#[cfg(test)]
+pub fn use_test_home() {
+    static HOME: std::sync::Once = std::sync::Once::new();
+    HOME.call_once(|| {
+        let home = std::env::temp_dir().join("nextcloud-aio-route-tests");
+        let _ = std::fs::remove_dir_all(&home);
+        std::fs::create_dir_all(home.join("data")).unwrap();
+        set_home_directory(home);
+    });
 }
 
-#[cfg(feature = "development")]
 fn get_home_directory() -> PathBuf {
-    PathBuf::from(".")
+    HOME_DIRECTORY
+        .get()
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_HOME_DIRECTORY))
 }
 
 pub fn get_data_directory() -> PathBuf {
@@ -50,10 +79,57 @@ pub fn get_session_date_file() -> PathBuf {
     get_data_directory().join("session_date_file")
 }
 
+pub fn get_api_tokens_file() -> PathBuf {
+    get_data_directory().join("api_tokens.json")
+}
+
+pub fn get_sessions_file() -> PathBuf {
+    get_data_directory().join("sessions.json")
+}
+
+pub fn get_audit_log_file() -> PathBuf {
+    get_data_directory().join("audit.log")
+}
+
+pub fn get_jobs_file() -> PathBuf {
+    get_data_directory().join("jobs.json")
+}
+
+pub fn get_operation_lock_file() -> PathBuf {
+    get_data_directory().join("operation.lock")
+}
+
+pub fn get_users_file() -> PathBuf {
+    get_data_directory().join("users.json")
+}
+
+pub fn get_two_factor_file() -> PathBuf {
+    get_data_directory().join("two_factor.json")
+}
+
+pub fn get_login_tokens_file() -> PathBuf {
+    get_data_directory().join("login_tokens.json")
+}
+
+pub fn get_tls_certificate_file() -> PathBuf {
+    get_data_directory().join("tls-certificate.pem")
+}
+
+pub fn get_tls_key_file() -> PathBuf {
+    get_data_directory().join("tls-key.pem")
+}
+
+pub fn get_word_lists_directory() -> PathBuf {
+    get_data_directory().join("wordlists")
+}
+
 pub fn get_community_containers_directory() -> PathBuf {
     PathBuf::from("../../../community-containers")
 }
 
 pub fn get_containers_file() -> PathBuf {
-    PathBuf::from("containers.json")
+    CONTAINERS_FILE
+        .get()
+        .cloned()
+        .unwrap_or_else(|| PathBuf::from(DEFAULT_CONTAINERS_FILE))
 }
@@ -1,2 +1,4 @@
+pub mod backup_archives;
+pub mod bundle;
 pub mod data_const;
 pub mod setup;
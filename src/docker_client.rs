@@ -2,8 +2,10 @@ use crate::container::controller::ContainerState;
 use crate::container::controller::VersionState;
 use crate::container::models::{Container, ContainerVolume};
 use axum::http::header;
-use bollard_stubs::models::{ContainerInspectResponse, ImageInspect, VolumeCreateOptions};
-use reqwest::{Client, StatusCode};
+use bollard_stubs::models::{
+    ContainerInspectResponse, HealthStatusEnum, ImageInspect, VolumeCreateOptions,
+};
+use reqwest::{Client, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use time::{Duration, OffsetDateTime};
 use tracing::log::warn;
@@ -108,6 +110,80 @@ impl DockerClient {
         //     }
     }
 
+    pub async fn container_logs(&self, id: &str) -> Result<String> {
+        let body = self
+            .client
+            .get(format!(
+                "{BASE_URL}/containers/{id}/logs?stdout=true&stderr=true&timestamps=true"
+            ))
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        // Without a TTY, every chunk starts with an eight byte header: the stream, three zero bytes
+        // and the length of the chunk.
+        let mut logs = String::new();
+        let mut rest = &body[..];
+        while rest.len() >= 8 {
+            let length = u32::from_be_bytes([rest[4], rest[5], rest[6], rest[7]]) as usize;
+            let end = rest.len().min(8 + length);
+            logs.push_str(&String::from_utf8_lossy(&rest[8..end]));
+            rest = &rest[end..];
+        }
+        Ok(logs)
+    }
+
+    pub async fn container_has_healthcheck(&self, id: &str) -> Result<bool> {
+        let response = self
+            .client
+            .get(format!("{BASE_URL}/containers/{id}/json"))
+            .send()
+            .await?
+            .error_for_status()?
+            .json::<ContainerInspectResponse>()
+            .await?;
+        let status = response
+            .state
+            .and_then(|state| state.health)
+            .and_then(|health| health.status);
+        Ok(!matches!(
+            status,
+            None | Some(HealthStatusEnum::EMPTY | HealthStatusEnum::NONE)
+        ))
+    }
+
+    // The response never ends, every event is a JSON object on a line of its own.
+    pub async fn events(&self) -> Result<Response> {
+        self.client
+            .get(format!("{BASE_URL}/events"))
+            .query(&[("filters", r#"{"type":["container"]}"#)])
+            .send()
+            .await?
+            .error_for_status()
+    }
+
+    // The digests the local image was pulled by, empty if it was never pulled.
+    pub async fn image_digests(&self, image: &str) -> Result<Vec<String>> {
+        match self
+            .client
+            .get(format!("{BASE_URL}/images/{image}/json"))
+            .send()
+            .await?
+            .error_for_status()
+        {
+            Ok(r) => Ok(r
+                .json::<ImageInspect>()
+                .await?
+                .repo_digests
+                .unwrap_or_default()),
+            Err(e) => match e.status() {
+                Some(StatusCode::NOT_FOUND) => Ok(Vec::new()),
+                _ => Err(e),
+            },
+        }
+    }
+
     pub async fn container_get_running_state(&self, c: &Container) -> Result<ContainerState> {
         match self
             .client
@@ -270,7 +346,7 @@ impl DockerClient {
 
     // TODO Refactor
 
-    async fn build_image_name(&self, c: &Container) -> String {
+    pub async fn build_image_name(&self, c: &Container) -> String {
         if c.image_tag == "%AIO_CHANNEL%" {
             format!(
                 "{}:{}",
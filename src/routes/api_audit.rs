@@ -0,0 +1,18 @@
+pub use export::handler as export_handler;
+
+mod export {
+    use crate::auth::audit::{AuditFilter, AuditLog};
+    use crate::routes::HttpResponse;
+    use axum::extract::Query;
+    use axum_typed_routing::route;
+
+    // Takes the same filters as the audit log page.
+    #[route(GET "/api/audit/export")]
+    pub async fn handler(Query(filter): Query<AuditFilter>) -> HttpResponse {
+        let entries = AuditLog::instance().await.lock().await.entries(&filter);
+        match serde_json::to_vec_pretty(&entries) {
+            Ok(body) => HttpResponse::Attachment("aio-audit-log.json", body),
+            Err(e) => HttpResponse::Error(e.to_string()),
+        }
+    }
+}
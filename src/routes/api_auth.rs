@@ -1,24 +1,42 @@
 pub use getlogin::handler as getlogin_handler;
 pub use login::handler as login_handler;
+pub use login_token::handler as login_token_handler;
 pub use logout::handler as logout_handler;
+pub use password::handler as password_handler;
+pub use second_factor::handler as second_factor_handler;
 
 mod logout {
+    use crate::auth::audit::{record, Actor, AuditEntry, AuditEvent, Outcome};
     use crate::auth::clear_auth;
+    use crate::auth::client_ip::ClientIp;
     use crate::routes::HttpResponse;
     use axum_typed_routing::route;
     use tower_sessions::Session;
 
     #[route(POST "/api/auth/logout")]
-    pub async fn handler(session: Session) -> HttpResponse {
+    pub async fn handler(session: Session, client: ClientIp, Actor(actor): Actor) -> HttpResponse {
         match clear_auth(&session).await {
-            Ok(()) => HttpResponse::TemporaryRedirect("/login"),
+            Ok(()) => {
+                record(AuditEntry::new(
+                    AuditEvent::Logout,
+                    &actor,
+                    client,
+                    Outcome::Success,
+                ))
+                .await;
+                HttpResponse::TemporaryRedirect("/login")
+            }
             Err(e) => HttpResponse::Error(e.to_string()),
         }
     }
 }
 
 mod login {
-    use crate::auth::set_auth_from_password;
+    use crate::auth::audit::{record, AuditEntry, AuditEvent, Outcome};
+    use crate::auth::client_ip::ClientIp;
+    use crate::auth::login_throttle::LoginThrottle;
+    use crate::auth::users::SHARED_ACCOUNT;
+    use crate::auth::{set_auth_from_password, PasswordLogin};
     use crate::routes::HttpResponse;
     use axum::Form;
     use axum_typed_routing::route;
@@ -27,23 +45,197 @@ mod login {
 
     #[derive(Deserialize)]
     pub struct PasswordForm {
+        #[serde(default)]
+        pub username: String,
         pub password: String,
     }
     #[route(POST "/api/auth/login")]
     pub async fn handler(
         session: Session,
-        Form(PasswordForm { password }): Form<PasswordForm>,
+        client: ClientIp,
+        Form(PasswordForm { username, password }): Form<PasswordForm>,
     ) -> HttpResponse {
-        match set_auth_from_password(&session, &password).await {
-            Ok(true) => HttpResponse::TemporaryRedirect("/"),
-            Ok(false) => HttpResponse::UnauthorizedRedirect("/login"),
-            Err(e) => HttpResponse::Error(e.to_string()),
+        let actor = match username.trim() {
+            "" => SHARED_ACCOUNT,
+            name => name,
+        };
+        let entry = |outcome| AuditEntry::new(AuditEvent::Login, actor, client, outcome);
+        // Only held to count the attempt, the password is checked without it.
+        let throttle = LoginThrottle::instance().await;
+        let attempt = match throttle.lock().await.attempt(client) {
+            Ok(attempt) => attempt,
+            Err(retry_after) => {
+                record(entry(Outcome::Failure).detail("throttled")).await;
+                return HttpResponse::TooManyRequests(
+                    retry_after,
+                    format!(
+                        "Too many failed login attempts. Please try again in {} seconds.",
+                        retry_after
+                    ),
+                );
+            }
+        };
+        match set_auth_from_password(&session, &username, &password).await {
+            Ok(PasswordLogin::Authenticated) => {
+                throttle.lock().await.success(attempt);
+                record(entry(Outcome::Success)).await;
+                HttpResponse::CreatedAndRedirect("/")
+            }
+            Ok(PasswordLogin::SecondFactorRequired) => {
+                throttle.lock().await.cancel(attempt);
+                record(entry(Outcome::Success).detail("second factor required")).await;
+                HttpResponse::CreatedAndRedirect("/login/two-factor")
+            }
+            Ok(PasswordLogin::Invalid) => {
+                throttle.lock().await.failure(attempt);
+                record(entry(Outcome::Failure)).await;
+                HttpResponse::UnauthorizedRedirect("/login")
+            }
+            Err(e) => {
+                throttle.lock().await.cancel(attempt);
+                HttpResponse::Error(e.to_string())
+            }
+        }
+    }
+}
+
+mod second_factor {
+    use crate::auth::audit::{record, AuditEntry, AuditEvent, Outcome};
+    use crate::auth::client_ip::ClientIp;
+    use crate::auth::login_throttle::LoginThrottle;
+    use crate::auth::{login_name, set_auth_from_second_factor};
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+    use tower_sessions::Session;
+
+    #[derive(Deserialize)]
+    pub struct CodeForm {
+        pub code: String,
+    }
+
+    // Shares the throttle with the passphrase step, six digits are far easier to guess.
+    #[route(POST "/api/auth/two-factor")]
+    pub async fn handler(
+        session: Session,
+        client: ClientIp,
+        Form(CodeForm { code }): Form<CodeForm>,
+    ) -> HttpResponse {
+        let actor = match login_name(&session).await {
+            Ok(actor) => actor,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let entry = |outcome| AuditEntry::new(AuditEvent::SecondFactor, &actor, client, outcome);
+        let throttle = LoginThrottle::instance().await;
+        let attempt = match throttle.lock().await.attempt(client) {
+            Ok(attempt) => attempt,
+            Err(retry_after) => {
+                record(entry(Outcome::Failure).detail("throttled")).await;
+                return HttpResponse::TooManyRequests(
+                    retry_after,
+                    format!(
+                        "Too many failed login attempts. Please try again in {} seconds.",
+                        retry_after
+                    ),
+                );
+            }
+        };
+        match set_auth_from_second_factor(&session, &code).await {
+            Ok(true) => {
+                throttle.lock().await.success(attempt);
+                record(entry(Outcome::Success)).await;
+                HttpResponse::CreatedAndRedirect("/")
+            }
+            Ok(false) => {
+                throttle.lock().await.failure(attempt);
+                record(entry(Outcome::Failure)).await;
+                HttpResponse::UnprocessableEntity(String::from(
+                    "The code is wrong or the login has expired. Please try again.",
+                ))
+            }
+            Err(e) => {
+                throttle.lock().await.cancel(attempt);
+                HttpResponse::Error(e.to_string())
+            }
+        }
+    }
+}
+
+mod password {
+    use crate::auth::audit::{record, Actor, AuditEntry, AuditEvent, Outcome};
+    use crate::auth::client_ip::ClientIp;
+    use crate::auth::login_throttle::LoginThrottle;
+    use crate::auth::{change_password, PasswordChange};
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+    use tower_sessions::Session;
+
+    #[derive(Deserialize)]
+    pub struct PasswordForm {
+        pub current_password: String,
+        pub new_password: String,
+    }
+
+    // The current password is guessed no easier here than at the login.
+    #[route(POST "/api/auth/password")]
+    pub async fn handler(
+        session: Session,
+        client: ClientIp,
+        Actor(actor): Actor,
+        Form(PasswordForm {
+            current_password,
+            new_password,
+        }): Form<PasswordForm>,
+    ) -> HttpResponse {
+        let entry = |outcome| {
+            AuditEntry::new(AuditEvent::PasswordChange, &actor, client, outcome)
+                .detail("own password")
+        };
+        let throttle = LoginThrottle::instance().await;
+        let attempt = match throttle.lock().await.attempt(client) {
+            Ok(attempt) => attempt,
+            Err(retry_after) => {
+                record(entry(Outcome::Failure).detail("throttled")).await;
+                return HttpResponse::TooManyRequests(
+                    retry_after,
+                    format!(
+                        "Too many failed attempts. Please try again in {} seconds.",
+                        retry_after
+                    ),
+                );
+            }
+        };
+        match change_password(&session, &current_password, &new_password).await {
+            Ok(PasswordChange::Changed) => {
+                throttle.lock().await.success(attempt);
+                record(entry(Outcome::Success)).await;
+                HttpResponse::CreatedAndRedirect("/two-factor")
+            }
+            Ok(PasswordChange::WrongPassword) => {
+                throttle.lock().await.failure(attempt);
+                record(entry(Outcome::Failure)).await;
+                HttpResponse::UnprocessableEntity(String::from("The current password is wrong."))
+            }
+            Ok(PasswordChange::Rejected(e)) => {
+                throttle.lock().await.cancel(attempt);
+                HttpResponse::UnprocessableEntity(e)
+            }
+            Err(e) => {
+                throttle.lock().await.cancel(attempt);
+                HttpResponse::Error(e.to_string())
+            }
         }
     }
 }
 
 mod getlogin {
+    use crate::auth::audit::{record, AuditEntry, AuditEvent, Outcome};
+    use crate::auth::client_ip::ClientIp;
     use crate::auth::set_auth_from_token;
+    use crate::auth::users::SHARED_ACCOUNT;
     use crate::routes::HttpResponse;
     use axum::extract::Query;
     use axum_typed_routing::route;
@@ -58,12 +250,38 @@ mod getlogin {
     #[route(GET "/api/auth/getlogin")]
     pub async fn handler(
         session: Session,
+        client: ClientIp,
         Query(TokenQuery { token }): Query<TokenQuery>,
     ) -> HttpResponse {
-        match set_auth_from_token(&session, &token).await {
+        let result = set_auth_from_token(&session, &token).await;
+        if let Ok(success) = result {
+            let outcome = Outcome::of(success);
+            let entry = AuditEntry::new(AuditEvent::TokenLogin, SHARED_ACCOUNT, client, outcome);
+            record(entry).await;
+        }
+        match result {
             Ok(true) => HttpResponse::TemporaryRedirect("/"),
             Ok(false) => HttpResponse::UnauthorizedRedirect("/login"),
             Err(e) => HttpResponse::Error(e.to_string()),
         }
     }
 }
+
+mod login_token {
+    use crate::auth::login_tokens::{LoginTokens, LOGIN_TOKEN_TTL};
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+    use serde_json::json;
+    use tracing::info;
+
+    #[route(POST "/api/auth/login-token")]
+    pub async fn handler() -> HttpResponse {
+        let token = LoginTokens::instance().await.lock().await.mint();
+        info!("Issued a one-time login token.");
+        HttpResponse::Json(json!({
+            "token": token,
+            "url": format!("/api/auth/getlogin?token={}", token),
+            "expires_in": LOGIN_TOKEN_TTL.whole_seconds(),
+        }))
+    }
+}
@@ -0,0 +1,55 @@
+pub use retention::handler as retention_handler;
+
+mod retention {
+    use crate::configuration::retention::KeepReason;
+    use crate::configuration::{RetentionPolicy, StateConfiguration};
+    use crate::data::backup_archives::get_backup_archives;
+    use crate::routes::HttpResponse;
+    use axum::extract::Query;
+    use axum_typed_routing::route;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use time::OffsetDateTime;
+
+    #[derive(Deserialize)]
+    pub struct RetentionQuery {
+        pub policy: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    struct ArchiveDecision {
+        name: String,
+        time: String,
+        kept: bool,
+        kept_by: Option<KeepReason>,
+    }
+
+    #[route(GET "/api/backup/retention")]
+    pub async fn handler(Query(RetentionQuery { policy }): Query<RetentionQuery>) -> HttpResponse {
+        let policy: RetentionPolicy = match policy {
+            Some(policy) => match policy.parse() {
+                Ok(policy) => policy,
+                Err(e) => return HttpResponse::UnprocessableEntity(e.to_string()),
+            },
+            None => StateConfiguration::instance_ref()
+                .await
+                .borg_retention_policy
+                .clone(),
+        };
+        let archives = get_backup_archives();
+        let decisions: Vec<ArchiveDecision> = policy
+            .simulate(&archives, OffsetDateTime::now_utc())
+            .into_iter()
+            .map(|decision| ArchiveDecision {
+                name: decision.archive.name.clone(),
+                time: decision.archive.time_string(),
+                kept: decision.kept_by.is_some(),
+                kept_by: decision.kept_by,
+            })
+            .collect();
+        HttpResponse::Json(json!({
+            "policy": policy.to_string(),
+            "archives": decisions,
+        }))
+    }
+}
@@ -1,12 +1,326 @@
+pub use export::handler as export_handler;
+pub use import::handler as import_handler;
 pub use root::handler;
+pub use sources::handler as sources_handler;
 
 mod root {
+    use crate::auth::api_tokens::ApiToken;
+    use crate::auth::audit::{record, Actor, AuditEntry, AuditEvent, Outcome};
+    use crate::auth::client_ip::ClientIp;
+    use crate::auth::password_generator::check_password_strength;
+    use crate::auth::password_hash::{hash_password, verify_password, Verification};
+    use crate::auth::sessions::Sessions;
+    use crate::auth::{session_id, set_session_generation};
+    use crate::configuration::locale::{CollaboraDictionaries, LocaleError};
+    use crate::configuration::retention::RetentionPolicyError;
+    use crate::configuration::units::UnitError;
+    use crate::configuration::{Configuration, ConfigurationSource, StateConfiguration};
     use crate::routes::HttpResponse;
+    use axum::{Extension, Form};
     use axum_typed_routing::route;
+    use serde::Deserialize;
+    use tower_sessions::Session;
+    use tracing::{info, warn};
+
+    #[derive(Deserialize)]
+    pub struct ConfigurationForm {
+        pub domain: Option<String>,
+        pub borg_backup_host_location: Option<String>,
+        pub delete_borg_backup_host_location: Option<String>,
+        #[serde(rename = "options-form")]
+        pub options_form: Option<String>,
+        pub clamav: Option<String>,
+        pub collabora: Option<String>,
+        pub fulltextsearch: Option<String>,
+        pub imaginary: Option<String>,
+        pub talk: Option<String>,
+        #[serde(rename = "talk-recording")]
+        pub talk_recording: Option<String>,
+        pub onlyoffice: Option<String>,
+        #[serde(rename = "docker-socket-proxy")]
+        pub docker_socket_proxy: Option<String>,
+        pub whiteboard: Option<String>,
+        pub apache_port: Option<String>,
+        pub talk_port: Option<String>,
+        pub nextcloud_max_time: Option<String>,
+        pub nextcloud_upload_limit: Option<String>,
+        pub nextcloud_memory_limit: Option<String>,
+        pub borg_retention_policy: Option<String>,
+        pub nextcloud_mount: Option<String>,
+        pub timezone: Option<String>,
+        pub delete_timezone: Option<String>,
+        pub collabora_dictionaries: Option<String>,
+        pub delete_collabora_dictionaries: Option<String>,
+        #[serde(rename = "current-master-password")]
+        pub current_master_password: Option<String>,
+        #[serde(rename = "new-master-password")]
+        pub new_master_password: Option<String>,
+    }
+
+    fn unlocked<T>(
+        config: &Configuration,
+        key: &str,
+        value: Option<T>,
+    ) -> Result<Option<T>, String> {
+        match (value, config.source(key)) {
+            (Some(_), ConfigurationSource::Environment(variable)) => Err(format!(
+                "{key} is set by the environment variable {variable} and cannot be changed here."
+            )),
+            (value, _) => Ok(value),
+        }
+    }
+
+    fn parse<T: std::str::FromStr>(key: &str, value: String) -> Result<T, String> {
+        value
+            .trim()
+            .parse()
+            .map_err(|_| format!("The value '{value}' is not valid for {key}."))
+    }
+
+    fn change_password(config: &mut Configuration, current: &str, new: &str) -> Result<(), String> {
+        if verify_password(&config.password, current) == Verification::Invalid {
+            return Err(String::from("The current AIO passphrase is wrong."));
+        }
+        if current == new {
+            return Err(String::from(
+                "The new passphrase must be different from the current one.",
+            ));
+        }
+        check_password_strength(new)?;
+        config.password = hash_password(new).map_err(|e| e.to_string())?;
+        config.session_generation += 1;
+        Ok(())
+    }
+
+    fn apply(config: &mut Configuration, form: ConfigurationForm) -> Result<(), String> {
+        if let Some(domain) = form.domain {
+            if domain.is_empty() {
+                return Err(String::from("The domain must not be empty."));
+            }
+            config.domain = Some(domain);
+        }
+
+        if let Some(location) = form.borg_backup_host_location {
+            if location.is_empty() {
+                return Err(String::from("The backup location must not be empty."));
+            }
+            config.borg_backup_host_location = Some(location);
+        }
+        if form.delete_borg_backup_host_location.is_some() {
+            config.borg_backup_host_location = None;
+        }
+
+        if form.options_form.is_some() {
+            #[cfg(not(target_arch = "arm"))]
+            {
+                config.is_clamav_enabled = form.clamav.is_some();
+            }
+            config.is_collabora_enabled = form.collabora.is_some();
+            config.is_fulltextsearch_enabled = form.fulltextsearch.is_some();
+            config.is_imaginary_enabled = form.imaginary.is_some();
+            config.is_talk_enabled = form.talk.is_some();
+            config.is_talk_recording_enabled = form.talk.is_some() && form.talk_recording.is_some();
+            config.is_onlyoffice_enabled = form.onlyoffice.is_some();
+            config.is_docker_socket_proxy_enabled = form.docker_socket_proxy.is_some();
+            config.is_whiteboard_enabled = form.whiteboard.is_some();
+        }
+
+        if let Some(port) = unlocked(config, "apache_port", form.apache_port)? {
+            config.apache_port = parse("apache_port", port)?;
+        }
+        if let Some(port) = unlocked(config, "talk_port", form.talk_port)? {
+            config.talk_port = parse("talk_port", port)?;
+        }
+        if let Some(time) = unlocked(config, "nextcloud_max_time", form.nextcloud_max_time)? {
+            config.nextcloud_max_time = time.parse().map_err(|e: UnitError| e.to_string())?;
+        }
+        if let Some(limit) = unlocked(
+            config,
+            "nextcloud_upload_limit",
+            form.nextcloud_upload_limit,
+        )? {
+            config.nextcloud_upload_limit = limit.parse().map_err(|e: UnitError| e.to_string())?;
+        }
+        if let Some(limit) = unlocked(
+            config,
+            "nextcloud_memory_limit",
+            form.nextcloud_memory_limit,
+        )? {
+            config.nextcloud_memory_limit = limit.parse().map_err(|e: UnitError| e.to_string())?;
+        }
+        if let Some(policy) = unlocked(config, "borg_retention_policy", form.borg_retention_policy)?
+        {
+            config.borg_retention_policy = policy
+                .parse()
+                .map_err(|e: RetentionPolicyError| e.to_string())?;
+        }
+        if let Some(mount) = unlocked(config, "nextcloud_mount", form.nextcloud_mount)? {
+            config.nextcloud_mount = Some(mount).filter(|m| !m.is_empty());
+        }
+
+        if let Some(timezone) = form.timezone {
+            if timezone.trim().is_empty() {
+                return Err(String::from("The timezone must not be empty."));
+            }
+            config.timezone = Some(timezone.parse().map_err(|e: LocaleError| e.to_string())?);
+        }
+        if form.delete_timezone.is_some() {
+            config.timezone = None;
+        }
+
+        if let Some(dictionaries) = form.collabora_dictionaries {
+            if dictionaries.trim().is_empty() {
+                return Err(String::from("The dictionaries must not be empty."));
+            }
+            config.collabora_dictionaries = dictionaries
+                .parse()
+                .map_err(|e: LocaleError| e.to_string())?;
+        }
+        if form.delete_collabora_dictionaries.is_some() {
+            config.collabora_dictionaries = CollaboraDictionaries::default();
+        }
+
+        match (form.current_master_password, form.new_master_password) {
+            (None, None) => {}
+            (Some(current), Some(new)) if !current.is_empty() && !new.is_empty() => {
+                change_password(config, &current, &new)?
+            }
+            _ => {
+                return Err(String::from(
+                    "Please enter your current and your new AIO passphrase.",
+                ))
+            }
+        }
+        Ok(())
+    }
 
     #[route(POST "/api/configuration")]
+    pub async fn handler(
+        session: Session,
+        client: ClientIp,
+        Actor(actor): Actor,
+        token: Option<Extension<ApiToken>>,
+        Form(form): Form<ConfigurationForm>,
+    ) -> HttpResponse {
+        let password_change =
+            form.current_master_password.is_some() || form.new_master_password.is_some();
+        let entry = |outcome| {
+            AuditEntry::new(AuditEvent::PasswordChange, &actor, client, outcome)
+                .detail("AIO passphrase")
+        };
+        // Whoever holds a token must not be able to lock everybody else out.
+        if password_change && token.is_some() {
+            warn!(actor, "Rejected an AIO passphrase change with an API token");
+            record(entry(Outcome::Failure)).await;
+            return HttpResponse::Forbidden(
+                "The AIO passphrase can only be changed from a logged in session.",
+            );
+        }
+        let mut config = StateConfiguration::instance_mut().await;
+        let mut updated = Configuration::clone(&config);
+        match apply(&mut updated, form) {
+            Ok(()) => {
+                let generation = updated.session_generation;
+                let password_changed = generation != config.session_generation;
+                *config = updated;
+                config.commit();
+                if password_changed {
+                    info!("The AIO passphrase was changed, logging out all other sessions.");
+                    record(entry(Outcome::Success)).await;
+                    if let Err(e) = set_session_generation(&session, generation).await {
+                        return HttpResponse::Error(e.to_string());
+                    }
+                    let current = match session_id(&session).await {
+                        Ok(id) => id,
+                        Err(e) => return HttpResponse::Error(e.to_string()),
+                    };
+                    Sessions::instance()
+                        .await
+                        .lock()
+                        .await
+                        .revoke_others(current.as_deref(), None);
+                }
+                HttpResponse::CreatedAndRedirect("/")
+            }
+            Err(e) => {
+                if password_change {
+                    record(entry(Outcome::Failure)).await;
+                }
+                HttpResponse::UnprocessableEntity(e)
+            }
+        }
+    }
+}
+
+mod sources {
+    use crate::configuration::StateConfiguration;
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+
+    #[route(GET "/api/configuration/sources")]
     pub async fn handler() -> HttpResponse {
-        // TODO \AIO\Controller\ConfigurationController::class . ':SetConfig'
-        panic!("Not implemented") // TODO
+        let config = StateConfiguration::instance_ref().await;
+        match serde_json::to_value(config.sources()) {
+            Ok(sources) => HttpResponse::Json(sources),
+            Err(e) => HttpResponse::Error(e.to_string()),
+        }
+    }
+}
+
+mod export {
+    use crate::data::bundle::export_bundle;
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct ExportForm {
+        pub passphrase: String,
+    }
+
+    #[route(POST "/api/configuration/export")]
+    pub async fn handler(Form(ExportForm { passphrase }): Form<ExportForm>) -> HttpResponse {
+        match export_bundle(&passphrase) {
+            Ok(bundle) => HttpResponse::Attachment("aio-configuration.bundle", bundle),
+            Err(e) => HttpResponse::UnprocessableEntity(e.to_string()),
+        }
+    }
+}
+
+mod import {
+    use crate::data::bundle::{self, import_bundle};
+    use crate::routes::HttpResponse;
+    use axum::extract::Multipart;
+    use axum_typed_routing::route;
+
+    #[route(POST "/api/configuration/import")]
+    pub async fn handler(mut multipart: Multipart) -> HttpResponse {
+        let mut bundle = Vec::new();
+        let mut passphrase = String::new();
+        loop {
+            match multipart.next_field().await {
+                Ok(Some(field)) => match field.name() {
+                    Some("bundle") => match field.bytes().await {
+                        Ok(bytes) => bundle = bytes.to_vec(),
+                        Err(e) => return HttpResponse::UnprocessableEntity(e.to_string()),
+                    },
+                    Some("passphrase") => match field.text().await {
+                        Ok(text) => passphrase = text,
+                        Err(e) => return HttpResponse::UnprocessableEntity(e.to_string()),
+                    },
+                    _ => {}
+                },
+                Ok(None) => break,
+                Err(e) => return HttpResponse::UnprocessableEntity(e.to_string()),
+            }
+        }
+        match import_bundle(&bundle, &passphrase) {
+            Ok(()) => {
+                bundle::reload().await;
+                HttpResponse::CreatedAndRedirect("/")
+            }
+            Err(e) => HttpResponse::UnprocessableEntity(e.to_string()),
+        }
     }
 }
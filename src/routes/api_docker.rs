@@ -10,36 +10,56 @@ pub use stop::handler as stop_handler;
 pub use watchtower::handler as watchtower_handler;
 
 mod watchtower {
+    use crate::auth::audit::Actor;
     use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
     use crate::routes::HttpResponse;
     use axum_typed_routing::route;
 
     #[route(POST "/api/docker/watchtower")]
-    pub async fn handler() -> HttpResponse {
-        match DockerController::start_watchtower().await {
-            Ok(()) => HttpResponse::CreatedAndRedirect("/"),
-            Err(e) => HttpResponse::Error(e.to_string()),
-        }
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::Watchtower, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return e.into(),
+        };
+        jobs::spawn(
+            JobKind::Watchtower,
+            &actor,
+            DockerController::start_watchtower(lock),
+        )
+        .await;
+        HttpResponse::CreatedAndRedirect("/")
     }
 }
 
 mod getwatchtower {
+    use crate::auth::audit::Actor;
     use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
     use crate::routes::HttpResponse;
     use axum_typed_routing::route;
 
     #[route(GET "/api/docker/getwatchtower")]
-    pub async fn handler() -> HttpResponse {
-        match DockerController::start_watchtower().await {
-            Ok(()) => HttpResponse::CreatedAndRedirect("/"),
-            Err(e) => HttpResponse::Error(e.to_string()),
-        }
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::Watchtower, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return e.into(),
+        };
+        jobs::spawn(
+            JobKind::Watchtower,
+            &actor,
+            DockerController::start_watchtower(lock),
+        )
+        .await;
+        HttpResponse::CreatedAndRedirect("/")
     }
 }
 
 mod start {
+    use crate::auth::audit::Actor;
     use crate::configuration::StateConfiguration;
     use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
     use crate::routes::HttpResponse;
     use axum::http::Uri;
     use axum::Form;
@@ -53,7 +73,15 @@ mod start {
     }
 
     #[route(POST "/api/docker/start")]
-    pub async fn handler(uri: Uri, form: Form<ApiDockerStartBody>) -> HttpResponse {
+    pub async fn handler(
+        Actor(actor): Actor,
+        uri: Uri,
+        form: Form<ApiDockerStartBody>,
+    ) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::Start, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return e.into(),
+        };
         let uri = uri.authority().map(
             |authority| format!("{}:{}", authority.host(), authority.port_u16().and_then(
                 |port| if port == 8000 {
@@ -68,88 +96,136 @@ mod start {
         config.aio_url = uri;
         config.was_start_button_clicked = true;
         config.install_latest_major = form.install_latest_major.is_some();
-        match DockerController::start_top_container(config, true).await {
-            Ok(()) => HttpResponse::CreatedAndRedirect("/"),
-            Err(e) => HttpResponse::Error(e.to_string()),
-        }
+        jobs::spawn(
+            JobKind::Start,
+            &actor,
+            DockerController::start_top_container(lock, config, true),
+        )
+        .await;
+        HttpResponse::CreatedAndRedirect("/")
     }
 }
 
 mod backup {
+    use crate::auth::audit::Actor;
     use crate::configuration::StateConfiguration;
     use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
     use crate::routes::HttpResponse;
     use axum_typed_routing::route;
 
     #[route(POST "/api/docker/backup")]
-    pub async fn handler() -> HttpResponse {
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::Backup, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return e.into(),
+        };
         let config = StateConfiguration::instance_mut().await;
-        match DockerController::start_backup(config).await {
-            Ok(()) => HttpResponse::CreatedAndRedirect("/"),
-            Err(e) => HttpResponse::Error(e.to_string()),
-        }
+        jobs::spawn(
+            JobKind::Backup,
+            &actor,
+            DockerController::start_backup(lock, config),
+        )
+        .await;
+        HttpResponse::CreatedAndRedirect("/")
     }
 }
 
 mod stop {
+    use crate::auth::audit::Actor;
     use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
     use crate::routes::HttpResponse;
     use axum_typed_routing::route;
 
     #[route(POST "/api/docker/stop")]
-    pub async fn handler() -> HttpResponse {
-        match DockerController::stop_top_container().await {
-            Ok(()) => HttpResponse::CreatedAndRedirect("/"),
-            Err(e) => HttpResponse::Error(e.to_string()),
-        }
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::Stop, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return e.into(),
+        };
+        jobs::spawn(
+            JobKind::Stop,
+            &actor,
+            DockerController::stop_top_container(lock),
+        )
+        .await;
+        HttpResponse::CreatedAndRedirect("/")
     }
 }
 
 mod backup_check {
+    use crate::auth::audit::Actor;
     use crate::configuration::StateConfiguration;
     use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
     use crate::routes::HttpResponse;
     use axum_typed_routing::route;
 
     #[route(POST "/api/docker/backup-check")]
-    pub async fn handler() -> HttpResponse {
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::BackupCheck, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return e.into(),
+        };
         let config = StateConfiguration::instance_mut().await;
-        match DockerController::check_backup(config).await {
-            Ok(()) => HttpResponse::CreatedAndRedirect("/"),
-            Err(e) => HttpResponse::Error(e.to_string()),
-        }
+        jobs::spawn(
+            JobKind::BackupCheck,
+            &actor,
+            DockerController::check_backup(lock, config),
+        )
+        .await;
+        HttpResponse::CreatedAndRedirect("/")
     }
 }
 
 mod backup_check_repair {
+    use crate::auth::audit::Actor;
     use crate::configuration::StateConfiguration;
     use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
     use crate::routes::HttpResponse;
     use axum_typed_routing::route;
 
     #[route(POST "/api/docker/backup-check-repair")]
-    pub async fn handler() -> HttpResponse {
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::BackupCheckRepair, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return e.into(),
+        };
         let config = StateConfiguration::instance_mut().await;
-        match DockerController::repair_backup(config).await {
-            Ok(()) => HttpResponse::CreatedAndRedirect("/"),
-            Err(e) => HttpResponse::Error(e.to_string()),
-        }
+        jobs::spawn(
+            JobKind::BackupCheckRepair,
+            &actor,
+            DockerController::repair_backup(lock, config),
+        )
+        .await;
+        HttpResponse::CreatedAndRedirect("/")
     }
 }
 
 mod backup_test {
+    use crate::auth::audit::Actor;
     use crate::configuration::StateConfiguration;
     use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
     use crate::routes::HttpResponse;
     use axum_typed_routing::route;
 
     #[route(POST "/api/docker/backup-test")]
-    pub async fn handler() -> HttpResponse {
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::BackupTest, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return e.into(),
+        };
         let config = StateConfiguration::instance_mut().await;
-        match DockerController::test_backup(config).await {
-            Ok(()) => HttpResponse::CreatedAndRedirect("/"),
-            Err(e) => HttpResponse::Error(e.to_string()),
-        }
+        jobs::spawn(
+            JobKind::BackupTest,
+            &actor,
+            DockerController::test_backup(lock, config),
+        )
+        .await;
+        HttpResponse::CreatedAndRedirect("/")
     }
 }
 
@@ -0,0 +1,25 @@
+pub use stream::handler as stream_handler;
+
+mod stream {
+    use crate::auth::api_tokens::ApiToken;
+    use crate::auth::session_id;
+    use crate::auth::sessions::Holder;
+    use crate::container::events;
+    use crate::routes::HttpResponse;
+    use axum::Extension;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[route(GET "/api/events")]
+    pub async fn handler(session: Session, token: Option<Extension<ApiToken>>) -> HttpResponse {
+        let holder = match token {
+            Some(Extension(token)) => Holder::Token(token.id),
+            None => match session_id(&session).await {
+                Ok(Some(id)) => Holder::Session(id),
+                Ok(None) => return HttpResponse::UnauthorizedRedirect("/login"),
+                Err(e) => return HttpResponse::Error(e.to_string()),
+            },
+        };
+        HttpResponse::Events(events::subscribe(), holder)
+    }
+}
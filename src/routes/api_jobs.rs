@@ -0,0 +1,22 @@
+pub use cancel::handler as cancel_handler;
+
+mod cancel {
+    use crate::container::jobs::Jobs;
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct CancelForm {
+        pub id: u64,
+    }
+
+    #[route(POST "/api/jobs/cancel")]
+    pub async fn handler(Form(CancelForm { id }): Form<CancelForm>) -> HttpResponse {
+        match Jobs::instance().await.lock().await.cancel(id) {
+            Ok(_) => HttpResponse::CreatedAndRedirect("/jobs"),
+            Err(e) => HttpResponse::UnprocessableEntity(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,59 @@
+pub use generate::handler as generate_handler;
+
+mod generate {
+    use crate::auth::password_generator::{generate, PasswordError, PasswordOptions};
+    use crate::configuration::StateConfiguration;
+    use crate::routes::HttpResponse;
+    use axum::extract::Query;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct GenerateQuery {
+        pub word_list: Option<String>,
+        pub entropy: Option<String>,
+        pub separator: Option<String>,
+        pub capitalization: Option<String>,
+    }
+
+    fn options(
+        query: GenerateQuery,
+        mut options: PasswordOptions,
+    ) -> Result<PasswordOptions, String> {
+        if let Some(word_list) = query.word_list {
+            options.word_list = word_list;
+        }
+        if let Some(entropy) = query.entropy {
+            options.entropy = entropy
+                .trim()
+                .parse()
+                .map_err(|_| format!("The value '{entropy}' is not valid for entropy."))?;
+        }
+        if let Some(separator) = query.separator {
+            options.separator = separator
+                .parse()
+                .map_err(|e: PasswordError| e.to_string())?;
+        }
+        if let Some(capitalization) = query.capitalization {
+            options.capitalization = capitalization
+                .parse()
+                .map_err(|e: PasswordError| e.to_string())?;
+        }
+        Ok(options)
+    }
+
+    // Unset parameters fall back to the configured passphrase options.
+    #[route(GET "/api/password/generate")]
+    pub async fn handler(Query(query): Query<GenerateQuery>) -> HttpResponse {
+        let configured = PasswordOptions::from(&*StateConfiguration::instance_ref().await);
+        let passphrase = options(query, configured)
+            .and_then(|options| generate(&options).map_err(|e| e.to_string()));
+        match passphrase {
+            Ok(passphrase) => match serde_json::to_value(passphrase) {
+                Ok(body) => HttpResponse::Json(body),
+                Err(e) => HttpResponse::Error(e.to_string()),
+            },
+            Err(e) => HttpResponse::UnprocessableEntity(e),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+pub use revoke::handler as revoke_handler;
+pub use revoke_others::handler as revoke_others_handler;
+
+mod revoke {
+    use crate::auth::api_tokens::ApiTokens;
+    use crate::auth::sessions::Sessions;
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct RevokeForm {
+        pub id: String,
+    }
+
+    #[route(POST "/api/sessions/revoke")]
+    pub async fn handler(Form(RevokeForm { id }): Form<RevokeForm>) -> HttpResponse {
+        let Some(session) = Sessions::instance().await.lock().await.revoke(&id) else {
+            return HttpResponse::UnprocessableEntity(String::from(
+                "The session does not exist anymore.",
+            ));
+        };
+        if let Some(token) = session.token_id() {
+            ApiTokens::instance().await.lock().await.revoke(token);
+        }
+        HttpResponse::CreatedAndRedirect("/sessions")
+    }
+}
+
+mod revoke_others {
+    use crate::auth::session_id;
+    use crate::auth::sessions::Sessions;
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[route(POST "/api/sessions/revoke-others")]
+    pub async fn handler(session: Session) -> HttpResponse {
+        let current = match session_id(&session).await {
+            Ok(id) => id,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        Sessions::instance()
+            .await
+            .lock()
+            .await
+            .revoke_others(current.as_deref(), None);
+        HttpResponse::CreatedAndRedirect("/sessions")
+    }
+}
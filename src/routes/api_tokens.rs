@@ -0,0 +1,59 @@
+pub use create::handler as create_handler;
+pub use revoke::handler as revoke_handler;
+
+mod create {
+    use crate::auth::api_tokens::{ApiTokens, Scope};
+    use crate::routes::view::tokens_page;
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use std::collections::HashMap;
+    use tower_sessions::Session;
+
+    // Every scope is a checkbox named after the scope, e.g. `containers:control=on`. The form is
+    // submitted without XHR, so that the page with the new token is the response.
+    #[route(POST "/api/tokens")]
+    pub async fn handler(
+        session: Session,
+        Form(form): Form<HashMap<String, String>>,
+    ) -> HttpResponse {
+        let name = form.get("name").map(String::as_str).unwrap_or_default();
+        let scopes = Scope::ALL
+            .into_iter()
+            .filter(|scope| form.contains_key(scope.as_str()))
+            .collect();
+        let result = ApiTokens::instance()
+            .await
+            .lock()
+            .await
+            .create(name, scopes);
+        match result {
+            Ok(token) => tokens_page(&session, Some(token), None).await,
+            Err(e) => tokens_page(&session, None, Some(e)).await,
+        }
+    }
+}
+
+mod revoke {
+    use crate::auth::api_tokens::ApiTokens;
+    use crate::auth::sessions::Sessions;
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct RevokeForm {
+        pub id: String,
+    }
+
+    #[route(POST "/api/tokens/revoke")]
+    pub async fn handler(Form(RevokeForm { id }): Form<RevokeForm>) -> HttpResponse {
+        if ApiTokens::instance().await.lock().await.revoke(&id) {
+            Sessions::instance().await.lock().await.forget_token(&id);
+            HttpResponse::CreatedAndRedirect("/tokens")
+        } else {
+            HttpResponse::UnprocessableEntity(String::from("The API token does not exist."))
+        }
+    }
+}
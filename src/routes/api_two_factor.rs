@@ -0,0 +1,131 @@
+pub use disable::handler as disable_handler;
+pub use enable::handler as enable_handler;
+pub use recovery_codes::handler as recovery_codes_handler;
+
+use crate::auth::current_user;
+use crate::routes::HttpResponse;
+use serde::Deserialize;
+use tower_sessions::Session;
+
+#[derive(Deserialize)]
+pub struct CodeForm {
+    pub code: String,
+}
+
+// Everybody manages the second factor of the account they are logged in with.
+async fn account(session: &Session) -> Result<String, HttpResponse> {
+    match current_user(session).await {
+        Ok(Some(user)) => Ok(user.name),
+        Ok(None) => Err(HttpResponse::UnauthorizedRedirect("/login")),
+        Err(e) => Err(HttpResponse::Error(e.to_string())),
+    }
+}
+
+mod enable {
+    use super::{account, CodeForm};
+    use crate::auth::two_factor::{
+        TwoFactor, PENDING_SECRET_SESSION_KEY, RECOVERY_CODES_SESSION_KEY,
+    };
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[route(POST "/api/two-factor/enable")]
+    pub async fn handler(
+        session: Session,
+        Form(CodeForm { code }): Form<CodeForm>,
+    ) -> HttpResponse {
+        let account = match account(&session).await {
+            Ok(account) => account,
+            Err(response) => return response,
+        };
+        let secret: String =
+            match session.get(PENDING_SECRET_SESSION_KEY).await {
+                Ok(Some(secret)) => secret,
+                Ok(None) => return HttpResponse::UnprocessableEntity(String::from(
+                    "The enrollment has expired. Please reload the page and scan the new QR code.",
+                )),
+                Err(e) => return HttpResponse::Error(e.to_string()),
+            };
+        let codes = match TwoFactor::instance()
+            .await
+            .lock()
+            .await
+            .enable(&account, &secret, &code)
+        {
+            Ok(codes) => codes,
+            Err(e) => return HttpResponse::UnprocessableEntity(e),
+        };
+        let result = async {
+            session.remove::<String>(PENDING_SECRET_SESSION_KEY).await?;
+            session.insert(RECOVERY_CODES_SESSION_KEY, codes).await
+        };
+        match result.await {
+            Ok(()) => HttpResponse::CreatedAndRedirect("/two-factor"),
+            Err(e) => HttpResponse::Error(e.to_string()),
+        }
+    }
+}
+
+mod disable {
+    use super::{account, CodeForm};
+    use crate::auth::two_factor::TwoFactor;
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[route(POST "/api/two-factor/disable")]
+    pub async fn handler(
+        session: Session,
+        Form(CodeForm { code }): Form<CodeForm>,
+    ) -> HttpResponse {
+        let account = match account(&session).await {
+            Ok(account) => account,
+            Err(response) => return response,
+        };
+        match TwoFactor::instance()
+            .await
+            .lock()
+            .await
+            .disable(&account, &code)
+        {
+            Ok(()) => HttpResponse::CreatedAndRedirect("/two-factor"),
+            Err(e) => HttpResponse::UnprocessableEntity(e),
+        }
+    }
+}
+
+mod recovery_codes {
+    use super::{account, CodeForm};
+    use crate::auth::two_factor::{TwoFactor, RECOVERY_CODES_SESSION_KEY};
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[route(POST "/api/two-factor/recovery-codes")]
+    pub async fn handler(
+        session: Session,
+        Form(CodeForm { code }): Form<CodeForm>,
+    ) -> HttpResponse {
+        let account = match account(&session).await {
+            Ok(account) => account,
+            Err(response) => return response,
+        };
+        let result = TwoFactor::instance()
+            .await
+            .lock()
+            .await
+            .regenerate_recovery_codes(&account, &code);
+        let codes = match result {
+            Ok(codes) => codes,
+            Err(e) => return HttpResponse::UnprocessableEntity(e),
+        };
+        match session.insert(RECOVERY_CODES_SESSION_KEY, codes).await {
+            Ok(()) => HttpResponse::CreatedAndRedirect("/two-factor"),
+            Err(e) => HttpResponse::Error(e.to_string()),
+        }
+    }
+}
@@ -0,0 +1,128 @@
+pub use create::handler as create_handler;
+pub use delete::handler as delete_handler;
+pub use password::handler as password_handler;
+pub use role::handler as role_handler;
+
+mod create {
+    use crate::auth::users::{Role, Users};
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct UserForm {
+        pub name: String,
+        pub password: String,
+        pub role: Role,
+    }
+
+    #[route(POST "/api/users")]
+    pub async fn handler(
+        Form(UserForm {
+            name,
+            password,
+            role,
+        }): Form<UserForm>,
+    ) -> HttpResponse {
+        let result = Users::instance()
+            .await
+            .lock()
+            .await
+            .create(&name, &password, role);
+        match result {
+            Ok(()) => HttpResponse::CreatedAndRedirect("/users"),
+            Err(e) => HttpResponse::UnprocessableEntity(e),
+        }
+    }
+}
+
+mod role {
+    use crate::auth::users::{Role, Users};
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct RoleForm {
+        pub name: String,
+        pub role: Role,
+    }
+
+    #[route(POST "/api/users/role")]
+    pub async fn handler(Form(RoleForm { name, role }): Form<RoleForm>) -> HttpResponse {
+        match Users::instance().await.lock().await.set_role(&name, role) {
+            Ok(()) => HttpResponse::CreatedAndRedirect("/users"),
+            Err(e) => HttpResponse::UnprocessableEntity(e),
+        }
+    }
+}
+
+mod password {
+    use crate::auth::audit::{record, Actor, AuditEntry, AuditEvent, Outcome};
+    use crate::auth::client_ip::ClientIp;
+    use crate::auth::sessions::Sessions;
+    use crate::auth::users::Users;
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct PasswordForm {
+        pub name: String,
+        pub password: String,
+    }
+
+    #[route(POST "/api/users/password")]
+    pub async fn handler(
+        client: ClientIp,
+        Actor(actor): Actor,
+        Form(PasswordForm { name, password }): Form<PasswordForm>,
+    ) -> HttpResponse {
+        let result = Users::instance()
+            .await
+            .lock()
+            .await
+            .set_password(&name, &password);
+        if result.is_ok() {
+            let mut sessions = Sessions::instance().await.lock().await;
+            sessions.revoke_others(None, Some(&name));
+        }
+        let outcome = Outcome::of(result.is_ok());
+        let entry = AuditEntry::new(AuditEvent::PasswordChange, &actor, client, outcome);
+        record(entry.detail(format!("user {}", name))).await;
+        match result {
+            Ok(()) => HttpResponse::CreatedAndRedirect("/users"),
+            Err(e) => HttpResponse::UnprocessableEntity(e),
+        }
+    }
+}
+
+mod delete {
+    use crate::auth::sessions::Sessions;
+    use crate::auth::two_factor::TwoFactor;
+    use crate::auth::users::Users;
+    use crate::routes::HttpResponse;
+    use axum::Form;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct DeleteForm {
+        pub name: String,
+    }
+
+    #[route(POST "/api/users/delete")]
+    pub async fn handler(Form(DeleteForm { name }): Form<DeleteForm>) -> HttpResponse {
+        if Users::instance().await.lock().await.remove(&name) {
+            let mut sessions = Sessions::instance().await.lock().await;
+            sessions.revoke_others(None, Some(&name));
+            TwoFactor::instance().await.lock().await.remove(&name);
+            HttpResponse::CreatedAndRedirect("/users")
+        } else {
+            HttpResponse::UnprocessableEntity(String::from("The user does not exist."))
+        }
+    }
+}
@@ -0,0 +1,1019 @@
+pub use backup_check::handler as backup_check_handler;
+pub use backup_check_repair::handler as backup_check_repair_handler;
+pub use backup_create::handler as backup_create_handler;
+pub use backup_test::handler as backup_test_handler;
+pub use backups::handler as backups_handler;
+pub use configuration::handler as configuration_handler;
+pub use configuration_update::handler as configuration_update_handler;
+pub use containers::handler as containers_handler;
+pub use job::handler as job_handler;
+pub use job_cancel::handler as job_cancel_handler;
+pub use jobs::handler as jobs_handler;
+pub use logs::handler as logs_handler;
+pub use openapi::handler as openapi_handler;
+pub use start::handler as start_handler;
+pub use stop::handler as stop_handler;
+pub use updates::handler as updates_handler;
+
+use crate::container::jobs::{Job, JobError};
+use crate::container::lock::LockError;
+use crate::routes::HttpResponse;
+use axum::body::{to_bytes, Body};
+use axum::extract::Request;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+pub const PREFIX: &str = "/api/v1/";
+
+// Rejections are plain text and never longer than a sentence or two.
+const REJECTION_LIMIT: usize = 64 * 1024;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    BadRequest,
+    Unauthorized,
+    Forbidden,
+    NotFound,
+    MethodNotAllowed,
+    UnsupportedMediaType,
+    InvalidValue,
+    UnknownKey,
+    ReadOnly,
+    EnvironmentOverride,
+    NotCancellable,
+    JobFinished,
+    OperationInProgress,
+    TooManyRequests,
+    DockerUnavailable,
+    InternalError,
+}
+
+pub struct ApiError {
+    code: ErrorCode,
+    message: String,
+}
+
+impl ErrorCode {
+    pub const ALL: [ErrorCode; 16] = [
+        ErrorCode::BadRequest,
+        ErrorCode::Unauthorized,
+        ErrorCode::Forbidden,
+        ErrorCode::NotFound,
+        ErrorCode::MethodNotAllowed,
+        ErrorCode::UnsupportedMediaType,
+        ErrorCode::InvalidValue,
+        ErrorCode::UnknownKey,
+        ErrorCode::ReadOnly,
+        ErrorCode::EnvironmentOverride,
+        ErrorCode::NotCancellable,
+        ErrorCode::JobFinished,
+        ErrorCode::OperationInProgress,
+        ErrorCode::TooManyRequests,
+        ErrorCode::DockerUnavailable,
+        ErrorCode::InternalError,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::BadRequest => "bad_request",
+            ErrorCode::Unauthorized => "unauthorized",
+            ErrorCode::Forbidden => "forbidden",
+            ErrorCode::NotFound => "not_found",
+            ErrorCode::MethodNotAllowed => "method_not_allowed",
+            ErrorCode::UnsupportedMediaType => "unsupported_media_type",
+            ErrorCode::InvalidValue => "invalid_value",
+            ErrorCode::UnknownKey => "unknown_key",
+            ErrorCode::ReadOnly => "read_only",
+            ErrorCode::EnvironmentOverride => "environment_override",
+            ErrorCode::NotCancellable => "not_cancellable",
+            ErrorCode::JobFinished => "job_finished",
+            ErrorCode::OperationInProgress => "operation_in_progress",
+            ErrorCode::TooManyRequests => "too_many_requests",
+            ErrorCode::DockerUnavailable => "docker_unavailable",
+            ErrorCode::InternalError => "internal_error",
+        }
+    }
+
+    pub fn status(&self) -> StatusCode {
+        match self {
+            ErrorCode::BadRequest => StatusCode::BAD_REQUEST,
+            ErrorCode::Unauthorized => StatusCode::UNAUTHORIZED,
+            ErrorCode::Forbidden => StatusCode::FORBIDDEN,
+            ErrorCode::NotFound => StatusCode::NOT_FOUND,
+            ErrorCode::MethodNotAllowed => StatusCode::METHOD_NOT_ALLOWED,
+            ErrorCode::UnsupportedMediaType => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ErrorCode::InvalidValue | ErrorCode::UnknownKey | ErrorCode::ReadOnly => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            ErrorCode::EnvironmentOverride
+            | ErrorCode::NotCancellable
+            | ErrorCode::JobFinished
+            | ErrorCode::OperationInProgress => StatusCode::CONFLICT,
+            ErrorCode::TooManyRequests => StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::DockerUnavailable => StatusCode::BAD_GATEWAY,
+            ErrorCode::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // For the rejections of the middleware and the extractors, which only come with a status.
+    fn of(status: StatusCode) -> Self {
+        match status {
+            StatusCode::UNAUTHORIZED => ErrorCode::Unauthorized,
+            StatusCode::FORBIDDEN => ErrorCode::Forbidden,
+            StatusCode::NOT_FOUND => ErrorCode::NotFound,
+            StatusCode::METHOD_NOT_ALLOWED => ErrorCode::MethodNotAllowed,
+            StatusCode::UNSUPPORTED_MEDIA_TYPE => ErrorCode::UnsupportedMediaType,
+            StatusCode::UNPROCESSABLE_ENTITY => ErrorCode::InvalidValue,
+            StatusCode::TOO_MANY_REQUESTS => ErrorCode::TooManyRequests,
+            _ if status.is_server_error() => ErrorCode::InternalError,
+            _ => ErrorCode::BadRequest,
+        }
+    }
+}
+
+impl ApiError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        ApiError {
+            code,
+            message: message.into(),
+        }
+    }
+
+    fn body(&self) -> Json<serde_json::Value> {
+        Json(json!({ "error": { "code": self.code, "message": self.message } }))
+    }
+}
+
+impl From<reqwest::Error> for ApiError {
+    fn from(e: reqwest::Error) -> Self {
+        ApiError::new(ErrorCode::DockerUnavailable, e.to_string())
+    }
+}
+
+impl From<ApiError> for HttpResponse {
+    fn from(e: ApiError) -> Self {
+        HttpResponse::ApiError(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.code.status(), self.body()).into_response()
+    }
+}
+
+// The authentication, CSRF and extractor rejections are plain text, which is turned into the same
+// error object the handlers return. Other headers, like Retry-After, are kept.
+pub async fn json_errors(request: Request, next: Next) -> Response {
+    if !request.uri().path().starts_with(PREFIX) {
+        return next.run(request).await;
+    }
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+    if is_json || !(response.status().is_client_error() || response.status().is_server_error()) {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let text = to_bytes(body, REJECTION_LIMIT)
+        .await
+        .map(|bytes| String::from_utf8_lossy(&bytes).trim().to_string())
+        .unwrap_or_default();
+    let message = if text.is_empty() {
+        parts
+            .status
+            .canonical_reason()
+            .unwrap_or_default()
+            .to_string()
+    } else {
+        text
+    };
+    let error = ApiError::new(ErrorCode::of(parts.status), message);
+    let body = serde_json::to_vec(&error.body().0).unwrap_or_default();
+    parts.headers.remove(header::LOCATION);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    Response::from_parts(parts, Body::from(body))
+}
+
+// Operations run as jobs, the response only tells where to follow them.
+fn accepted(job: Job) -> HttpResponse {
+    HttpResponse::Accepted(format!("/api/v1/jobs/detail?id={}", job.id), json!(job))
+}
+
+impl From<LockError> for ApiError {
+    fn from(e: LockError) -> Self {
+        let code = match e {
+            LockError::Busy(_) => ErrorCode::OperationInProgress,
+            LockError::Io(_) => ErrorCode::InternalError,
+        };
+        ApiError::new(code, e.to_string())
+    }
+}
+
+impl From<JobError> for ApiError {
+    fn from(e: JobError) -> Self {
+        let code = match e {
+            JobError::NotFound(_) => ErrorCode::NotFound,
+            JobError::NotCancellable(_) => ErrorCode::NotCancellable,
+            JobError::Finished(_) => ErrorCode::JobFinished,
+        };
+        ApiError::new(code, e.to_string())
+    }
+}
+
+mod containers {
+    use crate::container::controller::DockerController;
+    use crate::routes::api_v1::ApiError;
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+    use serde_json::json;
+
+    #[route(GET "/api/v1/containers")]
+    pub async fn handler() -> HttpResponse {
+        match DockerController::status().await {
+            Ok(status) => HttpResponse::Json(json!(status
+                .iter()
+                .map(|(c, state)| json!({
+                    "id": c.identifier,
+                    "display_name": c.display_name,
+                    "state": state,
+                }))
+                .collect::<Vec<_>>())),
+            Err(e) => ApiError::from(e).into(),
+        }
+    }
+}
+
+mod logs {
+    use crate::container::controller::DockerController;
+    use crate::routes::api_v1::{ApiError, ErrorCode};
+    use crate::routes::HttpResponse;
+    use axum::extract::Query;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    #[derive(Deserialize)]
+    pub struct LogsQuery {
+        id: String,
+    }
+
+    #[route(GET "/api/v1/containers/logs")]
+    pub async fn handler(Query(LogsQuery { id }): Query<LogsQuery>) -> HttpResponse {
+        if !id.starts_with("nextcloud-aio-") {
+            return ApiError::new(
+                ErrorCode::NotFound,
+                format!("{} is not an AIO container.", id),
+            )
+            .into();
+        }
+        match DockerController::logs(&id).await {
+            Ok(logs) => HttpResponse::Json(json!({ "id": id, "logs": logs })),
+            Err(e) => ApiError::from(e).into(),
+        }
+    }
+}
+
+mod start {
+    use crate::auth::audit::Actor;
+    use crate::configuration::StateConfiguration;
+    use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
+    use crate::routes::api_v1::{accepted, ApiError};
+    use crate::routes::HttpResponse;
+    use axum::Json;
+    use axum_typed_routing::route;
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct StartRequest {
+        #[serde(default = "pull_by_default")]
+        pull: bool,
+    }
+
+    fn pull_by_default() -> bool {
+        true
+    }
+
+    // The body is optional, without one the images are pulled like on the start button.
+    #[route(POST "/api/v1/containers/start")]
+    pub async fn handler(Actor(actor): Actor, request: Option<Json<StartRequest>>) -> HttpResponse {
+        let pull = request.is_none_or(|Json(request)| request.pull);
+        let lock = match DockerController::lock(JobKind::Start, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return ApiError::from(e).into(),
+        };
+        let config = StateConfiguration::instance_mut().await;
+        accepted(
+            jobs::spawn(
+                JobKind::Start,
+                &actor,
+                DockerController::start_top_container(lock, config, pull),
+            )
+            .await,
+        )
+    }
+}
+
+mod stop {
+    use crate::auth::audit::Actor;
+    use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
+    use crate::routes::api_v1::{accepted, ApiError};
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+
+    #[route(POST "/api/v1/containers/stop")]
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::Stop, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return ApiError::from(e).into(),
+        };
+        accepted(
+            jobs::spawn(
+                JobKind::Stop,
+                &actor,
+                DockerController::stop_top_container(lock),
+            )
+            .await,
+        )
+    }
+}
+
+mod backups {
+    use crate::data::backup_archives::get_backup_archives;
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+    use serde_json::json;
+
+    #[route(GET "/api/v1/backups")]
+    pub async fn handler() -> HttpResponse {
+        HttpResponse::Json(json!(get_backup_archives()
+            .iter()
+            .map(|archive| json!({ "name": archive.name, "time": archive.time_string() }))
+            .collect::<Vec<_>>()))
+    }
+}
+
+mod backup_create {
+    use crate::auth::audit::Actor;
+    use crate::configuration::StateConfiguration;
+    use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
+    use crate::routes::api_v1::{accepted, ApiError};
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+
+    #[route(POST "/api/v1/backups")]
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::Backup, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return ApiError::from(e).into(),
+        };
+        let config = StateConfiguration::instance_mut().await;
+        accepted(
+            jobs::spawn(
+                JobKind::Backup,
+                &actor,
+                DockerController::start_backup(lock, config),
+            )
+            .await,
+        )
+    }
+}
+
+mod backup_check {
+    use crate::auth::audit::Actor;
+    use crate::configuration::StateConfiguration;
+    use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
+    use crate::routes::api_v1::{accepted, ApiError};
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+
+    #[route(POST "/api/v1/backups/check")]
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::BackupCheck, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return ApiError::from(e).into(),
+        };
+        let config = StateConfiguration::instance_mut().await;
+        accepted(
+            jobs::spawn(
+                JobKind::BackupCheck,
+                &actor,
+                DockerController::check_backup(lock, config),
+            )
+            .await,
+        )
+    }
+}
+
+mod backup_check_repair {
+    use crate::auth::audit::Actor;
+    use crate::configuration::StateConfiguration;
+    use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
+    use crate::routes::api_v1::{accepted, ApiError};
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+
+    #[route(POST "/api/v1/backups/check-repair")]
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::BackupCheckRepair, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return ApiError::from(e).into(),
+        };
+        let config = StateConfiguration::instance_mut().await;
+        accepted(
+            jobs::spawn(
+                JobKind::BackupCheckRepair,
+                &actor,
+                DockerController::repair_backup(lock, config),
+            )
+            .await,
+        )
+    }
+}
+
+mod backup_test {
+    use crate::auth::audit::Actor;
+    use crate::configuration::StateConfiguration;
+    use crate::container::controller::DockerController;
+    use crate::container::jobs::{self, JobKind};
+    use crate::routes::api_v1::{accepted, ApiError};
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+
+    #[route(POST "/api/v1/backups/test")]
+    pub async fn handler(Actor(actor): Actor) -> HttpResponse {
+        let lock = match DockerController::lock(JobKind::BackupTest, &actor) {
+            Ok(lock) => lock,
+            Err(e) => return ApiError::from(e).into(),
+        };
+        let config = StateConfiguration::instance_mut().await;
+        accepted(
+            jobs::spawn(
+                JobKind::BackupTest,
+                &actor,
+                DockerController::test_backup(lock, config),
+            )
+            .await,
+        )
+    }
+}
+
+mod configuration {
+    use crate::configuration::{ConfigurationSource, StateConfiguration, KEYS, SECRET_KEYS};
+    use crate::routes::api_v1::{ApiError, ErrorCode};
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+    use serde::Serialize;
+    use serde_json::{json, Value};
+
+    #[derive(Serialize)]
+    struct Entry {
+        key: &'static str,
+        value: Option<Value>,
+        #[serde(flatten)]
+        source: ConfigurationSource,
+    }
+
+    // Secrets are left out, the passphrase hash included.
+    #[route(GET "/api/v1/configuration")]
+    pub async fn handler() -> HttpResponse {
+        let config = StateConfiguration::instance_ref().await;
+        let mut entries = Vec::new();
+        for key in KEYS.iter().filter(|key| !SECRET_KEYS.contains(key)) {
+            match config.get(key) {
+                Ok(value) => entries.push(Entry {
+                    key,
+                    value,
+                    source: config.source(key),
+                }),
+                Err(e) => return ApiError::new(ErrorCode::InternalError, e).into(),
+            }
+        }
+        HttpResponse::Json(json!(entries))
+    }
+}
+
+mod configuration_update {
+    use crate::configuration::{ConfigurationSource, StateConfiguration, API_WRITABLE_KEYS, KEYS};
+    use crate::routes::api_v1::{ApiError, ErrorCode};
+    use crate::routes::HttpResponse;
+    use axum::Json;
+    use axum_typed_routing::route;
+    use serde_json::{json, Map, Value};
+
+    // Either every value is applied or none, so a typo never leaves half a change behind.
+    #[route(POST "/api/v1/configuration")]
+    pub async fn handler(Json(values): Json<Map<String, Value>>) -> HttpResponse {
+        let mut config = StateConfiguration::instance_mut().await;
+        let mut updated = (*config).clone();
+        for (key, value) in &values {
+            if !KEYS.contains(&key.as_str()) {
+                return ApiError::new(
+                    ErrorCode::UnknownKey,
+                    format!("There is no configuration key called {}.", key),
+                )
+                .into();
+            }
+            if !API_WRITABLE_KEYS.contains(&key.as_str()) {
+                return ApiError::new(
+                    ErrorCode::ReadOnly,
+                    format!("{} cannot be changed through the API.", key),
+                )
+                .into();
+            }
+            if let ConfigurationSource::Environment(variable) = config.source(key) {
+                return ApiError::new(
+                    ErrorCode::EnvironmentOverride,
+                    format!(
+                        "{key} is set by the environment variable {variable} and cannot be changed here."
+                    ),
+                )
+                .into();
+            }
+            let value = match value {
+                Value::String(value) => value.clone(),
+                value => value.to_string(),
+            };
+            if let Err(e) = updated.set(key, &value) {
+                return ApiError::new(ErrorCode::InvalidValue, e).into();
+            }
+        }
+        *config = updated;
+        config.commit();
+        HttpResponse::Json(json!({ "updated": values.keys().collect::<Vec<_>>() }))
+    }
+}
+
+mod updates {
+    use crate::container::controller::DockerController;
+    use crate::routes::api_v1::ApiError;
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+    use serde_json::json;
+
+    #[route(GET "/api/v1/updates")]
+    pub async fn handler() -> HttpResponse {
+        match DockerController::update_report().await {
+            Ok(report) => HttpResponse::Json(json!(report)),
+            Err(e) => ApiError::from(e).into(),
+        }
+    }
+}
+
+mod jobs {
+    use crate::container::jobs::Jobs;
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+    use serde_json::json;
+
+    #[route(GET "/api/v1/jobs")]
+    pub async fn handler() -> HttpResponse {
+        HttpResponse::Json(json!(Jobs::instance().await.lock().await.list()))
+    }
+}
+
+#[derive(Deserialize)]
+pub struct JobQuery {
+    id: u64,
+}
+
+mod job {
+    use crate::container::jobs::{JobError, Jobs};
+    use crate::routes::api_v1::{ApiError, JobQuery};
+    use crate::routes::HttpResponse;
+    use axum::extract::Query;
+    use axum_typed_routing::route;
+    use serde_json::json;
+
+    #[route(GET "/api/v1/jobs/detail")]
+    pub async fn handler(Query(JobQuery { id }): Query<JobQuery>) -> HttpResponse {
+        match Jobs::instance().await.lock().await.get(id) {
+            Some(job) => HttpResponse::Json(json!(job)),
+            None => ApiError::from(JobError::NotFound(id)).into(),
+        }
+    }
+}
+
+mod job_cancel {
+    use crate::container::jobs::Jobs;
+    use crate::routes::api_v1::{ApiError, JobQuery};
+    use crate::routes::HttpResponse;
+    use axum::extract::Query;
+    use axum_typed_routing::route;
+    use serde_json::json;
+
+    #[route(POST "/api/v1/jobs/cancel")]
+    pub async fn handler(Query(JobQuery { id }): Query<JobQuery>) -> HttpResponse {
+        match Jobs::instance().await.lock().await.cancel(id) {
+            Ok(job) => HttpResponse::Json(json!(job)),
+            Err(e) => ApiError::from(e).into(),
+        }
+    }
+}
+
+mod openapi {
+    use crate::auth::api_tokens::Scope;
+    use crate::configuration::API_WRITABLE_KEYS;
+    use crate::routes::api_v1::ErrorCode;
+    use crate::routes::HttpResponse;
+    use axum_typed_routing::route;
+    use serde_json::{json, Map, Value};
+
+    pub struct Endpoint {
+        pub method: &'static str,
+        pub path: &'static str,
+        pub summary: &'static str,
+        pub status: &'static str,
+        pub scope: Scope,
+        pub query: Option<(&'static str, &'static str)>,
+        pub request: Option<&'static str>,
+        pub response: &'static str,
+    }
+
+    // The document is generated from this list, which has to follow the routes above.
+    pub const ENDPOINTS: &[Endpoint] = &[
+        Endpoint {
+            method: "get",
+            path: "/api/v1/containers",
+            summary: "State of every container",
+            status: "200",
+            scope: Scope::Read,
+            query: None,
+            request: None,
+            response: "Containers",
+        },
+        Endpoint {
+            method: "get",
+            path: "/api/v1/containers/logs",
+            summary: "Logs of a container",
+            status: "200",
+            scope: Scope::Read,
+            query: Some(("id", "The container, e.g. nextcloud-aio-nextcloud")),
+            request: None,
+            response: "Logs",
+        },
+        Endpoint {
+            method: "post",
+            path: "/api/v1/containers/start",
+            summary: "Start the containers",
+            status: "202",
+            scope: Scope::ContainersControl,
+            query: None,
+            request: Some("StartRequest"),
+            response: "Job",
+        },
+        Endpoint {
+            method: "post",
+            path: "/api/v1/containers/stop",
+            summary: "Stop the containers",
+            status: "202",
+            scope: Scope::ContainersControl,
+            query: None,
+            request: None,
+            response: "Job",
+        },
+        Endpoint {
+            method: "get",
+            path: "/api/v1/backups",
+            summary: "The backup archives",
+            status: "200",
+            scope: Scope::Read,
+            query: None,
+            request: None,
+            response: "Backups",
+        },
+        Endpoint {
+            method: "post",
+            path: "/api/v1/backups",
+            summary: "Stop the containers and create a backup",
+            status: "202",
+            scope: Scope::Backup,
+            query: None,
+            request: None,
+            response: "Job",
+        },
+        Endpoint {
+            method: "post",
+            path: "/api/v1/backups/check",
+            summary: "Check the integrity of the backup archives",
+            status: "202",
+            scope: Scope::Backup,
+            query: None,
+            request: None,
+            response: "Job",
+        },
+        Endpoint {
+            method: "post",
+            path: "/api/v1/backups/check-repair",
+            summary: "Check and repair the backup archives",
+            status: "202",
+            scope: Scope::Backup,
+            query: None,
+            request: None,
+            response: "Job",
+        },
+        Endpoint {
+            method: "post",
+            path: "/api/v1/backups/test",
+            summary: "Test the backup location",
+            status: "202",
+            scope: Scope::Backup,
+            query: None,
+            request: None,
+            response: "Job",
+        },
+        Endpoint {
+            method: "get",
+            path: "/api/v1/configuration",
+            summary: "Every configuration value with its source, without the secrets",
+            status: "200",
+            scope: Scope::Read,
+            query: None,
+            request: None,
+            response: "Configuration",
+        },
+        Endpoint {
+            method: "post",
+            path: "/api/v1/configuration",
+            summary: "Change the feature and resource settings, all or none of them",
+            status: "200",
+            scope: Scope::ConfigWrite,
+            query: None,
+            request: Some("ConfigurationUpdate"),
+            response: "ConfigurationUpdated",
+        },
+        Endpoint {
+            method: "get",
+            path: "/api/v1/jobs",
+            summary: "The running jobs and the history, newest first",
+            status: "200",
+            scope: Scope::Read,
+            query: None,
+            request: None,
+            response: "Jobs",
+        },
+        Endpoint {
+            method: "get",
+            path: "/api/v1/jobs/detail",
+            summary: "Phase, progress and logs of a job",
+            status: "200",
+            scope: Scope::Read,
+            query: Some(("id", "The id of the job")),
+            request: None,
+            response: "Job",
+        },
+        Endpoint {
+            method: "post",
+            path: "/api/v1/jobs/cancel",
+            summary: "Stop a start or stop job before its next container",
+            status: "200",
+            scope: Scope::ContainersControl,
+            query: Some(("id", "The id of the job")),
+            request: None,
+            response: "Job",
+        },
+        Endpoint {
+            method: "get",
+            path: "/api/v1/updates",
+            summary: "Available image updates",
+            status: "200",
+            scope: Scope::Read,
+            query: None,
+            request: None,
+            response: "UpdateReport",
+        },
+    ];
+
+    fn schemas() -> Value {
+        json!({
+            "Error": {
+                "type": "object",
+                "required": ["error"],
+                "properties": {
+                    "error": {
+                        "type": "object",
+                        "required": ["code", "message"],
+                        "properties": {
+                            "code": {
+                                "type": "string",
+                                "enum": ErrorCode::ALL.iter().map(ErrorCode::as_str).collect::<Vec<_>>(),
+                            },
+                            "message": { "type": "string" },
+                        },
+                    },
+                },
+            },
+            "Containers": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "id": { "type": "string" },
+                        "display_name": { "type": "string" },
+                        "state": {
+                            "type": "string",
+                            "enum": ["image-does-not-exist", "not-restarting", "restarting", "running", "starting", "stopped"],
+                        },
+                    },
+                },
+            },
+            "Logs": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "string" },
+                    "logs": { "type": "string" },
+                },
+            },
+            "StartRequest": {
+                "type": "object",
+                "properties": {
+                    "pull": { "type": "boolean", "default": true },
+                },
+            },
+            "Job": {
+                "type": "object",
+                "properties": {
+                    "id": { "type": "integer" },
+                    "kind": {
+                        "type": "string",
+                        "enum": ["start", "stop", "backup", "backup-check", "backup-check-repair", "backup-test", "watchtower"],
+                    },
+                    "actor": { "type": "string" },
+                    "state": {
+                        "type": "string",
+                        "enum": ["running", "cancelling", "completed", "failed", "cancelled", "interrupted"],
+                    },
+                    "phase": { "type": "string", "enum": ["preparing", "stopping", "starting"] },
+                    "cancellable": { "type": "boolean" },
+                    "containers": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "id": { "type": "string" },
+                                "step": {
+                                    "type": "string",
+                                    "enum": ["stopping", "stopped", "pulling", "starting", "started", "skipped"],
+                                },
+                            },
+                        },
+                    },
+                    "logs": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "time": { "type": "string", "format": "date-time" },
+                                "message": { "type": "string" },
+                            },
+                        },
+                    },
+                    "error": { "type": "string" },
+                    "started_at": { "type": "string", "format": "date-time" },
+                    "finished_at": { "type": "string", "format": "date-time", "nullable": true },
+                },
+            },
+            "Jobs": { "type": "array", "items": schema("Job") },
+            "Backups": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "name": { "type": "string" },
+                        "time": { "type": "string", "example": "2024-11-30 04:00:00" },
+                    },
+                },
+            },
+            "Configuration": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "properties": {
+                        "key": { "type": "string" },
+                        "value": {},
+                        "source": { "type": "string", "enum": ["default", "file", "environment"] },
+                        "variable": { "type": "string" },
+                    },
+                },
+            },
+            "ConfigurationUpdate": {
+                "type": "object",
+                "additionalProperties": {},
+                "propertyNames": { "enum": API_WRITABLE_KEYS },
+                "example": { "timezone": "Europe/Berlin", "nextcloud_upload_limit": "16G" },
+            },
+            "ConfigurationUpdated": {
+                "type": "object",
+                "properties": {
+                    "updated": { "type": "array", "items": { "type": "string" } },
+                },
+            },
+            "UpdateReport": {
+                "type": "object",
+                "properties": {
+                    "channel": { "type": "string" },
+                    "nextcloud_image_outdated": { "type": "boolean" },
+                    "containers": {
+                        "type": "array",
+                        "items": {
+                            "type": "object",
+                            "properties": {
+                                "id": { "type": "string" },
+                                "image": { "type": "string" },
+                                "update_available": { "type": "boolean" },
+                            },
+                        },
+                    },
+                },
+            },
+        })
+    }
+
+    fn schema(name: &str) -> Value {
+        json!({ "$ref": format!("#/components/schemas/{}", name) })
+    }
+
+    fn operation(endpoint: &Endpoint) -> Value {
+        let error = json!({
+            "description": "Error",
+            "content": { "application/json": { "schema": schema("Error") } },
+        });
+        let mut operation = json!({
+            "summary": endpoint.summary,
+            "description": format!("Needs the {} scope.", endpoint.scope),
+            "responses": {
+                endpoint.status: {
+                    "description": "Success",
+                    "content": { "application/json": { "schema": schema(endpoint.response) } },
+                },
+                "default": error,
+            },
+        });
+        if let Some((name, description)) = endpoint.query {
+            operation["parameters"] = json!([{
+                "name": name,
+                "in": "query",
+                "required": true,
+                "description": description,
+                "schema": { "type": "string" },
+            }]);
+        }
+        if let Some(request) = endpoint.request {
+            operation["requestBody"] = json!({
+                "required": request != "StartRequest",
+                "content": { "application/json": { "schema": schema(request) } },
+            });
+        }
+        operation
+    }
+
+    pub fn document() -> Value {
+        let mut paths = Map::new();
+        for endpoint in ENDPOINTS {
+            let path = paths
+                .entry(endpoint.path)
+                .or_insert_with(|| Value::Object(Map::new()));
+            path[endpoint.method] = operation(endpoint);
+        }
+        json!({
+            "openapi": "3.0.3",
+            "info": {
+                "title": "Nextcloud AIO",
+                "version": env!("CARGO_PKG_VERSION"),
+                "description": "Manage the Nextcloud AIO containers. Authenticate with an API token \
+                    in the Authorization header, e.g. `Authorization: Bearer <token>`.",
+            },
+            "servers": [{ "url": "/" }],
+            "security": [{ "bearerAuth": [] }],
+            "paths": paths,
+            "components": {
+                "schemas": schemas(),
+                "securitySchemes": {
+                    "bearerAuth": { "type": "http", "scheme": "bearer" },
+                },
+            },
+        })
+    }
+
+    #[route(GET "/api/v1/openapi.json")]
+    pub async fn handler() -> HttpResponse {
+        HttpResponse::Json(document())
+    }
+}
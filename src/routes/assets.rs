@@ -1,11 +1,6 @@
 use crate::routes::HttpResponse;
 use axum_typed_routing::route;
 
-#[route(GET "/automatic_reload.js")]
-pub async fn automatic_reload_js() -> HttpResponse {
-    HttpResponse::JavaScript(include_str!("../../public/automatic_reload.js"))
-}
-
 #[route(GET "/before-unload.js")]
 pub async fn before_unload_js() -> HttpResponse {
     HttpResponse::JavaScript(include_str!("../../public/before-unload.js"))
@@ -61,6 +56,11 @@ pub async fn forms_js() -> HttpResponse {
     HttpResponse::JavaScript(include_str!("../../public/forms.js"))
 }
 
+#[route(GET "/live-status.js")]
+pub async fn live_status_js() -> HttpResponse {
+    HttpResponse::JavaScript(include_str!("../../public/live-status.js"))
+}
+
 #[route(GET "/options-form-submit.js")]
 pub async fn options_form_submit_js() -> HttpResponse {
     HttpResponse::JavaScript(include_str!("../../public/options-form-submit.js"))
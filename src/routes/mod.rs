@@ -1,12 +1,48 @@
+pub mod api_audit;
 pub mod api_auth;
+pub mod api_backup;
 pub mod api_configuration;
 pub mod api_docker;
+pub mod api_events;
+pub mod api_jobs;
+pub mod api_password;
+pub mod api_sessions;
+pub mod api_tokens;
+pub mod api_two_factor;
+pub mod api_users;
+pub mod api_v1;
 pub mod assets;
 pub mod view;
 
+#[cfg(test)]
+mod tests;
+
+use crate::auth::audit::prune_periodically;
+use crate::auth::auth_middleware;
+use crate::auth::csrf::csrf_middleware;
+use crate::auth::session_store::FileStore;
+use crate::auth::sessions::Holder;
+use crate::configuration::StateConfiguration;
+use crate::container::events::Event;
+use crate::container::lock::LockError;
+use crate::data::data_const::get_session_directory;
+use api_v1::{json_errors, ApiError};
 use askama::Template;
 use axum::http::{header, StatusCode};
+use axum::response::sse::{self, KeepAlive, Sse};
 use axum::response::{Html, IntoResponse, Response};
+use axum::routing::MethodRouter;
+use axum::{middleware, Json, Router};
+use axum_typed_routing::TypedRouter;
+use futures_util::stream;
+use time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tower_sessions::{Expiry, SessionManagerLayer, SessionStore};
+
+type TypedHandler = fn() -> (&'static str, MethodRouter);
+
+// How often an open event stream checks that its session or token is still valid.
+const EVENTS_RECHECK: std::time::Duration = std::time::Duration::from_secs(5);
 
 mod error {
     use askama::Template;
@@ -42,7 +78,16 @@ pub enum HttpResponse {
     TemporaryRedirect(&'static str),
     HTML(String),
     UnauthorizedRedirect(&'static str),
+    Forbidden(&'static str),
+    Json(serde_json::Value),
+    Accepted(String, serde_json::Value),
+    Attachment(&'static str, Vec<u8>),
+    UnprocessableEntity(String),
+    Conflict(String),
+    TooManyRequests(u64, String),
     Error(String),
+    ApiError(ApiError),
+    Events(broadcast::Receiver<Event>, Holder),
     NotFound,
 }
 
@@ -75,7 +120,7 @@ impl IntoResponse for HttpResponse {
                 ([(header::CONTENT_TYPE, "image/png")], body).into_response()
             }
             HttpResponse::CreatedAndRedirect(location) => {
-                ([(header::LOCATION, location)], "").into_response()
+                (StatusCode::CREATED, [(header::LOCATION, location)], "").into_response()
             }
             HttpResponse::InlineText(body) => {
                 ([(header::CONTENT_DISPOSITION, "inline")], body).into_response()
@@ -89,6 +134,84 @@ impl IntoResponse for HttpResponse {
             HttpResponse::UnauthorizedRedirect(location) => {
                 (StatusCode::UNAUTHORIZED, [(header::LOCATION, location)]).into_response()
             }
+            HttpResponse::Forbidden(error) => (
+                StatusCode::FORBIDDEN,
+                [(header::CONTENT_TYPE, "text/plain")],
+                error,
+            )
+                .into_response(),
+            HttpResponse::Json(body) => Json(body).into_response(),
+            HttpResponse::Accepted(location, body) => (
+                StatusCode::ACCEPTED,
+                [(header::LOCATION, location)],
+                Json(body),
+            )
+                .into_response(),
+            HttpResponse::Attachment(name, body) => (
+                [
+                    (
+                        header::CONTENT_TYPE,
+                        String::from("application/octet-stream"),
+                    ),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}\"", name),
+                    ),
+                ],
+                body,
+            )
+                .into_response(),
+            HttpResponse::UnprocessableEntity(error) => (
+                StatusCode::UNPROCESSABLE_ENTITY,
+                [(header::CONTENT_TYPE, "text/plain")],
+                error,
+            )
+                .into_response(),
+            HttpResponse::Conflict(error) => (
+                StatusCode::CONFLICT,
+                [(header::CONTENT_TYPE, "text/plain")],
+                error,
+            )
+                .into_response(),
+            HttpResponse::TooManyRequests(retry_after, error) => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [
+                    (header::CONTENT_TYPE, String::from("text/plain")),
+                    (header::RETRY_AFTER, retry_after.to_string()),
+                ],
+                error,
+            )
+                .into_response(),
+            HttpResponse::ApiError(error) => error.into_response(),
+            // A client that fell behind has missed events, it is told to load everything again. The
+            // stream ends once its session or token is no longer valid.
+            HttpResponse::Events(receiver, holder) => {
+                let recheck = tokio::time::interval(EVENTS_RECHECK);
+                let state = (receiver, holder, recheck);
+                Sse::new(stream::unfold(state, |mut state| async move {
+                    let (receiver, holder, recheck) = &mut state;
+                    let event = loop {
+                        tokio::select! {
+                            result = receiver.recv() => break result,
+                            _ = recheck.tick() => {
+                                if !holder.is_valid().await {
+                                    return None;
+                                }
+                            }
+                        }
+                    };
+                    let event = match event {
+                        Ok(event) => sse::Event::default().event(event.name()).json_data(&event),
+                        Err(RecvError::Lagged(_)) => {
+                            Ok(sse::Event::default().event("lagged").data("{}"))
+                        }
+                        Err(RecvError::Closed) => return None,
+                    };
+                    Some((event, state))
+                }))
+                .keep_alive(KeepAlive::default())
+                .into_response()
+            }
             HttpResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
             HttpResponse::Error(error) => (
                 StatusCode::INTERNAL_SERVER_ERROR,
@@ -99,3 +222,139 @@ impl IntoResponse for HttpResponse {
         }
     }
 }
+
+impl From<LockError> for HttpResponse {
+    fn from(e: LockError) -> Self {
+        match e {
+            LockError::Busy(_) => HttpResponse::Conflict(e.to_string()),
+            LockError::Io(_) => HttpResponse::Error(e.to_string()),
+        }
+    }
+}
+
+pub fn clean_up_periodically() {
+    let session_store = FileStore::new(get_session_directory());
+    tokio::spawn(
+        session_store.delete_expired_periodically(std::time::Duration::from_secs(60 * 60)),
+    );
+    tokio::spawn(prune_periodically(std::time::Duration::from_secs(60 * 60)));
+}
+
+// Every route of the app. The route tests check that each of them is either public or protected.
+const HANDLERS: &[TypedHandler] = &[
+    view::audit_handler,
+    view::index_handler,
+    view::jobs_handler,
+    view::setup_handler,
+    view::login_handler,
+    view::containers_handler,
+    view::sessions_handler,
+    view::tokens_handler,
+    view::two_factor_handler,
+    view::two_factor_login_handler,
+    view::users_handler,
+    api_audit::export_handler,
+    api_auth::getlogin_handler,
+    api_auth::login_handler,
+    api_auth::login_token_handler,
+    api_auth::logout_handler,
+    api_auth::password_handler,
+    api_auth::second_factor_handler,
+    api_backup::retention_handler,
+    api_configuration::handler,
+    api_configuration::export_handler,
+    api_configuration::import_handler,
+    api_configuration::sources_handler,
+    api_docker::backup_handler,
+    api_docker::backup_check_handler,
+    api_docker::backup_check_repair_handler,
+    api_docker::backup_test_handler,
+    api_docker::getwatchtower_handler,
+    api_docker::logs_handler,
+    api_docker::restore_handler,
+    api_docker::start_handler,
+    api_docker::stop_handler,
+    api_docker::watchtower_handler,
+    api_events::stream_handler,
+    api_jobs::cancel_handler,
+    api_password::generate_handler,
+    api_sessions::revoke_handler,
+    api_sessions::revoke_others_handler,
+    api_tokens::create_handler,
+    api_tokens::revoke_handler,
+    api_two_factor::disable_handler,
+    api_two_factor::enable_handler,
+    api_two_factor::recovery_codes_handler,
+    api_users::create_handler,
+    api_users::delete_handler,
+    api_users::password_handler,
+    api_users::role_handler,
+    api_v1::backup_check_handler,
+    api_v1::backup_check_repair_handler,
+    api_v1::backup_create_handler,
+    api_v1::backup_test_handler,
+    api_v1::backups_handler,
+    api_v1::configuration_handler,
+    api_v1::configuration_update_handler,
+    api_v1::containers_handler,
+    api_v1::job_handler,
+    api_v1::job_cancel_handler,
+    api_v1::jobs_handler,
+    api_v1::logs_handler,
+    api_v1::openapi_handler,
+    api_v1::start_handler,
+    api_v1::stop_handler,
+    api_v1::updates_handler,
+    assets::before_unload_js,
+    assets::disable_clamav_js,
+    assets::disable_collabora_js,
+    assets::disable_docker_socket_proxy_js,
+    assets::disable_fulltextsearch_js,
+    assets::disable_imaginary_js,
+    assets::disable_onlyoffice_js,
+    assets::disable_talk_js,
+    assets::disable_talk_recording_js,
+    assets::disable_whiteboard_js,
+    assets::forms_js,
+    assets::live_status_js,
+    assets::options_form_submit_js,
+    assets::robots_txt,
+    assets::second_tab_warning_js,
+    assets::style_css,
+    assets::timezone_js,
+    assets::toggle_dark_mode_js,
+    assets::favicon_png,
+    assets::jenna_kim_the_globe_webp,
+    assets::jenna_kim_the_globe_dark_webp,
+    assets::nextcloud_logo_svg,
+];
+
+// Session cookies are marked Secure on the TLS listeners, so browsers never send them in clear.
+pub async fn router(secure: bool) -> Router {
+    let session_store = FileStore::new(get_session_directory());
+    // The absolute lifetime is enforced by the auth middleware, see track_session().
+    let idle_timeout = StateConfiguration::instance_ref()
+        .await
+        .session_idle_timeout;
+    app(
+        session_store,
+        Duration::minutes(idle_timeout.into()),
+        secure,
+    )
+}
+
+fn app<S: SessionStore + Clone>(session_store: S, idle_timeout: Duration, secure: bool) -> Router {
+    let session_layer = SessionManagerLayer::new(session_store)
+        .with_secure(secure)
+        .with_expiry(Expiry::OnInactivity(idle_timeout));
+
+    HANDLERS
+        .iter()
+        .fold(Router::new(), |router, handler| {
+            router.typed_route(*handler)
+        })
+        .layer(middleware::from_fn(csrf_middleware))
+        .layer(middleware::from_fn(auth_middleware))
+        .layer(middleware::from_fn(json_errors))
+        .layer(session_layer)
+}
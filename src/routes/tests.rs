@@ -0,0 +1,1396 @@
+use crate::auth::api_tokens::{ApiTokens, Scope};
+use crate::auth::audit::{AuditFilter, AuditLog, Outcome};
+use crate::auth::auth_middleware::is_public;
+use crate::auth::csrf::{csrf_middleware, csrf_token};
+use crate::auth::login_tokens::LoginTokens;
+use crate::auth::password_generator::check_password_strength;
+use crate::auth::password_hash::hash_password;
+use crate::auth::two_factor::{generate_secret, TwoFactor};
+use crate::auth::users::{Role, Users};
+use crate::configuration::{StateConfiguration, PASSWORD_TURN};
+use crate::container::controller::{ContainerState, DockerController};
+use crate::container::events::{self, ContainerStatus, Event};
+use crate::container::jobs::JobKind;
+use crate::data::data_const::{get_operation_lock_file, get_word_lists_directory, use_test_home};
+use crate::routes::{app, HANDLERS};
+use axum::body::{to_bytes, Body};
+use axum::extract::ConnectInfo;
+use axum::http::{header, Request, StatusCode};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::{middleware, Router};
+use futures_util::{FutureExt, StreamExt};
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::panic::AssertUnwindSafe;
+use time::{Duration, OffsetDateTime};
+use tokio::sync::RwLock;
+use totp_rs::{Algorithm, Secret, TOTP};
+use tower::ServiceExt;
+use tower_sessions::session::{Id, Record};
+use tower_sessions::{MemoryStore, Session, SessionManagerLayer, SessionStore};
+
+const PROTECTED_PAGES: &[&str] = &[
+    "/audit",
+    "/containers",
+    "/jobs",
+    "/sessions",
+    "/tokens",
+    "/two-factor",
+    "/users",
+];
+
+const PROTECTED_API: &[(&str, &str)] = &[
+    ("GET", "/api/audit/export"),
+    ("POST", "/api/auth/login-token"),
+    ("POST", "/api/auth/logout"),
+    ("POST", "/api/auth/password"),
+    ("GET", "/api/backup/retention"),
+    ("POST", "/api/configuration"),
+    ("GET", "/api/configuration/sources"),
+    ("POST", "/api/configuration/export"),
+    ("POST", "/api/docker/watchtower"),
+    ("GET", "/api/docker/getwatchtower"),
+    ("POST", "/api/docker/start"),
+    ("POST", "/api/docker/backup"),
+    ("POST", "/api/docker/stop"),
+    ("POST", "/api/docker/backup-check"),
+    ("POST", "/api/docker/backup-check-repair"),
+    ("POST", "/api/docker/backup-test"),
+    ("POST", "/api/docker/restore"),
+    ("GET", "/api/docker/logs?id=nextcloud-aio-nextcloud"),
+    ("GET", "/api/events"),
+    ("POST", "/api/jobs/cancel"),
+    ("GET", "/api/password/generate"),
+    ("POST", "/api/sessions/revoke"),
+    ("POST", "/api/sessions/revoke-others"),
+    ("POST", "/api/tokens"),
+    ("POST", "/api/tokens/revoke"),
+    ("POST", "/api/two-factor/enable"),
+    ("POST", "/api/two-factor/disable"),
+    ("POST", "/api/two-factor/recovery-codes"),
+    ("POST", "/api/users"),
+    ("POST", "/api/users/role"),
+    ("POST", "/api/users/password"),
+    ("POST", "/api/users/delete"),
+    ("GET", "/api/v1/backups"),
+    ("POST", "/api/v1/backups"),
+    ("POST", "/api/v1/backups/check"),
+    ("POST", "/api/v1/backups/check-repair"),
+    ("POST", "/api/v1/backups/test"),
+    ("GET", "/api/v1/configuration"),
+    ("POST", "/api/v1/configuration"),
+    ("GET", "/api/v1/containers"),
+    ("GET", "/api/v1/containers/logs?id=nextcloud-aio-nextcloud"),
+    ("POST", "/api/v1/containers/start"),
+    ("POST", "/api/v1/containers/stop"),
+    ("GET", "/api/v1/updates"),
+    ("GET", "/api/v1/jobs"),
+    ("GET", "/api/v1/jobs/detail?id=1"),
+    ("POST", "/api/v1/jobs/cancel?id=1"),
+];
+
+const NEEDS_DOCKER: &[&str] = &["GET /login"];
+
+const STATIC_ASSETS: &[&str] = &[
+    "/before-unload.js",
+    "/disable-clamav.js",
+    "/disable-collabora.js",
+    "/disable-docker-socket-proxy.js",
+    "/disable-fulltextsearch.js",
+    "/disable-imaginary.js",
+    "/disable-onlyoffice.js",
+    "/disable-talk.js",
+    "/disable-talk-recording.js",
+    "/disable-whiteboard.js",
+    "/forms.js",
+    "/live-status.js",
+    "/options-form-submit.js",
+    "/robots.txt",
+    "/second-tab-warning.js",
+    "/style.css",
+    "/timezone.js",
+    "/toggle-dark-mode.js",
+    "/img/favicon.png",
+    "/img/jenna-kim-the-globe.webp",
+    "/img/jenna-kim-the-globe-dark.webp",
+    "/img/nextcloud-logo.svg",
+];
+
+// Changing the shared AIO passphrase logs out every other session, including those of the other
+// tests. Their requests wait while the test that changes it runs, and it puts the previous
+// session generation back before they continue.
+static PASSPHRASE_CHANGE: RwLock<()> = RwLock::const_new(());
+
+fn test_app() -> Router {
+    use_test_home();
+    app(MemoryStore::default(), Duration::minutes(30), false)
+}
+
+// Handlers that need a running Docker daemon panic in the test environment. For the routes listed
+// in NEEDS_DOCKER a panic still shows that the request made it past the middleware, so it is
+// reported as `None`. A panic anywhere else fails the test.
+async fn send(app: &Router, method: &str, uri: &str, cookie: Option<&str>) -> Option<Response> {
+    let mut request = Request::builder().method(method).uri(uri);
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+    oneshot(app, request.body(Body::empty()).unwrap()).await
+}
+
+async fn send_bearer(app: &Router, method: &str, uri: &str, token: &str) -> Option<Response> {
+    let request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        .body(Body::empty())
+        .unwrap();
+    oneshot(app, request).await
+}
+
+async fn oneshot(app: &Router, request: Request<Body>) -> Option<Response> {
+    let route = format!("{} {}", request.method(), request.uri().path());
+    let _turn = PASSPHRASE_CHANGE.read().await;
+    match tokio::spawn(app.clone().oneshot(request)).await {
+        Ok(response) => Some(response.unwrap()),
+        Err(e) if e.is_panic() && NEEDS_DOCKER.contains(&route.as_str()) => None,
+        Err(e) => std::panic::resume_unwind(e.into_panic()),
+    }
+}
+
+async fn send_form(
+    app: &Router,
+    uri: &str,
+    cookie: &str,
+    origin: Option<&str>,
+    body: String,
+) -> Response {
+    let mut request = Request::builder()
+        .method("POST")
+        .uri(uri)
+        .header(header::HOST, "aio.example.com:8080")
+        .header(header::COOKIE, cookie)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+    if let Some(origin) = origin {
+        request = request.header(header::ORIGIN, origin);
+    }
+    let _turn = PASSPHRASE_CHANGE.read().await;
+    app.clone()
+        .oneshot(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap()
+}
+
+// Stands in for a reverse proxy at `peer` that has authenticated `user`.
+async fn send_proxied(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    peer: &str,
+    user: Option<&str>,
+    cookie: Option<&str>,
+) -> Response {
+    let peer: SocketAddr = peer.parse().unwrap();
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .extension(ConnectInfo(peer));
+    if let Some(user) = user {
+        request = request.header("Remote-User", user);
+    }
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+    let _turn = PASSPHRASE_CHANGE.read().await;
+    app.clone()
+        .oneshot(request.body(Body::empty()).unwrap())
+        .await
+        .unwrap()
+}
+
+// Runs `test` with 10.0.0.0/8 trusted to send the user in `Remote-User`, then puts the previous
+// settings back, even if the test fails. The tests that need it take turns. Requests from the
+// other tests have no peer address, so they are never seen as coming from the proxy.
+async fn with_trusted_proxy(test: impl Future<Output = ()>) {
+    static PROXY: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+    let _turn = PROXY.lock().await;
+    let previous = {
+        let mut config = StateConfiguration::instance_mut().await;
+        let previous = (
+            config.trusted_proxies.clone(),
+            config.auth_proxy_header.clone(),
+        );
+        config.trusted_proxies = vec!["10.0.0.0/8".parse().unwrap()];
+        config.auth_proxy_header = Some(String::from("Remote-User"));
+        previous
+    };
+    let result = AssertUnwindSafe(test).catch_unwind().await;
+    let mut config = StateConfiguration::instance_mut().await;
+    (config.trusted_proxies, config.auth_proxy_header) = previous;
+    drop(config);
+    if let Err(panic) = result {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+async fn create_user(name: &str, role: Role) {
+    let password = "correct horse battery staple for route tests";
+    let mut users = Users::instance().await.lock().await;
+    if !users.list().iter().any(|user| user.name == name) {
+        users.create(name, password, role).unwrap();
+    }
+}
+
+fn session_cookie(response: &Response) -> Option<String> {
+    response
+        .headers()
+        .get(header::SET_COOKIE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map(String::from)
+}
+
+async fn body_text(response: Response) -> String {
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+// The hidden CSRF fields of the first form on a page, ready to be sent as a form body.
+fn csrf_fields(html: &str) -> String {
+    let value_of = |name: &str| {
+        let start = html.find(&format!("name=\"{name}\" type=\"hidden\" value=\""))?;
+        let rest = &html[start..];
+        let rest = &rest[rest.find("value=\"")? + 7..];
+        Some(String::from(&rest[..rest.find('"')?]))
+    };
+    let name = value_of("csrf_name").unwrap();
+    let value = value_of("csrf_value").unwrap();
+    format!("csrf_name={name}&csrf_value={value}")
+}
+
+fn location(response: &Response) -> Option<&str> {
+    response
+        .headers()
+        .get(header::LOCATION)
+        .and_then(|value| value.to_str().ok())
+}
+
+#[test]
+fn every_route_is_public_or_tested_as_protected() {
+    let protected_api: Vec<&str> = PROTECTED_API
+        .iter()
+        .map(|(_, uri)| uri.split('?').next().unwrap())
+        .collect();
+    let routes: Vec<&str> = HANDLERS.iter().map(|handler| handler().0).collect();
+    for route in &routes {
+        assert!(
+            is_public(route) || PROTECTED_PAGES.contains(route) || protected_api.contains(route),
+            "{route} is neither public nor in the protected routes of these tests"
+        );
+    }
+    // The lists of these tests must not name routes that no longer exist.
+    for uri in PROTECTED_PAGES
+        .iter()
+        .chain(&protected_api)
+        .chain(STATIC_ASSETS)
+    {
+        assert!(routes.contains(uri), "{uri} is not a route");
+    }
+    for uri in PROTECTED_PAGES.iter().chain(&protected_api) {
+        assert!(!is_public(uri), "{uri} is public");
+    }
+}
+
+#[tokio::test]
+async fn api_routes_reject_anonymous_requests() {
+    let app = test_app();
+    for (method, uri) in PROTECTED_API {
+        let response = send(&app, method, uri, None).await;
+        let response = response.unwrap_or_else(|| panic!("{method} {uri} reached its handler"));
+        assert_eq!(
+            response.status(),
+            StatusCode::UNAUTHORIZED,
+            "{method} {uri}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn pages_redirect_anonymous_requests() {
+    let app = test_app();
+    for uri in PROTECTED_PAGES {
+        let response = send(&app, "GET", uri, None).await;
+        let response = response.unwrap_or_else(|| panic!("GET {uri} reached its handler"));
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT, "{uri}");
+        assert_eq!(location(&response), Some("/"), "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn static_assets_are_public() {
+    let app = test_app();
+    for uri in STATIC_ASSETS {
+        let response = send(&app, "GET", uri, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn public_pages_are_reachable() {
+    let app = test_app();
+
+    let response = send(&app, "GET", "/", None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_ne!(location(&response), Some("/"));
+
+    let response = send(&app, "GET", "/setup", None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = send(&app, "GET", "/login", None).await;
+    let status = response.map(|response| response.status());
+    assert!(matches!(status, None | Some(StatusCode::OK)), "{status:?}");
+
+    // The second step of the login is only shown after a correct passphrase.
+    let response = send(&app, "GET", "/login/two-factor", None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    assert_eq!(location(&response), Some("/login"));
+}
+
+#[tokio::test]
+async fn public_api_routes_are_reachable() {
+    let app = test_app();
+
+    // Without a CSRF token the request is rejected, which only happens after authentication.
+    for uri in ["/api/auth/login", "/api/auth/two-factor"] {
+        let response = send(&app, "POST", uri, None).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+
+    let response = send(&app, "POST", "/api/configuration/import", None).await;
+    assert_ne!(response.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+    let response = send(&app, "GET", "/api/auth/getlogin", None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn authenticated_session_reaches_protected_routes() {
+    let app = test_app();
+    let token = LoginTokens::instance().await.lock().await.mint();
+    let uri = format!("/api/auth/getlogin?token={token}");
+
+    let response = send(&app, "GET", &uri, None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    let cookie = session_cookie(&response).unwrap();
+
+    for uri in ["/api/configuration/sources", "/api/backup/retention"] {
+        let response = send(&app, "GET", uri, Some(&cookie)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{uri}");
+    }
+    let response = send(&app, "POST", "/api/configuration", Some(&cookie)).await;
+    assert_eq!(response.unwrap().status(), StatusCode::FORBIDDEN);
+    for uri in PROTECTED_PAGES {
+        let response = send(&app, "GET", uri, Some(&cookie)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn login_tokens_are_single_use() {
+    let app = test_app();
+    let token = LoginTokens::instance().await.lock().await.mint();
+    let uri = format!("/api/auth/getlogin?token={token}");
+
+    let response = send(&app, "GET", &uri, None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+
+    let response = send(&app, "GET", &uri, None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+    let response = send(&app, "GET", "/api/auth/getlogin?token=guessed", None).await;
+    assert_eq!(response.unwrap().status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn logins_get_a_new_session_id() {
+    let app = test_app();
+    // A failed login is enough to get a session that could be planted in another browser.
+    let response = send(&app, "GET", "/api/auth/getlogin?token=guessed", None).await;
+    let planted = session_cookie(&response.unwrap()).unwrap();
+
+    let token = LoginTokens::instance().await.lock().await.mint();
+    let uri = format!("/api/auth/getlogin?token={token}");
+    let response = send(&app, "GET", &uri, Some(&planted)).await.unwrap();
+    let cookie = session_cookie(&response).unwrap();
+    assert_ne!(cookie, planted);
+
+    let uri = "/api/backup/retention";
+    let response = send(&app, "GET", uri, Some(&cookie)).await;
+    assert_eq!(response.unwrap().status(), StatusCode::OK);
+    let response = send(&app, "GET", uri, Some(&planted)).await;
+    assert_eq!(response.unwrap().status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn session_cookies_are_secure_only_on_tls_listeners() {
+    for secure in [false, true] {
+        let app = app(MemoryStore::default(), Duration::minutes(30), secure);
+        let token = LoginTokens::instance().await.lock().await.mint();
+        let uri = format!("/api/auth/getlogin?token={token}");
+
+        let response = send(&app, "GET", &uri, None).await.unwrap();
+        let cookie = response.headers().get(header::SET_COOKIE).unwrap();
+        assert_eq!(cookie.to_str().unwrap().contains("Secure"), secure);
+    }
+}
+
+#[tokio::test]
+async fn state_changing_requests_require_csrf_token() {
+    async fn token(session: Session) -> String {
+        let csrf = csrf_token(&session).await.unwrap();
+        format!(
+            "{}={}&{}={}",
+            csrf.keys.name, csrf.name, csrf.keys.value, csrf.value
+        )
+    }
+    let app = Router::new()
+        .route("/token", get(token))
+        .route("/action", post(|| async { "done" }))
+        .layer(middleware::from_fn(csrf_middleware))
+        .layer(SessionManagerLayer::new(MemoryStore::default()));
+
+    let response = send(&app, "GET", "/token", None).await.unwrap();
+    let cookie = session_cookie(&response).unwrap();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let fields = String::from_utf8(body.to_vec()).unwrap();
+
+    let response = send_form(&app, "/action", &cookie, None, String::new()).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let forged = String::from("csrf_name=forged&csrf_value=forged");
+    let response = send_form(&app, "/action", &cookie, None, forged).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = send_form(&app, "/action", &cookie, None, fields.clone()).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let origin = Some("http://evil.example.com");
+    let response = send_form(&app, "/action", &cookie, origin, fields.clone()).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let origin = Some("https://aio.example.com:8080");
+    let response = send_form(&app, "/action", &cookie, origin, fields).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn api_tokens_are_limited_to_their_scopes() {
+    let app = test_app();
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-read", vec![Scope::Read])
+        .unwrap();
+
+    let response = send_bearer(&app, "GET", "/api/backup/retention", &token).await;
+    assert_eq!(response.unwrap().status(), StatusCode::OK);
+
+    for (method, uri) in [
+        ("POST", "/api/docker/start"),
+        ("POST", "/api/docker/backup"),
+        ("POST", "/api/configuration"),
+        ("POST", "/api/tokens"),
+        ("POST", "/api/two-factor/disable"),
+        ("POST", "/api/auth/logout"),
+        ("GET", "/containers"),
+    ] {
+        let response = send_bearer(&app, method, uri, &token).await;
+        let response = response.unwrap_or_else(|| panic!("{method} {uri} reached its handler"));
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{method} {uri}");
+    }
+
+    // The audit log is only for admins, and tokens have no role.
+    let response = send_bearer(&app, "GET", "/api/audit/export", &token).await;
+    assert_eq!(response.unwrap().status(), StatusCode::FORBIDDEN);
+
+    let forged = format!("{}0", token);
+    let response = send_bearer(&app, "GET", "/api/backup/retention", &forged).await;
+    assert_eq!(response.unwrap().status(), StatusCode::UNAUTHORIZED);
+
+    // Tokens skip the CSRF check, so the form extractor is the first to reject the empty body.
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-config", vec![Scope::ConfigWrite])
+        .unwrap();
+    let response = send_bearer(&app, "POST", "/api/configuration", &token).await;
+    assert_eq!(
+        response.unwrap().status(),
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+
+    // The passphrase and the export with all secrets stay with logged in sessions.
+    for (uri, body) in [
+        (
+            "/api/configuration",
+            "current-master-password=a&new-master-password=b",
+        ),
+        ("/api/configuration/export", "passphrase=export"),
+    ] {
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn new_api_tokens_are_only_shown_in_the_response() {
+    let app = test_app();
+    let token = LoginTokens::instance().await.lock().await.mint();
+    let uri = format!("/api/auth/getlogin?token={token}");
+    let response = send(&app, "GET", &uri, None).await.unwrap();
+    let cookie = session_cookie(&response).unwrap();
+    let response = send(&app, "GET", "/tokens", Some(&cookie)).await.unwrap();
+    let fields = csrf_fields(&body_text(response).await);
+
+    let body = format!("name=route-test-created&read=on&{fields}");
+    let response = send_form(&app, "/api/tokens", &cookie, None, body).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = body_text(response).await;
+    let start = html.find("<p class=\"monospace\">").unwrap() + 21;
+    let token = &html[start..start + html[start..].find('<').unwrap()];
+    let response = send_bearer(&app, "GET", "/api/backup/retention", token).await;
+    assert_eq!(response.unwrap().status(), StatusCode::OK);
+
+    let response = send(&app, "GET", "/tokens", Some(&cookie)).await.unwrap();
+    assert!(!body_text(response).await.contains(token));
+
+    let body = format!("name=route-test-created&read=on&{fields}");
+    let response = send_form(&app, "/api/tokens", &cookie, None, body).await;
+    assert!(body_text(response).await.contains("already exists"));
+}
+
+#[tokio::test]
+async fn json_api_errors_are_objects_with_codes() {
+    let app = test_app();
+    let error_code = |body: String| {
+        let body: serde_json::Value = serde_json::from_str(&body).unwrap();
+        body["error"]["code"].as_str().unwrap().to_string()
+    };
+
+    let response = send(&app, "GET", "/api/v1/containers", None).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert_eq!(error_code(body_text(response).await), "unauthorized");
+
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-v1-read", vec![Scope::Read])
+        .unwrap();
+    let response = send_bearer(&app, "POST", "/api/v1/containers/stop", &token).await;
+    let response = response.expect("the scope is checked before the handler");
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(error_code(body_text(response).await), "forbidden");
+
+    let response = send_bearer(&app, "GET", "/api/v1/nothing", &token).await;
+    let response = response.unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    assert_eq!(error_code(body_text(response).await), "not_found");
+
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-v1-config", vec![Scope::ConfigWrite])
+        .unwrap();
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/v1/configuration")
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(r#"{"no_such_key": true}"#))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error_code(body_text(response).await), "unknown_key");
+
+    // A token must not be able to trust itself as an authenticating proxy.
+    let request = Request::builder()
+        .method("POST")
+        .uri("/api/v1/configuration")
+        .header(header::AUTHORIZATION, format!("Bearer {token}"))
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(
+            r#"{"trusted_proxies": ["10.0.0.1/32"], "auth_proxy_header": "Remote-User"}"#,
+        ))
+        .unwrap();
+    let response = app.clone().oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(error_code(body_text(response).await), "read_only");
+
+    let response = send(&app, "POST", "/api/v1/configuration", None).await;
+    assert_eq!(response.unwrap().status(), StatusCode::UNAUTHORIZED);
+    let response = send_bearer(&app, "POST", "/api/v1/configuration", &token).await;
+    let response = response.unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        error_code(body_text(response).await),
+        "unsupported_media_type"
+    );
+}
+
+#[tokio::test]
+async fn configuration_api_checks_values() {
+    let app = test_app();
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-v1-values", vec![Scope::ConfigWrite])
+        .unwrap();
+    let update = |body: &'static str| {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/v1/configuration")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap();
+        app.clone().oneshot(request)
+    };
+
+    // Nothing is applied when one of the values is invalid.
+    for (body, message) in [
+        (
+            r#"{"isTalkEnabled": true, "nextcloud_memory_limit": "1M"}"#,
+            "at least 512M",
+        ),
+        (
+            r#"{"timezone": "Europe/Berln"}"#,
+            "Did you mean Europe/Berlin?",
+        ),
+        (
+            r#"{"borg_retention_policy": "--keep-weekly"}"#,
+            "--keep-weekly",
+        ),
+    ] {
+        let response = update(body).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "{body}"
+        );
+        let error: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+        assert_eq!(error["error"]["code"], "invalid_value", "{body}");
+        let text = error["error"]["message"].as_str().unwrap();
+        assert!(text.contains(message), "{text}");
+    }
+    assert!(!StateConfiguration::instance_ref().await.is_talk_enabled);
+
+    for enabled in [true, false] {
+        let body = if enabled {
+            r#"{"isTalkEnabled": true}"#
+        } else {
+            r#"{"isTalkEnabled": false}"#
+        };
+        let response = update(body).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let config = StateConfiguration::instance_ref().await;
+        assert_eq!(config.is_talk_enabled, enabled);
+    }
+}
+
+#[tokio::test]
+async fn openapi_document_is_public_and_routed() {
+    let app = test_app();
+    let response = send(&app, "GET", "/api/v1/openapi.json", None)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let document: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+
+    // Every documented operation exists, the scope is only checked on the token.
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-v1-all", Scope::ALL.to_vec())
+        .unwrap();
+    for (path, operations) in document["paths"].as_object().unwrap() {
+        for method in operations.as_object().unwrap().keys() {
+            let method = method.to_uppercase();
+            let response = send_bearer(&app, &method, path, &token).await.unwrap();
+            assert_ne!(response.status(), StatusCode::NOT_FOUND, "{method} {path}");
+            assert_ne!(
+                response.status(),
+                StatusCode::METHOD_NOT_ALLOWED,
+                "{method} {path}"
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn operations_run_as_jobs() {
+    let app = test_app();
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-jobs", Scope::ALL.to_vec())
+        .unwrap();
+    let json = |body: String| serde_json::from_str::<serde_json::Value>(&body).unwrap();
+
+    // Without a Docker daemon the job fails, but the request returns before that. The other tests
+    // may hold the operation lock for a moment.
+    let mut response = None;
+    for _ in 0..100 {
+        let attempt = send_bearer(&app, "POST", "/api/v1/containers/stop", &token).await;
+        let attempt = attempt.unwrap();
+        if attempt.status() != StatusCode::CONFLICT {
+            response = Some(attempt);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let response = response.unwrap();
+    assert_eq!(response.status(), StatusCode::ACCEPTED);
+    let location = String::from(location(&response).unwrap());
+    let job = json(body_text(response).await);
+    assert_eq!(job["kind"], "stop");
+    assert_eq!(location, format!("/api/v1/jobs/detail?id={}", job["id"]));
+
+    let mut job = job;
+    for _ in 0..100 {
+        let response = send_bearer(&app, "GET", &location, &token).await.unwrap();
+        job = json(body_text(response).await);
+        if job["finished_at"].is_string() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    assert_eq!(job["state"], "failed");
+    assert!(job["error"].is_string());
+
+    let uri = format!("/api/v1/jobs/cancel?id={}", job["id"]);
+    let response = send_bearer(&app, "POST", &uri, &token).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    assert_eq!(
+        json(body_text(response).await)["error"]["code"],
+        "job_finished"
+    );
+
+    let response = send_bearer(&app, "GET", "/api/v1/jobs", &token)
+        .await
+        .unwrap();
+    let jobs = json(body_text(response).await);
+    assert!(jobs
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|j| j["id"] == job["id"]));
+}
+
+#[tokio::test]
+async fn conflicting_operations_are_rejected() {
+    let app = test_app();
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-lock", Scope::ALL.to_vec())
+        .unwrap();
+    let mut lock = None;
+    for _ in 0..100 {
+        if let Ok(acquired) = DockerController::lock(JobKind::Backup, "the lock test") {
+            lock = Some(acquired);
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+    let lock = lock.unwrap();
+
+    let response = send_bearer(&app, "POST", "/api/v1/containers/stop", &token).await;
+    let response = response.unwrap();
+    assert_eq!(response.status(), StatusCode::CONFLICT);
+    let body: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+    assert_eq!(body["error"]["code"], "operation_in_progress");
+    assert!(body["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("the lock test started to create a backup"));
+
+    let response = send_bearer(&app, "POST", "/api/docker/backup", &token).await;
+    assert_eq!(response.unwrap().status(), StatusCode::CONFLICT);
+
+    // A lock whose process has ended, as after a crash, is taken over.
+    let file = get_operation_lock_file();
+    let mut stale: serde_json::Value =
+        serde_json::from_slice(&std::fs::read(&file).unwrap()).unwrap();
+    stale["pid"] = serde_json::json!(u32::MAX);
+    std::fs::write(&file, serde_json::to_vec(&stale).unwrap()).unwrap();
+    std::mem::forget(lock);
+    let lock = DockerController::lock(JobKind::Stop, "the lock test").unwrap();
+    drop(lock);
+    assert!(!file.exists());
+}
+
+#[tokio::test]
+async fn live_status_is_streamed() {
+    let app = test_app();
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-events", vec![Scope::Read])
+        .unwrap();
+    let response = send_bearer(&app, "GET", "/api/events", &token)
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.headers()[header::CONTENT_TYPE],
+        "text/event-stream"
+    );
+
+    // The other tests publish their jobs on the same channel.
+    events::publish(Event::Container(ContainerStatus {
+        id: String::from("nextcloud-aio-route-test"),
+        state: ContainerState::Running,
+    }));
+    let mut body = response.into_body().into_data_stream();
+    let mut received = String::new();
+    while !received.contains("nextcloud-aio-route-test") {
+        let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), body.next()).await;
+        received.push_str(&String::from_utf8_lossy(&chunk.unwrap().unwrap().unwrap()));
+    }
+    assert!(received.contains(
+        "event: container\ndata: {\"id\":\"nextcloud-aio-route-test\",\"state\":\"running\"}"
+    ));
+
+    // Revoking the token ends the stream.
+    let mut tokens = ApiTokens::instance().await.lock().await;
+    let id = tokens
+        .list()
+        .iter()
+        .find(|token| token.name == "route-test-events")
+        .map(|token| token.id.clone())
+        .unwrap();
+    tokens.revoke(&id);
+    drop(tokens);
+    let ended = tokio::time::timeout(std::time::Duration::from_secs(10), async {
+        while let Some(chunk) = body.next().await {
+            chunk.unwrap();
+        }
+    });
+    assert!(ended.await.is_ok());
+}
+
+#[tokio::test]
+async fn trusted_proxy_header_authenticates() {
+    with_trusted_proxy(async {
+        create_user("proxy-alice", Role::Viewer).await;
+        create_user("proxy-bob", Role::Viewer).await;
+        let app = test_app();
+        let uri = "/api/backup/retention";
+
+        let response = send_proxied(
+            &app,
+            "GET",
+            uri,
+            "192.0.2.1:4000",
+            Some("proxy-alice"),
+            None,
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = send_proxied(&app, "GET", uri, "10.0.0.2:4000", None, None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response =
+            send_proxied(&app, "GET", uri, "10.0.0.2:4000", Some("proxy-alice"), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = session_cookie(&response).unwrap();
+
+        // The session keeps the login, even for requests that bypass the proxy.
+        let response = send_proxied(&app, "GET", uri, "192.0.2.1:4000", None, Some(&cookie)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = send_proxied(
+            &app,
+            "GET",
+            uri,
+            "10.0.0.2:4000",
+            Some("proxy-bob"),
+            Some(&cookie),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_ne!(session_cookie(&response), Some(cookie));
+
+        // Once accounts exist, the proxy can only log in users that have one.
+        let response = send_proxied(&app, "GET", uri, "10.0.0.2:4000", Some("mallory"), None).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let filter = AuditFilter {
+            event: String::from("proxy-login"),
+            actor: String::from("mallory"),
+            outcome: String::new(),
+        };
+        let entries = AuditLog::instance().await.lock().await.entries(&filter);
+        assert!(!entries.is_empty());
+        assert!(entries
+            .iter()
+            .all(|entry| entry.outcome == Outcome::Failure));
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn user_roles_limit_routes() {
+    with_trusted_proxy(async {
+        create_user("role-viewer", Role::Viewer).await;
+        create_user("role-operator", Role::Operator).await;
+        let app = test_app();
+        let peer = "10.0.0.2:4000";
+        let denied = "Your role is not allowed to do this.";
+
+        for user in ["role-viewer", "role-operator"] {
+            let response =
+                send_proxied(&app, "GET", "/api/backup/retention", peer, Some(user), None);
+            assert_eq!(response.await.status(), StatusCode::OK, "{user}");
+        }
+
+        let cases = [
+            ("role-viewer", "/api/docker/start", true),
+            ("role-viewer", "/api/docker/backup", true),
+            ("role-operator", "/api/docker/start", false),
+            ("role-operator", "/api/docker/backup", false),
+            ("role-operator", "/api/docker/restore", true),
+            ("role-operator", "/api/configuration", true),
+            ("role-operator", "/api/users", true),
+            ("role-operator", "/api/tokens", true),
+        ];
+        for (user, uri, is_denied) in cases {
+            let response = send_proxied(&app, "POST", uri, peer, Some(user), None).await;
+            // Allowed requests get as far as the CSRF check.
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{user} {uri}");
+            assert_eq!(
+                body_text(response).await == denied,
+                is_denied,
+                "{user} {uri}"
+            );
+        }
+
+        for uri in ["/users", "/tokens", "/audit", "/api/audit/export"] {
+            let response = send_proxied(&app, "GET", uri, peer, Some("role-operator"), None).await;
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{uri}");
+        }
+
+        // A removed user loses the session.
+        let uri = "/api/backup/retention";
+        let response = send_proxied(&app, "GET", uri, peer, Some("role-viewer"), None).await;
+        let cookie = session_cookie(&response).unwrap();
+        Users::instance().await.lock().await.remove("role-viewer");
+        let response = send(&app, "GET", uri, Some(&cookie)).await;
+        assert_eq!(response.unwrap().status(), StatusCode::UNAUTHORIZED);
+    })
+    .await;
+}
+
+#[tokio::test]
+async fn other_sessions_can_be_revoked() {
+    let app = test_app();
+    let mut cookies = Vec::new();
+    for _ in 0..2 {
+        let token = LoginTokens::instance().await.lock().await.mint();
+        let uri = format!("/api/auth/getlogin?token={token}");
+        let response = send(&app, "GET", &uri, None).await.unwrap();
+        cookies.push(session_cookie(&response).unwrap());
+    }
+    let (current, other) = (&cookies[0], &cookies[1]);
+    let uri = "/api/backup/retention";
+    for cookie in &cookies {
+        let response = send(&app, "GET", uri, Some(cookie)).await;
+        assert_eq!(response.unwrap().status(), StatusCode::OK);
+    }
+
+    let response = send(&app, "GET", "/sessions", Some(current)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let fields = csrf_fields(&body_text(response).await);
+    let response = send_form(&app, "/api/sessions/revoke-others", current, None, fields).await;
+    assert_eq!(response.status(), StatusCode::CREATED);
+
+    let response = send(&app, "GET", uri, Some(other)).await;
+    assert_eq!(response.unwrap().status(), StatusCode::UNAUTHORIZED);
+    let response = send(&app, "GET", uri, Some(current)).await;
+    assert_eq!(response.unwrap().status(), StatusCode::OK);
+}
+
+// Sends the requests of the test that holds PASSPHRASE_CHANGE, the other helpers would wait for it.
+async fn send_during_change(
+    app: &Router,
+    method: &str,
+    uri: &str,
+    cookie: Option<&str>,
+    body: String,
+) -> Response {
+    let mut request = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded");
+    if let Some(cookie) = cookie {
+        request = request.header(header::COOKIE, cookie);
+    }
+    app.clone()
+        .oneshot(request.body(Body::from(body)).unwrap())
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn changing_the_passphrase_logs_out_other_sessions() {
+    let app = test_app();
+    let _turn = PASSWORD_TURN.lock().await;
+    let _change = PASSPHRASE_CHANGE.write().await;
+    let current = "the current shared passphrase of the route tests";
+    let new = "a different shared passphrase for the route tests";
+    let previous = {
+        let mut config = StateConfiguration::instance_mut().await;
+        let previous = (config.password.clone(), config.session_generation);
+        config.password = hash_password(current).unwrap();
+        previous
+    };
+
+    let result = AssertUnwindSafe(async {
+        let mut cookies = Vec::new();
+        for _ in 0..2 {
+            let token = LoginTokens::instance().await.lock().await.mint();
+            let uri = format!("/api/auth/getlogin?token={token}");
+            let response = send_during_change(&app, "GET", &uri, None, String::new()).await;
+            cookies.push(session_cookie(&response).unwrap());
+        }
+        let (this, other) = (cookies[0].as_str(), cookies[1].as_str());
+        let response = send_during_change(&app, "GET", "/sessions", Some(this), String::new());
+        let fields = csrf_fields(&body_text(response.await).await);
+        let change = |current: &str, new: &str| {
+            let body = format!(
+                "current-master-password={}&new-master-password={}&{fields}",
+                current.replace(' ', "+"),
+                new.replace(' ', "+")
+            );
+            send_during_change(&app, "POST", "/api/configuration", Some(this), body)
+        };
+        let generation = StateConfiguration::instance_ref().await.session_generation;
+
+        for (current, new, message) in [
+            ("a wrong passphrase", new, "current AIO passphrase is wrong"),
+            (current, "too short", "at least 24 characters"),
+            (current, current, "must be different"),
+        ] {
+            let response = change(current, new).await;
+            assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+            assert!(body_text(response).await.contains(message), "{message}");
+        }
+        assert!(StateConfiguration::test_password(current).await);
+        let config = StateConfiguration::instance_ref().await;
+        assert_eq!(config.session_generation, generation);
+        drop(config);
+
+        let response = change(current, new).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert!(StateConfiguration::test_password(new).await);
+        assert!(!StateConfiguration::test_password(current).await);
+        let config = StateConfiguration::instance_ref().await;
+        assert_eq!(config.session_generation, generation + 1);
+        drop(config);
+
+        let uri = "/api/backup/retention";
+        let response = send_during_change(&app, "GET", uri, Some(other), String::new()).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = send_during_change(&app, "GET", uri, Some(this), String::new()).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    })
+    .catch_unwind()
+    .await;
+
+    let mut config = StateConfiguration::instance_mut().await;
+    (config.password, config.session_generation) = previous;
+    config.commit();
+    if let Err(panic) = result {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+// A session in which the passphrase of `account` was correct and only the second factor is
+// missing. The password step needs Docker to tell whether logins are allowed, so it is skipped.
+async fn pending_second_factor(store: &MemoryStore, account: &str) -> String {
+    let users = Users::instance().await.lock().await;
+    let user = users.list().iter().find(|user| user.name == account);
+    let generation = user.unwrap().generation;
+    drop(users);
+    let mut record = Record {
+        id: Id::default(),
+        data: HashMap::from([
+            (
+                String::from("second_factor_pending"),
+                serde_json::json!({
+                    "user": {"name": account, "generation": generation},
+                    "since": OffsetDateTime::now_utc().unix_timestamp(),
+                }),
+            ),
+            (
+                String::from("csrf"),
+                serde_json::json!({"name": "pending", "value": "pending"}),
+            ),
+        ]),
+        expiry_date: OffsetDateTime::now_utc() + Duration::minutes(30),
+    };
+    store.create(&mut record).await.unwrap();
+    format!("id={}", record.id)
+}
+
+#[tokio::test]
+async fn second_factor_completes_the_login() {
+    use_test_home();
+    let store = MemoryStore::default();
+    let routes = app(store.clone(), Duration::minutes(30), false);
+    let app = &routes;
+    create_user("totp-carol", Role::Admin).await;
+    let secret = generate_secret();
+    let totp = TOTP::new(
+        Algorithm::SHA1,
+        6,
+        1,
+        30,
+        Secret::Encoded(secret.clone()).to_bytes().unwrap(),
+    )
+    .unwrap();
+    let mut two_factor = TwoFactor::instance().await.lock().await;
+    two_factor.remove("totp-carol");
+    let now = OffsetDateTime::now_utc().unix_timestamp() as u64;
+    let recovery_codes = two_factor
+        .enable("totp-carol", &secret, &totp.generate(now))
+        .unwrap();
+    drop(two_factor);
+
+    // Its own address, so that the failures here are not throttled together with other tests.
+    let submit = move |cookie: String, code: String| {
+        let request = Request::builder()
+            .method("POST")
+            .uri("/api/auth/two-factor")
+            .header(header::COOKIE, cookie)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .extension(ConnectInfo(SocketAddr::from(([192, 0, 2, 39], 4000))))
+            .body(Body::from(format!(
+                "code={code}&csrf_name=pending&csrf_value=pending"
+            )))
+            .unwrap();
+        oneshot(app, request)
+    };
+    let retention = move |cookie: String| async move {
+        let response = send(app, "GET", "/api/backup/retention", Some(&cookie)).await;
+        response.unwrap().status()
+    };
+
+    let cookie = pending_second_factor(&store, "totp-carol").await;
+    let response = submit(cookie.clone(), String::from("000000"))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(retention(cookie.clone()).await, StatusCode::UNAUTHORIZED);
+    // The code that enabled the second factor cannot be used again, the next one can.
+    let response = submit(cookie.clone(), totp.generate(now)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    let response = submit(cookie.clone(), totp.generate(now + 30))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    assert_eq!(location(&response), Some("/"));
+    let cookie = session_cookie(&response).unwrap_or(cookie);
+    assert_eq!(retention(cookie).await, StatusCode::OK);
+
+    // Recovery codes work once, in any case and with spaces.
+    let code = recovery_codes[0].to_uppercase();
+    let cookie = pending_second_factor(&store, "totp-carol").await;
+    let response = submit(cookie.clone(), format!("+{code}+")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::CREATED);
+    let cookie = session_cookie(&response).unwrap_or(cookie);
+    assert_eq!(retention(cookie).await, StatusCode::OK);
+    let two_factor = TwoFactor::instance().await.lock().await;
+    let remaining = two_factor.remaining_recovery_codes("totp-carol");
+    assert_eq!(remaining, recovery_codes.len() - 1);
+    drop(two_factor);
+
+    let cookie = pending_second_factor(&store, "totp-carol").await;
+    let response = submit(cookie.clone(), code).await.unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    assert_eq!(retention(cookie).await, StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn password_generator_applies_options() {
+    let app = test_app();
+    let token = ApiTokens::instance()
+        .await
+        .lock()
+        .await
+        .create("route-test-password", vec![Scope::Read])
+        .unwrap();
+
+    let uri = "/api/password/generate?entropy=64&separator=hyphen&capitalization=title";
+    let response = send_bearer(&app, "GET", uri, &token).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+    let password = body["password"].as_str().unwrap();
+    assert!(body["entropy"].as_f64().unwrap() >= 64.0);
+    assert_eq!(
+        password.split('-').count() as u64,
+        body["words"].as_u64().unwrap()
+    );
+    assert!(password
+        .split('-')
+        .all(|word| word.starts_with(char::is_uppercase)));
+    assert_eq!(check_password_strength(password), Ok(()));
+
+    // Lists in other languages are read from the word list directory.
+    let words = "apfel birne kirsche pflaume traube zitrone melone feige dattel mango quitte \
+                 banane ananas himbeere erdbeere brombeere";
+    std::fs::create_dir_all(get_word_lists_directory()).unwrap();
+    let file = get_word_lists_directory().join("route-test-de.txt");
+    std::fs::write(file, words.replace(' ', "\n")).unwrap();
+    let uri = "/api/password/generate?word_list=route-test-de&entropy=20";
+    let response = send_bearer(&app, "GET", uri, &token).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+    assert_eq!(body["word_list"], "route-test-de");
+    let password = body["password"].as_str().unwrap();
+    assert!(password
+        .split(' ')
+        .all(|word| words.split_whitespace().any(|known| known == word)));
+
+    for uri in [
+        "/api/password/generate?separator=comma",
+        "/api/password/generate?entropy=1000",
+        "/api/password/generate?word_list=..%2Fconfiguration",
+    ] {
+        let response = send_bearer(&app, "GET", uri, &token).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY, "{uri}");
+    }
+}
+
+#[tokio::test]
+async fn audit_log_can_be_filtered_and_exported() {
+    let app = test_app();
+    let token = LoginTokens::instance().await.lock().await.mint();
+    let uri = format!("/api/auth/getlogin?token={token}");
+    let response = send(&app, "GET", &uri, None).await.unwrap();
+    let cookie = session_cookie(&response).unwrap();
+
+    let uri = "/audit?event=token-login&outcome=success&actor=aio";
+    let response = send(&app, "GET", uri, Some(&cookie)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let html = body_text(response).await;
+    assert!(html.contains(
+        "href=\"/api/audit/export?event=token-login&amp;actor=aio&amp;outcome=success\""
+    ));
+    assert!(html.contains("<option value=\"token-login\" selected>"));
+
+    let uri = "/api/audit/export?event=login";
+    let response = send(&app, "GET", uri, Some(&cookie)).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let disposition = response.headers().get(header::CONTENT_DISPOSITION).unwrap();
+    assert!(disposition.to_str().unwrap().contains("aio-audit-log.json"));
+    let entries: serde_json::Value = serde_json::from_str(&body_text(response).await).unwrap();
+    assert!(entries
+        .as_array()
+        .unwrap()
+        .iter()
+        .all(|entry| entry["event"] == "login"));
+}
+
+#[tokio::test]
+async fn accounts_manage_their_own_login() {
+    with_trusted_proxy(async {
+        create_user("own-alice", Role::Viewer).await;
+        create_user("own-bob", Role::Viewer).await;
+        let app = test_app();
+        let peer = "10.0.0.2:4000";
+
+        // Enrolling Alice leaves Bob without a second factor.
+        let response =
+            send_proxied(&app, "GET", "/two-factor", peer, Some("own-alice"), None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let cookie = session_cookie(&response).unwrap();
+        let html = body_text(response).await;
+        assert!(html.contains("disabled for your account"));
+        let start = html.find("<p class=\"monospace\">").unwrap() + 21;
+        let secret = &html[start..start + html[start..].find('<').unwrap()];
+        let secret = Secret::Encoded(String::from(secret)).to_bytes().unwrap();
+        let code = TOTP::new(Algorithm::SHA1, 6, 1, 30, secret)
+            .unwrap()
+            .generate_current()
+            .unwrap();
+        let body = format!("code={code}&{}", csrf_fields(&html));
+        let response = send_form(&app, "/api/two-factor/enable", &cookie, None, body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let response = send(&app, "GET", "/two-factor", Some(&cookie))
+            .await
+            .unwrap();
+        assert!(body_text(response)
+            .await
+            .contains("enabled for your account"));
+        let response = send_proxied(&app, "GET", "/two-factor", peer, Some("own-bob"), None).await;
+        assert!(body_text(response)
+            .await
+            .contains("disabled for your account"));
+        let two_factor = TwoFactor::instance().await.lock().await;
+        assert!(two_factor.is_enrolled("own-alice") && !two_factor.is_enrolled("own-bob"));
+        drop(two_factor);
+
+        let response = send(&app, "GET", "/two-factor", Some(&cookie))
+            .await
+            .unwrap();
+        let fields = csrf_fields(&body_text(response).await);
+        let current = "correct horse battery staple for route tests";
+        let new = "another horse battery staple for route tests";
+        let body = format!("current_password=wrong&new_password={new}&{fields}");
+        let response = send_form(&app, "/api/auth/password", &cookie, None, body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body = format!("current_password={current}&new_password={new}&{fields}");
+        let response = send_form(&app, "/api/auth/password", &cookie, None, body).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let users = Users::instance().await.lock().await;
+        assert!(users.authenticate("own-alice", new).is_some());
+        assert!(users.authenticate("own-alice", current).is_none());
+        drop(users);
+
+        // The shared AIO passphrase is changed in the configuration instead.
+        let token = LoginTokens::instance().await.lock().await.mint();
+        let uri = format!("/api/auth/getlogin?token={token}");
+        let response = send(&app, "GET", &uri, None).await.unwrap();
+        let cookie = session_cookie(&response).unwrap();
+        let response = send(&app, "GET", "/two-factor", Some(&cookie))
+            .await
+            .unwrap();
+        let html = body_text(response).await;
+        assert!(!html.contains("/api/auth/password"));
+        let body = format!(
+            "current_password=x&new_password={new}&{}",
+            csrf_fields(&html)
+        );
+        let response = send_form(&app, "/api/auth/password", &cookie, None, body).await;
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    })
+    .await;
+}
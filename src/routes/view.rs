@@ -1,24 +1,48 @@
+pub use audit::handler as audit_handler;
 pub use containers::handler as containers_handler;
 pub use index::handler as index_handler;
+pub use jobs::handler as jobs_handler;
 pub use login::handler as login_handler;
+pub use sessions::handler as sessions_handler;
 pub use setup::handler as setup_handler;
+pub use tokens::handler as tokens_handler;
+pub use tokens::page as tokens_page;
+pub use two_factor::handler as two_factor_handler;
+pub use two_factor_login::handler as two_factor_login_handler;
+pub use users::handler as users_handler;
 
 mod login {
+    use crate::auth::client_ip::ClientIp;
+    use crate::auth::csrf::{csrf_token, Csrf};
+    use crate::auth::login_throttle::LoginThrottle;
     use crate::container::controller::DockerController;
     use crate::routes::HttpResponse;
     use askama::Template;
     use axum_typed_routing::route;
+    use tower_sessions::Session;
 
     #[derive(Template)]
     #[template(path = "login.askama.html")]
     pub struct LoginTemplate {
         pub is_login_allowed: bool,
+        pub retry_after: Option<u64>,
+        pub csrf: Csrf,
     }
 
     #[route(GET "/login")]
-    pub async fn handler() -> HttpResponse {
+    pub async fn handler(client: ClientIp, session: Session) -> HttpResponse {
+        let csrf = match csrf_token(&session).await {
+            Ok(csrf) => csrf,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
         HttpResponse::html_template(LoginTemplate {
             is_login_allowed: DockerController::is_login_allowed().await.unwrap(),
+            retry_after: LoginThrottle::instance()
+                .await
+                .lock()
+                .await
+                .retry_after(client),
+            csrf,
         })
     }
 }
@@ -72,11 +96,17 @@ mod setup {
 }
 
 mod containers {
+    use crate::auth::csrf::{csrf_token, Csrf};
+    use crate::auth::password_generator::{generate_password, PasswordOptions};
+    use crate::auth::{current_user, CurrentUser};
     use crate::configuration::StateConfiguration;
+    use crate::container::controller::ContainerState;
     use crate::container::models::Container;
     use crate::routes::HttpResponse;
     use askama::Template;
     use axum_typed_routing::route;
+    use std::collections::HashMap;
+    use tower_sessions::Session;
 
     #[derive(Template)]
     #[template(path = "containers.askama.html")]
@@ -86,6 +116,7 @@ mod containers {
         pub borg_backup_host_location: String,
         pub nextcloud_password: String,
         pub containers: Vec<Container>,
+        pub states: HashMap<String, ContainerState>,
         pub borgbackup_password: String,
         pub is_mastercontainer_update_available: bool,
         pub has_backup_run_once: bool,
@@ -109,7 +140,7 @@ mod containers {
         pub timezone: String,
         pub skip_domain_validation: bool,
         pub talk_port: u16,
-        pub collabora_dictionaries: Vec<String>,
+        pub collabora_dictionaries: String,
         pub automatic_updates: bool,
         pub is_backup_section_enabled: bool,
         pub is_imaginary_enabled: bool,
@@ -118,23 +149,51 @@ mod containers {
         pub nextcloud_datadir: String,
         pub nextcloud_mount: String,
         pub nextcloud_upload_limit: String,
-        pub nextcloud_max_time: u16,
+        pub nextcloud_memory_limit: String,
+        pub nextcloud_max_time: String,
         pub is_dri_device_enabled: bool,
         pub is_talk_recording_enabled: bool,
         pub is_docker_socket_proxy_enabled: bool,
         pub is_whiteboard_enabled: bool,
+        pub env_overrides: HashMap<&'static str, &'static str>,
+        pub password_suggestion: String,
+        pub user: CurrentUser,
+        pub csrf: Csrf,
+    }
+
+    impl ContainersTemplate {
+        fn state(&self, container: &Container) -> String {
+            self.states
+                .get(&container.identifier)
+                .map(ContainerState::to_string)
+                .unwrap_or_default()
+        }
     }
 
     #[route(GET "/containers")]
-    pub async fn handler() -> HttpResponse {
+    pub async fn handler(session: Session) -> HttpResponse {
+        let csrf = match csrf_token(&session).await {
+            Ok(csrf) => csrf,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let user = match current_user(&session).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return HttpResponse::TemporaryRedirect("/"),
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let nextcloud_password =
+            StateConfiguration::get_and_generate_secret("NEXTCLOUD_PASSWORD").await;
+        let borgbackup_password =
+            StateConfiguration::get_and_generate_secret("BORGBACKUP_PASSWORD").await;
         let config = StateConfiguration::instance_ref().await;
         HttpResponse::html_template(ContainersTemplate {
-            domain: config.domain.clone().unwrap(),
+            domain: config.domain.clone().unwrap_or_default(),
             apache_port: config.apache_port,
-            borg_backup_host_location: config.borg_backup_host_location.clone().unwrap(),
-            nextcloud_password: config.nextcloud_password.clone().unwrap(),
-            containers: vec![],                         //TODO
-            borgbackup_password: "".to_string(),        //TODO
+            borg_backup_host_location: config.borg_backup_host_location.clone().unwrap_or_default(),
+            nextcloud_password,
+            containers: vec![],     //TODO
+            states: HashMap::new(), //TODO
+            borgbackup_password,
             is_mastercontainer_update_available: false, //TODO
             has_backup_run_once: false,                 //TODO
             is_backup_container_running: false,         //TODO
@@ -160,23 +219,28 @@ mod containers {
             borg_restore_password: "".to_string(), //TODO
             daily_backup_time: "".to_string(),     //TODO
             is_daily_backup_running: false,        //TODO
-            timezone: "".to_string(),              //TODO
-            skip_domain_validation: false,         //TODO
+            timezone: config.timezone.as_deref().unwrap_or_default().to_string(),
+            skip_domain_validation: false, //TODO
             talk_port: config.talk_port,
-            collabora_dictionaries: vec![],   //TODO
+            collabora_dictionaries: config.collabora_dictionaries.to_string(),
             automatic_updates: false,         //TODO
             is_backup_section_enabled: false, //TODO
             is_imaginary_enabled: config.is_imaginary_enabled,
             is_fulltextsearch_enabled: config.is_fulltextsearch_enabled,
             additional_backup_directories: "".to_string(),
             nextcloud_datadir: config.nextcloud_datadir.clone(),
-            nextcloud_mount: config.nextcloud_mount.clone().unwrap(),
-            nextcloud_upload_limit: config.nextcloud_upload_limit.clone(),
-            nextcloud_max_time: config.nextcloud_max_time,
+            nextcloud_mount: config.nextcloud_mount.clone().unwrap_or_default(),
+            nextcloud_upload_limit: config.nextcloud_upload_limit.to_string(),
+            nextcloud_memory_limit: config.nextcloud_memory_limit.to_string(),
+            nextcloud_max_time: config.nextcloud_max_time.to_string(),
             is_dri_device_enabled: config.nextcloud_enable_dri_device,
             is_talk_recording_enabled: config.is_talk_recording_enabled,
             is_docker_socket_proxy_enabled: config.is_docker_socket_proxy_enabled,
             is_whiteboard_enabled: config.is_whiteboard_enabled,
+            env_overrides: config.env_overrides().clone(),
+            password_suggestion: generate_password(&PasswordOptions::from(&*config)),
+            user,
+            csrf,
         })
 
         //TODO $view = Twig::fromRequest($request);
@@ -233,3 +297,324 @@ mod containers {
         //     ]);
     }
 }
+
+mod audit {
+    use crate::auth::audit::{AuditEntry, AuditEvent, AuditFilter, AuditLog, Outcome};
+    use crate::auth::csrf::{csrf_token, Csrf};
+    use crate::configuration::StateConfiguration;
+    use crate::routes::HttpResponse;
+    use askama::Template;
+    use axum::extract::Query;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[derive(Template)]
+    #[template(path = "audit.askama.html")]
+    pub struct AuditTemplate {
+        pub entries: Vec<AuditEntry>,
+        pub filter: AuditFilter,
+        pub query: String,
+        pub events: [AuditEvent; 6],
+        pub outcomes: [Outcome; 2],
+        pub retention: u32,
+        pub csrf: Csrf,
+    }
+
+    #[route(GET "/audit")]
+    pub async fn handler(session: Session, Query(filter): Query<AuditFilter>) -> HttpResponse {
+        let csrf = match csrf_token(&session).await {
+            Ok(csrf) => csrf,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let query = match serde_urlencoded::to_string(&filter) {
+            Ok(query) => query,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        HttpResponse::html_template(AuditTemplate {
+            entries: AuditLog::instance().await.lock().await.entries(&filter),
+            filter,
+            query,
+            events: AuditEvent::ALL,
+            outcomes: Outcome::ALL,
+            retention: StateConfiguration::instance_ref().await.audit_retention,
+            csrf,
+        })
+    }
+}
+
+mod jobs {
+    use crate::auth::csrf::{csrf_token, Csrf};
+    use crate::container::jobs::{Job, Jobs};
+    use crate::container::lock::{self, OperationLock};
+    use crate::routes::HttpResponse;
+    use askama::Template;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[derive(Template)]
+    #[template(path = "jobs.askama.html")]
+    pub struct JobsTemplate {
+        pub jobs: Vec<Job>,
+        pub operation: Option<OperationLock>,
+        pub csrf: Csrf,
+    }
+
+    #[route(GET "/jobs")]
+    pub async fn handler(session: Session) -> HttpResponse {
+        let csrf = match csrf_token(&session).await {
+            Ok(csrf) => csrf,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let jobs = Jobs::instance().await.lock().await.list();
+        HttpResponse::html_template(JobsTemplate {
+            operation: lock::current(),
+            jobs,
+            csrf,
+        })
+    }
+}
+
+mod sessions {
+    use crate::auth::csrf::{csrf_token, Csrf};
+    use crate::auth::session_id;
+    use crate::auth::sessions::{Lifetimes, SessionInfo, Sessions};
+    use crate::configuration::StateConfiguration;
+    use crate::routes::HttpResponse;
+    use askama::Template;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[derive(Template)]
+    #[template(path = "sessions.askama.html")]
+    pub struct SessionsTemplate {
+        pub sessions: Vec<SessionInfo>,
+        pub current: String,
+        pub idle_timeout: u32,
+        pub lifetime: u32,
+        pub csrf: Csrf,
+    }
+
+    #[route(GET "/sessions")]
+    pub async fn handler(session: Session) -> HttpResponse {
+        let csrf = match csrf_token(&session).await {
+            Ok(csrf) => csrf,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let current = match session_id(&session).await {
+            Ok(id) => id.unwrap_or_default(),
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let (idle_timeout, lifetime) = {
+            let config = StateConfiguration::instance_ref().await;
+            (config.session_idle_timeout, config.session_lifetime)
+        };
+        let lifetimes = Lifetimes::configured().await;
+        let mut sessions = Sessions::instance()
+            .await
+            .lock()
+            .await
+            .list(&lifetimes)
+            .to_vec();
+        sessions.sort_by_key(|session| std::cmp::Reverse(session.last_seen_at));
+        HttpResponse::html_template(SessionsTemplate {
+            sessions,
+            current,
+            idle_timeout,
+            lifetime,
+            csrf,
+        })
+    }
+}
+
+mod tokens {
+    use crate::auth::api_tokens::{ApiToken, ApiTokens, Scope};
+    use crate::auth::csrf::{csrf_token, Csrf};
+    use crate::routes::HttpResponse;
+    use askama::Template;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[derive(Template)]
+    #[template(path = "tokens.askama.html")]
+    pub struct TokensTemplate {
+        pub tokens: Vec<ApiToken>,
+        pub scopes: [Scope; 4],
+        pub new_token: Option<String>,
+        pub error: Option<String>,
+        pub csrf: Csrf,
+    }
+
+    // A new token is only ever part of the response that created it, it is stored nowhere else.
+    pub async fn page(
+        session: &Session,
+        new_token: Option<String>,
+        error: Option<String>,
+    ) -> HttpResponse {
+        let csrf = match csrf_token(session).await {
+            Ok(csrf) => csrf,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        HttpResponse::html_template(TokensTemplate {
+            tokens: ApiTokens::instance().await.lock().await.list().to_vec(),
+            scopes: Scope::ALL,
+            new_token,
+            error,
+            csrf,
+        })
+    }
+
+    #[route(GET "/tokens")]
+    pub async fn handler(session: Session) -> HttpResponse {
+        page(&session, None, None).await
+    }
+}
+
+mod users {
+    use crate::auth::csrf::{csrf_token, Csrf};
+    use crate::auth::password_generator::{generate_password, PasswordOptions};
+    use crate::auth::users::{Role, User, Users};
+    use crate::configuration::StateConfiguration;
+    use crate::routes::HttpResponse;
+    use askama::Template;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[derive(Template)]
+    #[template(path = "users.askama.html")]
+    pub struct UsersTemplate {
+        pub users: Vec<User>,
+        pub roles: [Role; 3],
+        pub password_suggestion: String,
+        pub csrf: Csrf,
+    }
+
+    #[route(GET "/users")]
+    pub async fn handler(session: Session) -> HttpResponse {
+        let csrf = match csrf_token(&session).await {
+            Ok(csrf) => csrf,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        HttpResponse::html_template(UsersTemplate {
+            users: Users::instance().await.lock().await.list().to_vec(),
+            roles: Role::ALL,
+            password_suggestion: generate_password(&PasswordOptions::from(
+                &*StateConfiguration::instance_ref().await,
+            )),
+            csrf,
+        })
+    }
+}
+
+mod two_factor_login {
+    use crate::auth::csrf::{csrf_token, Csrf};
+    use crate::auth::is_second_factor_pending;
+    use crate::routes::HttpResponse;
+    use askama::Template;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[derive(Template)]
+    #[template(path = "login-two-factor.askama.html")]
+    pub struct TwoFactorLoginTemplate {
+        pub csrf: Csrf,
+    }
+
+    #[route(GET "/login/two-factor")]
+    pub async fn handler(session: Session) -> HttpResponse {
+        match is_second_factor_pending(&session).await {
+            Ok(true) => {}
+            Ok(false) => return HttpResponse::TemporaryRedirect("/login"),
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        }
+        match csrf_token(&session).await {
+            Ok(csrf) => HttpResponse::html_template(TwoFactorLoginTemplate { csrf }),
+            Err(e) => HttpResponse::Error(e.to_string()),
+        }
+    }
+}
+
+mod two_factor {
+    use crate::auth::csrf::{csrf_token, Csrf};
+    use crate::auth::current_user;
+    use crate::auth::two_factor::{
+        generate_secret, otpauth_url, qr_code_svg, TwoFactor, PENDING_SECRET_SESSION_KEY,
+        RECOVERY_CODES_SESSION_KEY,
+    };
+    use crate::auth::users::Users;
+    use crate::routes::HttpResponse;
+    use askama::Template;
+    use axum_typed_routing::route;
+    use tower_sessions::Session;
+
+    #[derive(Template)]
+    #[template(path = "two-factor.askama.html")]
+    pub struct TwoFactorTemplate {
+        pub is_enrolled: bool,
+        pub remaining_recovery_codes: usize,
+        pub secret: String,
+        pub otpauth_url: String,
+        pub qr_code: String,
+        pub recovery_codes: Option<Vec<String>>,
+        // Only accounts from the user list have a password of their own.
+        pub has_password: bool,
+        pub csrf: Csrf,
+    }
+
+    async fn pending_secret(session: &Session) -> Result<String, tower_sessions::session::Error> {
+        if let Some(secret) = session.get(PENDING_SECRET_SESSION_KEY).await? {
+            return Ok(secret);
+        }
+        let secret = generate_secret();
+        session
+            .insert(PENDING_SECRET_SESSION_KEY, &secret)
+            .await
+            .and(Ok(secret))
+    }
+
+    #[route(GET "/two-factor")]
+    pub async fn handler(session: Session) -> HttpResponse {
+        let csrf = match csrf_token(&session).await {
+            Ok(csrf) => csrf,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let recovery_codes = match session.remove(RECOVERY_CODES_SESSION_KEY).await {
+            Ok(codes) => codes,
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let account = match current_user(&session).await {
+            Ok(Some(user)) => user.name,
+            Ok(None) => return HttpResponse::TemporaryRedirect("/"),
+            Err(e) => return HttpResponse::Error(e.to_string()),
+        };
+        let has_password = Users::instance()
+            .await
+            .lock()
+            .await
+            .list()
+            .iter()
+            .any(|user| user.name == account);
+        let two_factor = TwoFactor::instance().await.lock().await;
+        let (secret, qr_code) = if two_factor.is_enrolled(&account) {
+            (String::new(), String::new())
+        } else {
+            let secret = match pending_secret(&session).await {
+                Ok(secret) => secret,
+                Err(e) => return HttpResponse::Error(e.to_string()),
+            };
+            match qr_code_svg(&secret) {
+                Ok(qr_code) => (secret, qr_code),
+                Err(e) => return HttpResponse::Error(e),
+            }
+        };
+        HttpResponse::html_template(TwoFactorTemplate {
+            is_enrolled: two_factor.is_enrolled(&account),
+            remaining_recovery_codes: two_factor.remaining_recovery_codes(&account),
+            otpauth_url: otpauth_url(&secret),
+            secret,
+            qr_code,
+            recovery_codes,
+            has_password,
+            csrf,
+        })
+    }
+}
@@ -0,0 +1,129 @@
+mod self_signed;
+mod tls;
+
+use crate::configuration::StateConfiguration;
+use crate::routes;
+use axum::extract::ConnectInfo;
+use axum::Router;
+use hyper::body::Incoming;
+use hyper::service::service_fn;
+use hyper::Request;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::TcpListener;
+use tokio::task::JoinSet;
+use tokio::time::{sleep, timeout};
+use tokio_rustls::TlsAcceptor;
+use tower::ServiceExt;
+use tracing::{debug, info, warn};
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug)]
+pub enum ServerError {
+    NoAddress,
+    Bind(SocketAddr, io::Error),
+    Serve(io::Error),
+    Tls(String),
+}
+
+impl Display for ServerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ServerError::NoAddress => write!(
+                f,
+                "There is no address to listen on. Please set AIO_LISTEN or AIO_TLS_LISTEN."
+            ),
+            ServerError::Bind(address, e) => write!(f, "Could not listen on {}: {}", address, e),
+            ServerError::Serve(e) => write!(f, "The server stopped: {}", e),
+            ServerError::Tls(e) => write!(f, "Could not set up TLS: {}", e),
+        }
+    }
+}
+
+async fn bind(address: SocketAddr) -> Result<TcpListener, ServerError> {
+    TcpListener::bind(address)
+        .await
+        .map_err(|e| ServerError::Bind(address, e))
+}
+
+// Handshakes run in the connection tasks, so a slow client cannot hold up the others.
+async fn serve_tls(listener: TcpListener, app: Router, acceptor: TlsAcceptor) -> io::Result<()> {
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                warn!("Could not accept a connection: {}", e);
+                sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream = match timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => return debug!(%peer, "TLS handshake failed: {}", e),
+                Err(_) => return debug!(%peer, "TLS handshake timed out"),
+            };
+            // The same connection info that axum::serve adds on the plain listeners.
+            let service = service_fn(move |mut request: Request<Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(peer));
+                app.clone().oneshot(request)
+            });
+            if let Err(e) = Builder::new(TokioExecutor::new())
+                .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                .await
+            {
+                debug!(%peer, "Connection ended with an error: {}", e);
+            }
+        });
+    }
+}
+
+pub async fn serve() -> Result<(), ServerError> {
+    let (listen, tls_listen) = {
+        let config = StateConfiguration::instance_ref().await;
+        (config.listen.clone(), config.tls_listen.clone())
+    };
+    if listen.is_empty() && tls_listen.is_empty() {
+        return Err(ServerError::NoAddress);
+    }
+
+    let mut servers = JoinSet::new();
+    for address in listen {
+        let listener = bind(address).await?;
+        let app = routes::router(false).await;
+        info!("now listening on http://{}", address);
+        servers.spawn(async move {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<SocketAddr>(),
+            )
+            .await
+        });
+    }
+    if !tls_listen.is_empty() {
+        let acceptor = tls::acceptor().await?;
+        for address in tls_listen {
+            let listener = bind(address).await?;
+            let app = routes::router(true).await;
+            info!("now listening on https://{}", address);
+            servers.spawn(serve_tls(listener, app, acceptor.clone()));
+        }
+    }
+    routes::clean_up_periodically();
+
+    while let Some(result) = servers.join_next().await {
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => return Err(ServerError::Serve(e)),
+            Err(e) => return Err(ServerError::Serve(io::Error::other(e))),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::error::Unspecified;
+use ring::rand::SystemRandom;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_ASN1_SIGNING};
+use std::net::IpAddr;
+use time::{Duration, OffsetDateTime};
+
+const VALIDITY: Duration = Duration::days(10 * 365);
+const COMMON_NAME: &str = "Nextcloud AIO";
+
+// Object identifiers, already DER encoded.
+const OID_ECDSA_WITH_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x04, 0x03, 0x02];
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+const OID_PRIME256V1: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x03, 0x01, 0x07];
+const OID_COMMON_NAME: &[u8] = &[0x55, 0x04, 0x03];
+const OID_SUBJECT_ALT_NAME: &[u8] = &[0x55, 0x1d, 0x11];
+
+fn tlv(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if content.len() < 0x80 {
+        out.push(content.len() as u8);
+    } else {
+        let length: Vec<u8> = content
+            .len()
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|byte| *byte == 0)
+            .collect();
+        out.push(0x80 | length.len() as u8);
+        out.extend(length);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+fn sequence(parts: &[Vec<u8>]) -> Vec<u8> {
+    tlv(0x30, &parts.concat())
+}
+
+fn oid(value: &[u8]) -> Vec<u8> {
+    tlv(0x06, value)
+}
+
+fn bit_string(content: &[u8]) -> Vec<u8> {
+    tlv(0x03, &[&[0], content].concat())
+}
+
+// UTCTime only covers the years until 2049, later dates need a GeneralizedTime.
+fn time(time: OffsetDateTime) -> Vec<u8> {
+    let seconds = format!(
+        "{:02}{:02}{:02}{:02}{:02}Z",
+        u8::from(time.month()),
+        time.day(),
+        time.hour(),
+        time.minute(),
+        time.second()
+    );
+    if time.year() < 2050 {
+        tlv(
+            0x17,
+            format!("{:02}{}", time.year() % 100, seconds).as_bytes(),
+        )
+    } else {
+        tlv(0x18, format!("{:04}{}", time.year(), seconds).as_bytes())
+    }
+}
+
+fn name(common_name: &str) -> Vec<u8> {
+    let attribute = sequence(&[oid(OID_COMMON_NAME), tlv(0x0c, common_name.as_bytes())]);
+    sequence(&[tlv(0x31, &attribute)])
+}
+
+fn alt_name(name: &str) -> Vec<u8> {
+    match name.parse::<IpAddr>() {
+        Ok(IpAddr::V4(ip)) => tlv(0x87, &ip.octets()),
+        Ok(IpAddr::V6(ip)) => tlv(0x87, &ip.octets()),
+        Err(_) => tlv(0x82, name.as_bytes()),
+    }
+}
+
+fn pem(label: &str, der: &[u8]) -> String {
+    let encoded = STANDARD.encode(der);
+    let mut pem = format!("-----BEGIN {}-----\n", label);
+    for line in encoded.as_bytes().chunks(64) {
+        pem.push_str(&String::from_utf8_lossy(line));
+        pem.push('\n');
+    }
+    pem.push_str(&format!("-----END {}-----\n", label));
+    pem
+}
+
+// A self-signed X.509 v3 certificate with an ECDSA P-256 key, valid for the given host names and
+// IP addresses. Returns the certificate and the PKCS#8 key, both PEM encoded.
+pub fn generate(names: &[String]) -> Result<(String, String), Unspecified> {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, &rng)?;
+    let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_ASN1_SIGNING, pkcs8.as_ref(), &rng)
+        .map_err(|_| Unspecified)?;
+
+    // Positive and without a leading zero byte, as DER requires.
+    let mut serial = rand::random::<[u8; 16]>();
+    serial[0] = (serial[0] & 0x7f) | 0x40;
+    let now = OffsetDateTime::now_utc();
+    let algorithm = sequence(&[oid(OID_ECDSA_WITH_SHA256)]);
+    let alt_names: Vec<Vec<u8>> = names.iter().map(|name| alt_name(name)).collect();
+    let extension = sequence(&[oid(OID_SUBJECT_ALT_NAME), tlv(0x04, &sequence(&alt_names))]);
+    let public_key = sequence(&[
+        sequence(&[oid(OID_EC_PUBLIC_KEY), oid(OID_PRIME256V1)]),
+        bit_string(key_pair.public_key().as_ref()),
+    ]);
+    let certificate = sequence(&[
+        tlv(0xa0, &tlv(0x02, &[2])),
+        tlv(0x02, &serial),
+        algorithm.clone(),
+        name(COMMON_NAME),
+        sequence(&[time(now - Duration::days(1)), time(now + VALIDITY)]),
+        name(COMMON_NAME),
+        public_key,
+        tlv(0xa3, &sequence(&[extension])),
+    ]);
+    let signature = key_pair.sign(&rng, &certificate)?;
+    let certificate = sequence(&[certificate, algorithm, bit_string(signature.as_ref())]);
+    Ok((
+        pem("CERTIFICATE", &certificate),
+        pem("PRIVATE KEY", pkcs8.as_ref()),
+    ))
+}
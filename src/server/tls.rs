@@ -0,0 +1,99 @@
+use crate::configuration::StateConfiguration;
+use crate::data::data_const::{get_tls_certificate_file, get_tls_key_file};
+use crate::server::self_signed;
+use crate::server::ServerError;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::sync::Arc;
+use tokio_rustls::rustls::crypto::ring::default_provider;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::ServerConfig;
+use tokio_rustls::TlsAcceptor;
+use tracing::info;
+
+type CertifiedKey = (Vec<CertificateDer<'static>>, PrivateKeyDer<'static>);
+
+fn read_pem(certificate: &Path, key: &Path) -> Result<CertifiedKey, ServerError> {
+    let open = |path: &Path| {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| ServerError::Tls(format!("Could not read {}: {}", path.display(), e)))
+    };
+    let certificates = rustls_pemfile::certs(&mut open(certificate)?)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ServerError::Tls(format!("{}: {}", certificate.display(), e)))?;
+    if certificates.is_empty() {
+        return Err(ServerError::Tls(format!(
+            "{} does not contain a PEM certificate.",
+            certificate.display()
+        )));
+    }
+    let key = rustls_pemfile::private_key(&mut open(key)?)
+        .map_err(|e| ServerError::Tls(format!("{}: {}", key.display(), e)))?
+        .ok_or_else(|| {
+            ServerError::Tls(format!(
+                "{} does not contain a PEM private key.",
+                key.display()
+            ))
+        })?;
+    Ok((certificates, key))
+}
+
+// Generated once and kept in the data directory, so that browsers only need to trust it once.
+fn self_signed(domain: Option<&str>) -> Result<CertifiedKey, ServerError> {
+    let certificate_file = get_tls_certificate_file();
+    let key_file = get_tls_key_file();
+    if !certificate_file.is_file() || !key_file.is_file() {
+        let mut names = vec![
+            String::from("localhost"),
+            String::from("127.0.0.1"),
+            String::from("::1"),
+        ];
+        names.extend(domain.filter(|domain| domain.is_ascii()).map(String::from));
+        let (certificate, key) = self_signed::generate(&names)
+            .map_err(|_| ServerError::Tls(String::from("Could not generate a certificate.")))?;
+        let write = || -> std::io::Result<()> {
+            OpenOptions::new()
+                .create(true)
+                .write(true)
+                .truncate(true)
+                .mode(0o600)
+                .open(&key_file)?
+                .write_all(key.as_bytes())?;
+            fs::write(&certificate_file, certificate)
+        };
+        write().map_err(|e| ServerError::Tls(format!("Could not store the certificate: {}", e)))?;
+        info!(
+            "Generated a self-signed certificate in {}",
+            certificate_file.display()
+        );
+    }
+    read_pem(&certificate_file, &key_file)
+}
+
+pub async fn acceptor() -> Result<TlsAcceptor, ServerError> {
+    let (certificates, key) = {
+        let config = StateConfiguration::instance_ref().await;
+        match (&config.tls_certificate, &config.tls_key) {
+            (Some(certificate), Some(key)) => read_pem(Path::new(certificate), Path::new(key))?,
+            (None, None) => self_signed(config.domain.as_deref())?,
+            _ => {
+                return Err(ServerError::Tls(String::from(
+                    "The TLS certificate and key need to be configured together.",
+                )))
+            }
+        }
+    };
+    let mut config = ServerConfig::builder_with_provider(Arc::new(default_provider()))
+        .with_safe_default_protocol_versions()
+        .and_then(|builder| {
+            builder
+                .with_no_client_auth()
+                .with_single_cert(certificates, key)
+        })
+        .map_err(|e| ServerError::Tls(e.to_string()))?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}